@@ -1,82 +1,151 @@
 use std::error::Error;
-use std::collections::HashMap;
 
 use serde_json::json;
 
-use crate::utils::send_openai_request;
-use crate::config::OPENAI_MODEL_NAME;
+use crate::analysis::{analysis_schema, RepoAnalysis};
+use crate::ecosystem::{self, Ecosystem};
+use crate::engine::ContainerEngine;
+use crate::utils::{send_openai_request, stream_json_request};
+use crate::config::LLM_MODEL_NAME;
+
+// Token limit for agents answering with a whole file (Dockerfiles, run scripts), which is refused
+// when cut off, so it leaves room for long multi-stage builds
+const FILE_MAX_TOKENS: u32 = 2000;
 
 // Agent 1: Documentation Analysis Agent
-pub fn documentation_analysis_agent(content: &str) -> Result<String, Box<dyn Error>> {
+pub fn documentation_analysis_agent(content: &str) -> Result<RepoAnalysis, Box<dyn Error>> {
     let prompt = format!(
-        "Please analyze the following repository documentation content and provide a response in Markdown format
-        without formatting markers (such as ```markdown or any other symbols). \
-        The Markdown should contain the following sections:\n\n\
-        ## Functionalities\nDescribe the main functionalities or purpose of the repository.\n\n\
-        ## Prerequisites\nList any prerequisites needed to use this repository.\n\n\
-        ## Requirements\nList the software dependencies required to run the repository.\n\n\
-        ## Installation\nProvide the installation steps in sequence.\n\n\
-        If you cannot find information for any of the sections, respond with an empty entry for that section.\n\n\
+        "Please analyze the following repository documentation content and describe how to run the repository:\n\
+        - functionalities: the main functionalities or purpose of the repository.\n\
+        - prerequisites: anything needed before installing it (accounts, hardware, services).\n\
+        - runtime: the main programming language and its required version, if stated.\n\
+        - system_packages: OS-level packages needed (e.g. build-essential, libpq-dev).\n\
+        - install_steps: the installation steps in sequence, as shell commands where possible.\n\
+        - exposed_ports: TCP ports the application listens on.\n\
+        - env_vars: environment variables it reads, with their default values if stated.\n\
+        - entrypoint: the command that starts the application.\n\n\
+        Facts detected from manifest files (runtime versions, package managers, scripts) are reliable; \
+        prefer them over guesses. If you cannot find information for a field, leave it empty or null.\n\n\
         ---\n\n{}",
         content
     );
     let messages = [
-        json!({"role": "system", "content": "You are an assistant that helps summarize repository documentation in Markdown format."}),
+        json!({"role": "system", "content": "You are an assistant that extracts structured setup information from repository documentation."}),
         json!({"role": "user", "content": prompt}),
     ];
-    send_openai_request(OPENAI_MODEL_NAME, &messages, 0.5, 500)
+    // Long answer, so it is shown while it is generated
+    println!("Analyzing documentation...");
+    let response = stream_json_request("documentation_analysis", &LLM_MODEL_NAME, &messages, 0.5, 1000, &analysis_schema())?;
+    RepoAnalysis::from_response(&response)
+        .map_err(|e| format!("Analysis is not valid JSON for the expected schema: {}", e).into())
 }
 
 // Agent 2: Docker File Generation Agent (only if Docker files are not found)
-pub fn docker_file_generation_agent(analysis: &str) -> Result<String, Box<dyn Error>> {
+pub fn docker_file_generation_agent(analysis: &RepoAnalysis, ecosystems: &[Ecosystem]) -> Result<String, Box<dyn Error>> {
     let prompt = format!(
-        "Based on the following analysis of repository requirements, prerequisites, and installation steps, \
-        generate only the Dockerfile content. Provide the content as raw text, without any explanations, \
-        introductory text, or formatting markers (such as ```Dockerfile or any other symbols).\n\n---\n\n{}",
-        analysis
+        "Based on the following analysis of repository requirements, prerequisites, and installation steps (JSON), \
+        generate only the Dockerfile content. Use the runtime version, package manager, lockfiles and entrypoints \
+        detected from the manifest files where the analysis does not say otherwise. Provide the content as raw text, \
+        without any explanations, introductory text, or formatting markers (such as ```Dockerfile or any other symbols).\n\n\
+        ---\n\n{}\n\nDetected ecosystems (from manifest files):\n{}",
+        serde_json::to_string_pretty(analysis)?,
+        ecosystem::summarize(ecosystems)
     );
     let messages = [
         json!({"role": "system", "content": "You are an assistant that generates Docker configuration files based on repository requirements."}),
         json!({"role": "user", "content": prompt}),
     ];
-    send_openai_request(OPENAI_MODEL_NAME, &messages, 0.5, 300)
+    send_openai_request("docker_file_generation", &LLM_MODEL_NAME, &messages, 0.5, FILE_MAX_TOKENS)
 }
 
-// TODO: Add check if the container is already installed, or container name has been used.
-// Agent 3: Run Script Generation Agent
+// Agent 2b: Dockerfile Repair Agent, fixes the problems found by the Dockerfile linter
+pub fn dockerfile_repair_agent(dockerfile: &str, lint_report: &str, analysis: &RepoAnalysis) -> Result<String, Box<dyn Error>> {
+    let prompt = format!(
+        "The following Dockerfile was generated for a repository but has problems. Fix every problem listed \
+        and return only the corrected Dockerfile content as raw text, without any explanations, introductory text, \
+        or formatting markers (such as ```Dockerfile or any other symbols).\n\n\
+        Problems:\n{}\n\nDockerfile:\n{}\n\nRepository analysis (JSON):\n{}",
+        lint_report,
+        dockerfile,
+        serde_json::to_string_pretty(analysis)?
+    );
+    let messages = [
+        json!({"role": "system", "content": "You are an assistant that generates Docker configuration files based on repository requirements."}),
+        json!({"role": "user", "content": prompt}),
+    ];
+    send_openai_request("dockerfile_repair", &LLM_MODEL_NAME, &messages, 0.2, FILE_MAX_TOKENS)
+}
+
+// Agent 2c: Dockerfile Build Fix Agent, corrects a Dockerfile from the output of a failed build
+pub fn dockerfile_build_fix_agent(dockerfile: &str, build_log: &str, analysis: &RepoAnalysis) -> Result<String, Box<dyn Error>> {
+    let prompt = format!(
+        "Building the following Dockerfile failed. Using the output of the failing build step, return only the \
+        corrected Dockerfile content as raw text, without any explanations, introductory text, \
+        or formatting markers (such as ```Dockerfile or any other symbols).\n\n\
+        Build output:\n{}\n\nDockerfile:\n{}\n\nRepository analysis (JSON):\n{}",
+        build_log,
+        dockerfile,
+        serde_json::to_string_pretty(analysis)?
+    );
+    let messages = [
+        json!({"role": "system", "content": "You are an assistant that generates Docker configuration files based on repository requirements."}),
+        json!({"role": "user", "content": prompt}),
+    ];
+    send_openai_request("dockerfile_build_fix", &LLM_MODEL_NAME, &messages, 0.2, FILE_MAX_TOKENS)
+}
+
+// Summary agent: condenses one chunk of documentation that does not fit the analysis prompt
+pub fn documentation_summary_agent(chunk: &str) -> Result<String, Box<dyn Error>> {
+    let prompt = format!(
+        "Summarize the following excerpt of repository documentation in at most a few short paragraphs. \
+        Keep installation steps, commands, required software and versions, ports and environment variables verbatim; \
+        drop everything else.\n\n---\n\n{}",
+        chunk
+    );
+    let messages = [
+        json!({"role": "system", "content": "You are an assistant that condenses repository documentation without losing setup details."}),
+        json!({"role": "user", "content": prompt}),
+    ];
+    send_openai_request("documentation_summary", &LLM_MODEL_NAME, &messages, 0.2, 400)
+}
+
+// Agent 3: Run Script Generation Agent, only used when no run plan can be built from the docker files
 pub fn run_script_generation_agent(
-    docker_content: &HashMap<String, String>, 
-    dockerfile_path: &str,     
-    docker_compose_path: Option<&str> 
+    dockerfile: Option<(&str, &str)>, // Path and content of the Dockerfile
+    build_context: &str, // Directory passed to the engine's `build`, the repository root
+    compose: Option<(&str, &str)>, // Path and content of the compose file
+    engine: &ContainerEngine, // CLI the script must use instead of assuming docker
 ) -> Result<String, Box<dyn Error>> {
-    // Check if Docker Compose file or Dockerfile is present
-    let has_compose_file = docker_compose_path.is_some();
-    let has_dockerfile = docker_content.contains_key("Dockerfile");
-
+    let compose_command = engine.compose.join(" ");
     // Create the prompt based on available files
-    let prompt = if has_compose_file {
+    let prompt = if let Some((compose_path, compose_content)) = compose {
         // Use Docker Compose if available
         format!(
             "Generate a shell script to set up and run the application using Docker Compose with the specific path provided. \
-            Use:\n  `docker compose -f {}`\n\n\
+            Use:\n  `{} -f {}`\n\n\
             Do not include any 'cd' commands to change directories. Provide the script content as raw text without any introductory text, \
             formatting markers, or explanations.\n\n\
             Docker Compose path: {}\n\nCompose File:\n{}",
-            docker_compose_path.unwrap(),
-            docker_compose_path.unwrap(),
-            docker_content.get("docker-compose.yml").unwrap_or(&String::new())
+            compose_command,
+            compose_path,
+            compose_path,
+            compose_content
         )
-    } else if has_dockerfile {
+    } else if let Some((dockerfile_path, dockerfile_content)) = dockerfile {
         // Fallback to Dockerfile if Docker Compose is not available
         format!(
-            "Generate a shell script to build and run the Docker container using the Dockerfile path provided. \
-            Use:\n  `docker build -f {}` followed by the appropriate `docker run` command.\n\n\
+            "Generate a shell script to build and run the container using the Dockerfile path provided. \
+            Use:\n  `{} build -f {} {}` followed by the appropriate `{} run` command.\n\n\
             Do not include any 'cd' commands to change directories. Provide the script content as raw text without any introductory text, \
             formatting markers, or explanations.\n\n\
-            Dockerfile path: {}\n\nDockerfile:\n{}",
+            Dockerfile path: {}\nBuild context: {}\n\nDockerfile:\n{}",
+            engine.binary,
             dockerfile_path,
+            build_context,
+            engine.binary,
             dockerfile_path,
-            docker_content.get("Dockerfile").unwrap()
+            build_context,
+            dockerfile_content
         )
     } else {
         return Err("No Docker-related files found to generate a run script.".into());
@@ -85,6 +154,6 @@ pub fn run_script_generation_agent(
         json!({"role": "system", "content": "You are an assistant that generates scripts to run Docker configurations."}),
         json!({"role": "user", "content": prompt}),
     ];
-    send_openai_request(OPENAI_MODEL_NAME, &messages, 0.5, 300)
+    send_openai_request("run_script_generation", &LLM_MODEL_NAME, &messages, 0.5, FILE_MAX_TOKENS)
 }
 
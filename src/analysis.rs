@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// Structured result of the documentation analysis agent, saved as analysis.json
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepoAnalysis {
+    pub functionalities: Vec<String>,
+    pub prerequisites: Vec<String>,
+    pub runtime: Runtime,
+    pub system_packages: Vec<String>,
+    pub install_steps: Vec<String>,
+    pub exposed_ports: Vec<u16>,
+    pub env_vars: Vec<EnvVar>,
+    pub entrypoint: Option<String>, // Command that starts the application
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Runtime {
+    pub language: String,
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EnvVar {
+    pub name: String,
+    pub description: Option<String>,
+    pub default: Option<String>,
+}
+
+// JSON schema the model's output is constrained to (OpenAI "json_schema" response format, strict mode:
+// every property required, optional values expressed as nullable types)
+pub fn analysis_schema() -> Value {
+    let strings = json!({"type": "array", "items": {"type": "string"}});
+    let nullable_string = json!({"type": ["string", "null"]});
+    json!({
+        "name": "repository_analysis",
+        "strict": true,
+        "schema": {
+            "type": "object",
+            "additionalProperties": false,
+            "required": [
+                "functionalities", "prerequisites", "runtime", "system_packages",
+                "install_steps", "exposed_ports", "env_vars", "entrypoint"
+            ],
+            "properties": {
+                "functionalities": strings,
+                "prerequisites": strings,
+                "runtime": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "required": ["language", "version"],
+                    "properties": {
+                        "language": {"type": "string"},
+                        "version": nullable_string
+                    }
+                },
+                "system_packages": strings,
+                "install_steps": strings,
+                "exposed_ports": {"type": "array", "items": {"type": "integer"}},
+                "env_vars": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["name", "description", "default"],
+                        "properties": {
+                            "name": {"type": "string"},
+                            "description": nullable_string,
+                            "default": nullable_string
+                        }
+                    }
+                },
+                "entrypoint": nullable_string
+            }
+        }
+    })
+}
+
+impl RepoAnalysis {
+    // Parse the model's answer, tolerating a ```json fence around it
+    pub fn from_response(response: &str) -> Result<Self, serde_json::Error> {
+        let trimmed = response.trim();
+        let unfenced = trimmed
+            .strip_prefix("```json")
+            .or_else(|| trimmed.strip_prefix("```"))
+            .and_then(|rest| rest.strip_suffix("```"))
+            .unwrap_or(trimmed);
+        serde_json::from_str(unfenced.trim())
+    }
+
+    // Human-readable version of the analysis, saved as analysis.md
+    pub fn render_markdown(&self) -> String {
+        fn bullet_list(items: &[String]) -> String {
+            items.iter().map(|item| format!("- {}\n", item)).collect()
+        }
+
+        let mut markdown = String::new();
+        markdown.push_str("## Functionalities\n");
+        markdown.push_str(&bullet_list(&self.functionalities));
+
+        markdown.push_str("\n## Prerequisites\n");
+        markdown.push_str(&bullet_list(&self.prerequisites));
+
+        markdown.push_str("\n## Requirements\n");
+        if !self.runtime.language.is_empty() {
+            match &self.runtime.version {
+                Some(version) => markdown.push_str(&format!("- Runtime: {} {}\n", self.runtime.language, version)),
+                None => markdown.push_str(&format!("- Runtime: {}\n", self.runtime.language)),
+            }
+        }
+        if !self.system_packages.is_empty() {
+            markdown.push_str(&format!("- System packages: {}\n", self.system_packages.join(", ")));
+        }
+
+        markdown.push_str("\n## Installation\n");
+        for (i, step) in self.install_steps.iter().enumerate() {
+            markdown.push_str(&format!("{}. {}\n", i + 1, step));
+        }
+
+        if !self.exposed_ports.is_empty() {
+            let ports: Vec<String> = self.exposed_ports.iter().map(|port| port.to_string()).collect();
+            markdown.push_str(&format!("\n## Ports\n{}\n", ports.join(", ")));
+        }
+
+        if !self.env_vars.is_empty() {
+            markdown.push_str("\n## Environment variables\n");
+            for var in &self.env_vars {
+                markdown.push_str(&format!("- `{}`", var.name));
+                if let Some(default) = &var.default {
+                    markdown.push_str(&format!(" (default: `{}`)", default));
+                }
+                if let Some(description) = &var.description {
+                    markdown.push_str(&format!(": {}", description));
+                }
+                markdown.push('\n');
+            }
+        }
+
+        if let Some(entrypoint) = &self.entrypoint {
+            markdown.push_str(&format!("\n## Entrypoint\n`{}`\n", entrypoint));
+        }
+
+        markdown
+    }
+}
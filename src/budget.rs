@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+use crate::agents::documentation_summary_agent;
+use crate::config::{LLM_DOC_TOKEN_BUDGET, LLM_SUMMARY_CHUNKS};
+use crate::llm::Usage;
+
+// Size of one chunk sent to the summary agent
+const CHUNK_TOKENS: usize = 2000;
+// Below this many free tokens, leftover documents are dropped instead of summarized
+const MIN_SUMMARY_TOKENS: usize = 200;
+
+// Rough token count (~4 characters per token for English text and code)
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+// Cut text down to about `tokens` tokens, on a character boundary
+fn truncate_to_tokens(text: &str, tokens: usize) -> String {
+    text.chars().take(tokens * 4).collect()
+}
+
+// Lower is more useful: README first, then INSTALL, CONTRIBUTING, docs/, other files, nested files
+pub fn document_rank(relative: &Path) -> usize {
+    let name = relative.file_name().and_then(|f| f.to_str()).unwrap_or_default().to_uppercase();
+    let in_docs = relative.components().next()
+        .and_then(|c| c.as_os_str().to_str())
+        .is_some_and(|first| first.eq_ignore_ascii_case("docs") || first.eq_ignore_ascii_case("doc"));
+    let is_root = relative.components().count() == 1;
+
+    if is_root && name.starts_with("README") {
+        0
+    } else if name.starts_with("INSTALL") || name.starts_with("SETUP") || name.starts_with("GETTING") {
+        1
+    } else if name.starts_with("CONTRIBUTING") {
+        2
+    } else if in_docs {
+        3
+    } else if is_root {
+        4
+    } else {
+        5
+    }
+}
+
+// Split text into chunks of at most CHUNK_TOKENS, on line boundaries where possible
+pub fn split_into_chunks(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        if !current.is_empty() && estimate_tokens(&current) + estimate_tokens(line) > CHUNK_TOKENS {
+            chunks.push(std::mem::take(&mut current));
+        }
+        // A single huge line is cut to fit
+        current.push_str(&truncate_to_tokens(line, CHUNK_TOKENS));
+        current.push('\n');
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+// Fit the markdown documents into `budget` tokens. Documents are taken whole in rank order;
+// what does not fit is summarized chunk by chunk (map), the summaries merged (reduce), and
+// the result truncated if it is still too large.
+pub fn fit_documents(
+    root: &Path, // Repository root, used to rank documents by their relative path
+    mut documents: Vec<(PathBuf, String)>,
+    budget: usize,
+) -> Result<String, Box<dyn Error>> {
+    documents.sort_by_key(|(path, _)| {
+        let relative = path.strip_prefix(root).unwrap_or(path).to_path_buf();
+        (document_rank(&relative), relative.components().count(), relative)
+    });
+
+    let total: usize = documents.iter().map(|(_, content)| estimate_tokens(content)).sum();
+    let mut merged = String::new();
+    let mut used = 0;
+    let mut overflow = String::new();
+    let mut overflow_count = 0;
+
+    for (path, content) in &documents {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let section = format!("--- {} ---\n{}\n\n", relative.display(), content);
+        let tokens = estimate_tokens(&section);
+        if overflow.is_empty() && used + tokens <= budget {
+            merged.push_str(&section);
+            used += tokens;
+        } else {
+            overflow.push_str(&section);
+            overflow_count += 1;
+        }
+    }
+
+    if overflow_count == 0 {
+        return Ok(merged);
+    }
+
+    let remaining = budget.saturating_sub(used);
+    println!(
+        "Documentation is ~{} tokens, over the budget of {}; condensing {} file(s).",
+        total, budget, overflow_count
+    );
+    if remaining < MIN_SUMMARY_TOKENS {
+        return Ok(merged);
+    }
+
+    let condensed = if *LLM_SUMMARY_CHUNKS == 0 {
+        overflow
+    } else {
+        // Map: summarize each chunk on its own
+        let chunks = split_into_chunks(&overflow);
+        if chunks.len() > *LLM_SUMMARY_CHUNKS {
+            println!("Summarizing the first {} of {} chunks.", *LLM_SUMMARY_CHUNKS, chunks.len());
+        }
+        let summaries = chunks.iter()
+            .take(*LLM_SUMMARY_CHUNKS)
+            .map(|chunk| documentation_summary_agent(chunk))
+            .collect::<Result<Vec<_>, _>>()?;
+        let combined = summaries.join("\n\n");
+
+        // Reduce: one more pass if the summaries together are still too large
+        if estimate_tokens(&combined) > remaining && summaries.len() > 1 {
+            documentation_summary_agent(&truncate_to_tokens(&combined, CHUNK_TOKENS))?
+        } else {
+            combined
+        }
+    };
+
+    merged.push_str("--- Summary of remaining documentation ---\n");
+    merged.push_str(&truncate_to_tokens(&condensed, remaining));
+    Ok(merged)
+}
+
+// Token budget for documentation once the Docker files (always sent whole) are accounted for
+pub fn documentation_budget(docker_content: &str) -> usize {
+    LLM_DOC_TOKEN_BUDGET.saturating_sub(estimate_tokens(docker_content))
+}
+
+//---------------- Token usage accounting -----------------
+
+struct UsageEntry {
+    agent: String,
+    usage: Usage,
+    cached: bool,
+}
+
+static USAGE_LOG: Lazy<Mutex<Vec<UsageEntry>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+// Remember what one call cost; cached calls cost nothing but are still listed
+pub fn record_usage(agent: &str, usage: Usage, cached: bool) {
+    if let Ok(mut log) = USAGE_LOG.lock() {
+        log.push(UsageEntry { agent: agent.to_string(), usage, cached });
+    }
+}
+
+// Print the tokens spent per agent since the last report, then reset the log
+pub fn print_usage_report() {
+    let entries = match USAGE_LOG.lock() {
+        Ok(mut log) => std::mem::take(&mut *log),
+        Err(_) => return,
+    };
+    if entries.is_empty() {
+        return;
+    }
+
+    // Aggregate per agent, keeping the order in which the agents ran
+    let mut order = Vec::new();
+    let mut totals: HashMap<String, (Usage, usize, usize)> = HashMap::new();
+    for entry in entries {
+        let total = totals.entry(entry.agent.clone()).or_insert_with(|| {
+            order.push(entry.agent.clone());
+            (Usage::default(), 0, 0)
+        });
+        total.0.prompt_tokens += entry.usage.prompt_tokens;
+        total.0.completion_tokens += entry.usage.completion_tokens;
+        if entry.cached {
+            total.2 += 1;
+        } else {
+            total.1 += 1;
+        }
+    }
+
+    println!("Tokens spent per agent:");
+    let mut grand_total = 0;
+    for agent in order {
+        let (usage, calls, cached) = totals[&agent];
+        grand_total += usage.prompt_tokens + usage.completion_tokens;
+        println!(
+            "- {}: {} prompt + {} completion tokens ({} call(s), {} cached)",
+            agent, usage.prompt_tokens, usage.completion_tokens, calls, cached
+        );
+    }
+    println!("Total: {} tokens", grand_total);
+}
@@ -0,0 +1,97 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::agents::dockerfile_build_fix_agent;
+use crate::analysis::RepoAnalysis;
+use crate::discovery::DockerSelection;
+use crate::engine::{failing_step_log, ContainerEngine, REPO_LABEL};
+
+// The repository's key for 'scripts/<key>', the folder name for scripts kept elsewhere
+fn repo_key(scripts_path: &Path) -> String {
+    match scripts_path.strip_prefix("scripts") {
+        Ok(key) if key.components().count() > 1 => key.to_string_lossy().replace('\\', "/"),
+        _ => scripts_path.file_name().and_then(|f| f.to_str()).unwrap_or("repo").to_string(),
+    }
+}
+
+// Labels put on what installing the repository creates, so it can be told apart from what other
+// repositories and tools created
+pub fn repo_labels(scripts_path: &Path) -> Vec<String> {
+    vec![format!("{}={}", REPO_LABEL, repo_key(scripts_path))]
+}
+
+// Image tag used for a repository's builds: "scripts/github.com/owner/app" -> "autocontain-github.com-owner-app"
+pub fn image_tag(scripts_path: &Path) -> String {
+    let name = repo_key(scripts_path).replace('/', "-");
+    let sanitized: String = name.to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    format!("autocontain-{}", sanitized)
+}
+
+// Compose project name, which compose limits to lowercase letters, digits, '-' and '_':
+// "scripts/github.com/owner/app" -> "autocontain-github-com-owner-app"
+pub fn project_name(scripts_path: &Path) -> String {
+    image_tag(scripts_path).replace('.', "-")
+}
+
+// The Dockerfile run.sh builds: the generated one in the scripts folder if there is one,
+// otherwise the one chosen from the repository
+pub fn active_dockerfile(local_path: &Path, scripts_path: &Path) -> PathBuf {
+    let generated = scripts_path.join("Dockerfile");
+    if generated.exists() {
+        return generated;
+    }
+    let chosen = DockerSelection::load(scripts_path).and_then(|selection| selection.dockerfile);
+    local_path.join(chosen.unwrap_or_else(|| PathBuf::from("Dockerfile")))
+}
+
+// Build the image, and on failure send the failing step's log to the Dockerfile agent and
+// try again with its corrected Dockerfile, up to `max_attempts` builds in total. Every attempt's
+// Dockerfile and log are kept in 'scripts/<repo>/build_attempts'. The Dockerfile that built
+// successfully replaces the active one. Returns whether a build succeeded.
+pub fn build_with_fixes(
+    local_path: &Path,
+    scripts_path: &Path,
+    engine: &ContainerEngine,
+    max_attempts: usize,
+) -> Result<bool, Box<dyn Error>> {
+    let dockerfile_path = active_dockerfile(local_path, scripts_path);
+    let attempts_path = scripts_path.join("build_attempts");
+    fs::create_dir_all(&attempts_path)?;
+
+    // The analysis gives the agent context beyond the Dockerfile itself
+    let analysis = fs::read_to_string(scripts_path.join("analysis.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<RepoAnalysis>(&content).ok())
+        .unwrap_or_default();
+    let tag = image_tag(scripts_path);
+    let original = fs::read_to_string(&dockerfile_path)?;
+    let mut dockerfile = original.clone();
+
+    for attempt in 1..=max_attempts {
+        let attempt_dockerfile = attempts_path.join(format!("attempt-{}.Dockerfile", attempt));
+        fs::write(&attempt_dockerfile, &dockerfile)?;
+
+        let output = engine.build(&attempt_dockerfile, local_path, &tag, &repo_labels(scripts_path))?;
+        fs::write(attempts_path.join(format!("attempt-{}.log", attempt)), &output.log)?;
+
+        if output.success {
+            println!("Build succeeded on attempt {}/{}.", attempt, max_attempts);
+            if dockerfile != original {
+                fs::write(&dockerfile_path, &dockerfile)?;
+                println!("Corrected Dockerfile written to {}.", dockerfile_path.display());
+            }
+            return Ok(true);
+        }
+
+        println!("Build attempt {}/{} failed.", attempt, max_attempts);
+        if attempt < max_attempts {
+            dockerfile = dockerfile_build_fix_agent(&dockerfile, &failing_step_log(&output.log), &analysis)?;
+        }
+    }
+
+    Ok(false)
+}
@@ -0,0 +1,69 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::config::{LLM_CACHE_DIR, LLM_CACHE_TTL};
+use crate::llm::{prompt_hash, ChatRequest};
+
+// Lookups can be turned off for a run (--no-cache); fresh responses are still stored
+static LOOKUPS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_lookups_enabled(enabled: bool) {
+    LOOKUPS_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// Every parameter that changes the completion is part of the key
+fn cache_path(provider: &str, request: &ChatRequest) -> PathBuf {
+    let key = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        provider,
+        request.model,
+        request.temperature,
+        request.max_tokens,
+        prompt_hash(request.messages),
+        request.json_schema.map(|schema| schema.to_string()).unwrap_or_default()
+    );
+    let digest = Sha256::digest(key.as_bytes());
+    let name: String = digest.iter().take(16).map(|byte| format!("{:02x}", byte)).collect();
+    LLM_CACHE_DIR.join(format!("{}.json", name))
+}
+
+// Return the cached response for this request if there is one younger than the TTL
+pub fn lookup(provider: &str, request: &ChatRequest) -> Option<String> {
+    if !LOOKUPS_ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    let content = fs::read_to_string(cache_path(provider, request)).ok()?;
+    let entry: serde_json::Value = serde_json::from_str(&content).ok()?;
+    let created_at = entry["created_at"].as_u64()?;
+    if now_secs().saturating_sub(created_at) > *LLM_CACHE_TTL {
+        return None;
+    }
+    entry["response"].as_str().map(|response| response.to_string())
+}
+
+// Save a response; failures only cost a future cache miss, so they are reported and ignored
+pub fn store(provider: &str, request: &ChatRequest, response: &str) {
+    let path = cache_path(provider, request);
+    let entry = json!({
+        "created_at": now_secs(),
+        "agent": request.agent,
+        "model": request.model,
+        "response": response,
+    });
+
+    let result = fs::create_dir_all(&*LLM_CACHE_DIR)
+        .and_then(|_| fs::write(&path, entry.to_string()));
+    if let Err(e) = result {
+        eprintln!("Warning: failed to write response cache {}: {}", path.display(), e);
+    }
+}
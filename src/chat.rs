@@ -5,8 +5,8 @@ use rusqlite::Connection;
 use serde_json::json;
 
 use crate::db::{get_dependencies, get_function_description};
-use crate::config::OPENAI_MODEL_NAME;
-use crate::utils::send_openai_request;
+use crate::config::LLM_MODEL_NAME;
+use crate::utils::{send_openai_request, stream_openai_request};
 
 // Main function to handle continuous chat with the assistant
 pub fn chat_with_assistant(conn: &Connection) {
@@ -31,17 +31,16 @@ pub fn chat_with_assistant(conn: &Connection) {
             break;
         }
 
-        // Handle the user's query and print the assistant's response
-        match handle_user_query(user_input, conn) {
-            Ok(response) => println!("Assistant: {}", response),
-            Err(err) => println!("Error: {}", err),
+        // Handle the user's query; the assistant's response is streamed as it is generated
+        if let Err(err) = handle_user_query(user_input, conn) {
+            println!("Error: {}", err);
         }
 
         println!(); // Print a newline for better readability
     }
 }
 
-// Function to handle each user query, determining intent and generating a response with OpenAI
+// Function to handle each user query, determining intent and streaming a response from the LLM
 pub fn handle_user_query(query: &str, conn: &Connection) -> Result<String, Box<dyn Error>> {
     // Detect User Intent (only "Casual Chat" and "Overall Code Logic")
     let intent = classify_intent(query)?;
@@ -71,7 +70,9 @@ pub fn handle_user_query(query: &str, conn: &Connection) -> Result<String, Box<d
         json!({"role": "system", "content": "You are an assistant who explains code repository structures, logic flow, and functionality."}),
         json!({"role": "user", "content": content}),
     ];
-    send_openai_request(OPENAI_MODEL_NAME ,&messages, 0.5, 1000)
+    print!("Assistant: ");
+    io::stdout().flush()?;
+    stream_openai_request("chat", &LLM_MODEL_NAME, &messages, 0.5, 1000)
 }
 
 // Intent classification function
@@ -86,7 +87,7 @@ fn classify_intent(query: &str) -> Result<String, Box<dyn Error>> {
         json!({"role": "system", "content": "You are an assistant that excels in recognizing user's prompt intent."}),
         json!({"role": "user", "content": prompt})
     ];
-    send_openai_request(OPENAI_MODEL_NAME, &messages, 0.5, 1000)
+    send_openai_request("intent_classification", &LLM_MODEL_NAME, &messages, 0.5, 1000)
 }
 
 fn format_program_flow(conn: &Connection) -> Result<String, Box<dyn Error>> {
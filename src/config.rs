@@ -1,11 +1,93 @@
 use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use dotenv::dotenv;
 use once_cell::sync::Lazy;
 
-pub static OPENAI_API_KEY: Lazy<String> = Lazy::new(|| {
+// Read an environment variable (loading .env first), treating empty values as unset
+pub fn env_var(name: &str) -> Option<String> {
     dotenv().ok();
-    env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY not found in .env")
+    env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+// Which LLM backend to talk to: "openai", "openai-compatible" or "anthropic"
+pub static LLM_PROVIDER: Lazy<String> = Lazy::new(|| {
+    env_var("LLM_PROVIDER").unwrap_or_else(|| "openai".to_string()).to_lowercase()
+});
+
+// Base URL of the LLM API, e.g. http://localhost:8080/v1 for a llama.cpp or vLLM server
+pub static LLM_BASE_URL: Lazy<Option<String>> = Lazy::new(|| env_var("LLM_BASE_URL"));
+
+// API key for the selected provider, falling back to the provider-specific variable
+pub static LLM_API_KEY: Lazy<Option<String>> = Lazy::new(|| {
+    env_var("LLM_API_KEY").or_else(|| match LLM_PROVIDER.as_str() {
+        "anthropic" => env_var("ANTHROPIC_API_KEY"),
+        _ => env_var("OPENAI_API_KEY"),
+    })
+});
+
+// Total time allowed for one LLM request, in seconds
+pub static LLM_TIMEOUT: Lazy<Duration> = Lazy::new(|| {
+    Duration::from_secs(env_var("LLM_TIMEOUT_SECS").and_then(|secs| secs.parse().ok()).unwrap_or(120))
+});
+
+// Time allowed to establish the connection, in seconds
+pub static LLM_CONNECT_TIMEOUT: Lazy<Duration> = Lazy::new(|| {
+    Duration::from_secs(env_var("LLM_CONNECT_TIMEOUT_SECS").and_then(|secs| secs.parse().ok()).unwrap_or(10))
+});
+
+// How many times a rate-limited, failed-over or timed-out request is retried
+pub static LLM_MAX_RETRIES: Lazy<u32> = Lazy::new(|| {
+    env_var("LLM_MAX_RETRIES").and_then(|retries| retries.parse().ok()).unwrap_or(3)
+});
+
+// Tokens of documentation (plus Docker files) sent to the analysis agent
+pub static LLM_DOC_TOKEN_BUDGET: Lazy<usize> = Lazy::new(|| {
+    env_var("LLM_DOC_TOKEN_BUDGET").and_then(|tokens| tokens.parse().ok()).unwrap_or(6000)
+});
+
+// Maximum chunks of over-budget documentation to summarize; 0 only truncates
+pub static LLM_SUMMARY_CHUNKS: Lazy<usize> = Lazy::new(|| {
+    env_var("LLM_SUMMARY_CHUNKS").and_then(|chunks| chunks.parse().ok()).unwrap_or(4)
+});
+
+// "live" talks to the provider, "record" also saves every response as a fixture,
+// "replay" answers from the fixtures only (no network, no API key)
+pub static LLM_MODE: Lazy<String> = Lazy::new(|| {
+    env_var("LLM_MODE").unwrap_or_else(|| "live".to_string()).to_lowercase()
+});
+
+// Directory holding recorded responses for the record and replay modes
+pub static LLM_FIXTURES_DIR: Lazy<PathBuf> = Lazy::new(|| {
+    PathBuf::from(env_var("LLM_FIXTURES_DIR").unwrap_or_else(|| "fixtures/llm".to_string()))
+});
+
+// Directory of the on-disk response cache
+pub static LLM_CACHE_DIR: Lazy<PathBuf> = Lazy::new(|| {
+    PathBuf::from(env_var("LLM_CACHE_DIR").unwrap_or_else(|| "cache/llm".to_string()))
+});
+
+// How long a cached response stays valid, in seconds (default: 7 days)
+pub static LLM_CACHE_TTL: Lazy<u64> = Lazy::new(|| {
+    env_var("LLM_CACHE_TTL").and_then(|ttl| ttl.parse().ok()).unwrap_or(7 * 24 * 60 * 60)
+});
+
+// Model used by the agents and the chat assistant
+pub static LLM_MODEL_NAME: Lazy<String> = Lazy::new(|| {
+    env_var("LLM_MODEL").unwrap_or_else(|| match LLM_PROVIDER.as_str() {
+        "anthropic" => ANTHROPIC_MODEL_NAME.to_string(),
+        _ => OPENAI_MODEL_NAME.to_string(),
+    })
+});
+
+// Container CLI to use instead of the one detected on PATH (docker, podman or nerdctl)
+pub static CONTAINER_ENGINE: Lazy<Option<String>> = Lazy::new(|| env_var("AUTOCONTAIN_ENGINE"));
+
+// Policy file deciding which safety findings block an install
+pub static SAFETY_POLICY: Lazy<PathBuf> = Lazy::new(|| {
+    PathBuf::from(env_var("AUTOCONTAIN_SAFETY_POLICY").unwrap_or_else(|| "safety_policy.toml".to_string()))
 });
 
-pub const OPENAI_MODEL_NAME: &str = "gpt-4o-mini";
\ No newline at end of file
+pub const OPENAI_MODEL_NAME: &str = "gpt-4o-mini";
+pub const ANTHROPIC_MODEL_NAME: &str = "claude-3-5-haiku-latest";
@@ -7,7 +7,9 @@ pub fn initialize_db(conn: &Connection) -> Result<()> {
         "CREATE TABLE IF NOT EXISTS repositories (
             id INTEGER PRIMARY KEY,
             name TEXT NOT NULL,
-            description TEXT
+            description TEXT,
+            git_ref TEXT,
+            commit_sha TEXT
         )",
         [],
     )?;
@@ -47,11 +49,42 @@ pub fn initialize_db(conn: &Connection) -> Result<()> {
             function_name TEXT NOT NULL,
             dependency TEXT NOT NULL,
             class_id INTEGER,
-            FOREIGN KEY(class_id) REFERENCES classes(id)
+            function_id INTEGER,
+            FOREIGN KEY(class_id) REFERENCES classes(id),
+            FOREIGN KEY(function_id) REFERENCES functions(id)
         )",
         [],
     )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS imports (
+            repo_id INTEGER,
+            module TEXT NOT NULL,
+            file_location TEXT,
+            FOREIGN KEY(repo_id) REFERENCES repositories(id)
+        )",
+        [],
+    )?;
+
+    migrate(conn)
+}
+
+// Columns added after the first release, as (table, column, type)
+const ADDED_COLUMNS: [(&str, &str, &str); 3] = [
+    ("repositories", "git_ref", "TEXT"),
+    ("repositories", "commit_sha", "TEXT"),
+    ("function_dependencies", "function_id", "INTEGER"),
+];
 
+// Bring databases created by older versions up to date
+fn migrate(conn: &Connection) -> Result<()> {
+    for (table, column, column_type) in ADDED_COLUMNS {
+        let exists = conn
+            .prepare(&format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1", table))?
+            .exists([column])?;
+        if !exists {
+            conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, column_type), [])?;
+        }
+    }
     Ok(())
 }
 
@@ -61,15 +94,52 @@ pub fn initialize_db(conn: &Connection) -> Result<()> {
 // Add repository to database
 pub fn insert_repository(conn: &Connection, repo: &Repository) -> Result<i32> {
     conn.execute(
-        "INSERT INTO repositories (name, description) VALUES (?1, ?2)",
-        &[&repo.name, &repo.description.as_deref().unwrap_or("").to_string()],
+        "INSERT INTO repositories (name, description, git_ref, commit_sha) VALUES (?1, ?2, ?3, ?4)",
+        params![repo.name, repo.description.as_deref().unwrap_or(""), repo.git_ref, repo.commit],
     )?;
     let repo_id = conn.last_insert_rowid() as i32;
     Ok(repo_id)
 }
 
-// Add function to database
-pub fn insert_function(conn: &Connection, func: &Function) -> Result<()> {
+// Rename a repository's rows, returns how many were renamed
+pub fn rename_repository(conn: &Connection, old_name: &str, new_name: &str) -> Result<usize> {
+    conn.execute("UPDATE repositories SET name = ?2 WHERE name = ?1", params![old_name, new_name])
+}
+
+// Id of the latest parse of a repository
+pub fn latest_repository_id(conn: &Connection, name: &str) -> Result<Option<i32>> {
+    let mut stmt = conn.prepare("SELECT id FROM repositories WHERE name = ?1 ORDER BY id DESC LIMIT 1")?;
+    let mut rows = stmt.query_map(params![name], |row| row.get(0))?;
+    rows.next().transpose()
+}
+
+// Record the commit a repository is at, without parsing it again
+pub fn set_repository_commit(conn: &Connection, name: &str, commit: &str) -> Result<usize> {
+    conn.execute("UPDATE repositories SET commit_sha = ?2 WHERE name = ?1", params![name, commit])
+}
+
+// Delete every parse of a repository, with its classes, functions, their dependencies and imports
+pub fn delete_repository(conn: &Connection, name: &str) -> Result<()> {
+    let repo_ids = "SELECT id FROM repositories WHERE name = ?1";
+    // Dependencies recorded before they had a function id are only known by their class
+    conn.execute(
+        &format!(
+            "DELETE FROM function_dependencies
+             WHERE function_id IN (SELECT id FROM functions WHERE repo_id IN ({0}))
+                OR class_id IN (SELECT id FROM classes WHERE repo_id IN ({0}))",
+            repo_ids
+        ),
+        params![name],
+    )?;
+    for table in ["functions", "classes", "imports"] {
+        conn.execute(&format!("DELETE FROM {} WHERE repo_id IN ({})", table, repo_ids), params![name])?;
+    }
+    conn.execute("DELETE FROM repositories WHERE name = ?1", params![name])?;
+    Ok(())
+}
+
+// Add function to database and return its id
+pub fn insert_function(conn: &Connection, func: &Function) -> Result<i64> {
     conn.execute(
         "INSERT INTO functions (repo_id, class_id, name, parameters, return_type, file_location, start_line, end_line, docstring)
          VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
@@ -85,7 +155,7 @@ pub fn insert_function(conn: &Connection, func: &Function) -> Result<()> {
             func.docstring
         ],
     )?;
-    Ok(())
+    Ok(conn.last_insert_rowid())
 }
 
 // Add class to database
@@ -107,16 +177,36 @@ pub fn insert_class(conn: &Connection, class: &Class) -> Result<()> {
 }
 
 // Add function dependencies to database
-pub fn insert_dependencies(conn: &Connection, function_name: &str, class_id: Option<i32>, dependencies: &[String]) -> Result<()> {
-    let mut stmt = conn.prepare("INSERT INTO function_dependencies (function_name, dependency, class_id) VALUES (?1, ?2, ?3)")?;
+pub fn insert_dependencies(conn: &Connection, function_id: i64, function_name: &str, class_id: Option<i32>, dependencies: &[String]) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "INSERT INTO function_dependencies (function_name, dependency, class_id, function_id) VALUES (?1, ?2, ?3, ?4)",
+    )?;
 
     for dependency in dependencies {
-        stmt.execute(params![function_name, dependency, class_id])?;
+        stmt.execute(params![function_name, dependency, class_id, function_id])?;
     }
 
     Ok(())
 }
 
+// Add the top-level modules a file imports to database
+pub fn insert_imports(conn: &Connection, repo_id: i32, file_location: &str, modules: &[String]) -> Result<()> {
+    let mut stmt = conn.prepare("INSERT INTO imports (repo_id, module, file_location) VALUES (?1, ?2, ?3)")?;
+
+    for module in modules {
+        stmt.execute(params![repo_id, module, file_location])?;
+    }
+
+    Ok(())
+}
+
+// Fetch the distinct modules imported anywhere in a repository
+pub fn get_imported_modules(conn: &Connection, repo_id: i32) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT DISTINCT module FROM imports WHERE repo_id = ?1 ORDER BY module")?;
+    let modules = stmt.query_map(params![repo_id], |row| row.get(0))?.collect::<Result<Vec<_>, _>>()?;
+    Ok(modules)
+}
+
 // Fetch dependencies for a specific function and class ID, if applicable
 pub fn get_dependencies(conn: &Connection, function_name: &str, class_id: Option<i32>) -> Result<Vec<(String, Option<i32>)>> {
     let mut stmt = if class_id.is_some() {
@@ -150,5 +240,5 @@ pub fn get_function_description(conn: &Connection, function_name: &str, class_id
         stmt.query_row(params![function_name, class_id], |row| row.get(0))
     } else {
         stmt.query_row(params![function_name], |row| row.get(0))
-    }.map_err(|e| e.into())
+    }
 }
@@ -0,0 +1,199 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+// Subdirectories where repositories commonly keep their container files
+pub const DOCKER_DIRS: [&str; 3] = ["docker", "deploy", ".docker"];
+pub const DEVCONTAINER_DIR: &str = ".devcontainer";
+
+// Variant names that describe the image meant to be deployed, or only for development
+const PRODUCTION_VARIANTS: [&str; 4] = ["prod", "production", "release", "deploy"];
+const DEVELOPMENT_VARIANTS: [&str; 7] = ["dev", "development", "test", "ci", "local", "debug", "override"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DockerFileKind {
+    Dockerfile,
+    Compose,
+}
+
+// A Dockerfile or compose file found in the repository
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DockerFile {
+    pub path: PathBuf, // Relative to the repository root
+    pub kind: DockerFileKind,
+    pub rank: u32, // Higher is a better default
+}
+
+// The files run.sh is generated from, saved as docker_files.json in the scripts folder
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DockerSelection {
+    pub candidates: Vec<DockerFile>,
+    pub dockerfile: Option<PathBuf>, // Relative to the repository root
+    pub compose: Option<PathBuf>,
+}
+
+impl DockerSelection {
+    pub fn is_empty(&self) -> bool {
+        self.dockerfile.is_none() && self.compose.is_none()
+    }
+
+    pub fn load(scripts_path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(scripts_path.join("docker_files.json")).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self, scripts_path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(scripts_path.join("docker_files.json"), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// "Dockerfile" -> "", "Dockerfile.prod" -> "prod", "api.Dockerfile" -> "api"
+fn dockerfile_variant(name: &str) -> Option<String> {
+    let lower = name.to_lowercase();
+    if lower.ends_with(".dockerignore") {
+        return None;
+    }
+    if lower == "dockerfile" || lower == "containerfile" {
+        return Some(String::new());
+    }
+    lower.strip_prefix("dockerfile.")
+        .or_else(|| lower.strip_prefix("containerfile."))
+        .or_else(|| lower.strip_suffix(".dockerfile"))
+        .map(|variant| variant.to_string())
+}
+
+// "compose.yaml" -> "", "docker-compose.override.yml" -> "override", "docker-compose-prod.yml" -> "prod"
+fn compose_variant(name: &str) -> Option<String> {
+    let lower = name.to_lowercase();
+    let stem = lower.strip_suffix(".yml").or_else(|| lower.strip_suffix(".yaml"))?;
+    let rest = stem.strip_prefix("docker-compose").or_else(|| stem.strip_prefix("compose"))?;
+    if rest.is_empty() {
+        return Some(String::new());
+    }
+    rest.strip_prefix(['.', '-', '_']).map(|variant| variant.to_string())
+}
+
+// Whether a file with this name is a Dockerfile or compose file
+pub fn is_docker_file(name: &str) -> bool {
+    dockerfile_variant(name).is_some() || compose_variant(name).is_some()
+}
+
+fn variant_rank(variant: &str) -> u32 {
+    if variant.is_empty() {
+        10
+    } else if PRODUCTION_VARIANTS.contains(&variant) {
+        8
+    } else if DEVELOPMENT_VARIANTS.iter().any(|dev| variant.split(['.', '-', '_']).any(|part| part == *dev)) {
+        0
+    } else {
+        5
+    }
+}
+
+// Add the container files directly inside `dir`
+fn scan_dir(root: &Path, dir: &Path, location_rank: u32, found: &mut Vec<DockerFile>) {
+    let Ok(entries) = fs::read_dir(root.join(dir)) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        if !entry.path().is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        let (kind, variant) = if let Some(variant) = dockerfile_variant(&name) {
+            (DockerFileKind::Dockerfile, variant)
+        } else if let Some(variant) = compose_variant(&name) {
+            (DockerFileKind::Compose, variant)
+        } else {
+            continue;
+        };
+        found.push(DockerFile { path: dir.join(&name), kind, rank: location_rank + variant_rank(&variant) });
+    }
+}
+
+// Find every Dockerfile and compose file at the root, in docker/, deploy/ (one level of
+// subdirectories included) and in .devcontainer/, best candidates first
+pub fn discover_docker_files(root: &Path) -> Vec<DockerFile> {
+    let mut found = Vec::new();
+    scan_dir(root, Path::new(""), 30, &mut found);
+    for dir in DOCKER_DIRS {
+        scan_dir(root, Path::new(dir), 20, &mut found);
+        if let Ok(entries) = fs::read_dir(root.join(dir)) {
+            for entry in entries.filter_map(Result::ok).filter(|entry| entry.path().is_dir()) {
+                scan_dir(root, &Path::new(dir).join(entry.file_name()), 15, &mut found);
+            }
+        }
+    }
+    // Development containers are a last resort: they set up an editor, not the application
+    scan_dir(root, Path::new(DEVCONTAINER_DIR), 0, &mut found);
+
+    found.sort_by(|a, b| b.rank.cmp(&a.rank).then_with(|| a.path.cmp(&b.path)));
+    found
+}
+
+// Pick the file of one kind: the one requested on the command line, the user's choice when
+// there are several and `interactive` is set, the best ranked one otherwise
+fn select(
+    root: &Path,
+    candidates: &[DockerFile],
+    kind: DockerFileKind,
+    requested: Option<&str>,
+    interactive: bool,
+) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    if let Some(requested) = requested {
+        if !root.join(requested).is_file() {
+            return Err(format!("'{}' does not exist in the repository.", requested).into());
+        }
+        return Ok(Some(PathBuf::from(requested)));
+    }
+
+    let matching: Vec<&DockerFile> = candidates.iter().filter(|file| file.kind == kind).collect();
+    if matching.len() <= 1 || !interactive {
+        return Ok(matching.first().map(|file| file.path.clone()));
+    }
+
+    let label = match kind {
+        DockerFileKind::Dockerfile => "Dockerfiles",
+        DockerFileKind::Compose => "compose files",
+    };
+    println!("Found several {}:", label);
+    for (i, file) in matching.iter().enumerate() {
+        println!("  {}. {}{}", i + 1, file.path.display(), if i == 0 { " (recommended)" } else { "" });
+    }
+    print!("Choose one [1]: ");
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let choice = match input.trim() {
+        "" => 0,
+        value => match value.parse::<usize>() {
+            Ok(n) if (1..=matching.len()).contains(&n) => n - 1,
+            _ => {
+                println!("Invalid choice, using {}.", matching[0].path.display());
+                0
+            }
+        },
+    };
+    Ok(Some(matching[choice].path.clone()))
+}
+
+// Discover the repository's container files and choose the Dockerfile and compose file to use
+pub fn select_docker_files(
+    root: &Path,
+    dockerfile: Option<&str>,
+    compose: Option<&str>,
+    interactive: bool,
+) -> Result<DockerSelection, Box<dyn Error>> {
+    let candidates = discover_docker_files(root);
+    Ok(DockerSelection {
+        dockerfile: select(root, &candidates, DockerFileKind::Dockerfile, dockerfile, interactive)?,
+        compose: select(root, &candidates, DockerFileKind::Compose, compose, interactive)?,
+        candidates,
+    })
+}
@@ -0,0 +1,246 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+const INSTRUCTIONS: [&str; 18] = [
+    "FROM", "RUN", "CMD", "LABEL", "MAINTAINER", "EXPOSE", "ENV", "ADD", "COPY", "ENTRYPOINT",
+    "VOLUME", "USER", "WORKDIR", "ARG", "ONBUILD", "STOPSIGNAL", "HEALTHCHECK", "SHELL",
+];
+
+// One logical instruction, with continuation lines joined
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub line: usize, // 1-based line where the instruction starts
+    pub keyword: String, // Upper-cased instruction name as written
+    pub args: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub line: usize,
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "line {}: {} [{}] {}", self.line, severity, self.rule, self.message)
+    }
+}
+
+// Split a Dockerfile into instructions. Comments and blank lines are dropped, lines ending in '\'
+// are joined with the next one and heredoc bodies (RUN <<EOF ... EOF) are kept with their instruction.
+pub fn parse(content: &str) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut lines = content.lines().enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let mut text = trimmed.to_string();
+        while text.ends_with('\\') {
+            text.pop();
+            match lines.next() {
+                Some((_, next)) if next.trim_start().starts_with('#') => text.push('\\'),
+                Some((_, next)) => text.push_str(&format!(" {}", next.trim())),
+                None => break,
+            }
+        }
+
+        // Heredoc: everything up to the terminator belongs to this instruction
+        if let Some(marker) = heredoc_marker(&text) {
+            for (_, next) in lines.by_ref() {
+                text.push('\n');
+                text.push_str(next);
+                if next.trim() == marker {
+                    break;
+                }
+            }
+        }
+
+        let (keyword, args) = match text.split_once(char::is_whitespace) {
+            Some((keyword, args)) => (keyword.to_string(), args.trim().to_string()),
+            None => (text.clone(), String::new()),
+        };
+        instructions.push(Instruction { line: index + 1, keyword: keyword.to_uppercase(), args });
+    }
+
+    instructions
+}
+
+fn heredoc_marker(text: &str) -> Option<String> {
+    let start = text.find("<<")?;
+    let marker = text[start + 2..]
+        .trim_start_matches('-')
+        .split_whitespace()
+        .next()?
+        .trim_matches(|c| c == '"' || c == '\'');
+    if marker.is_empty() || !marker.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    Some(marker.to_string())
+}
+
+// Check a Dockerfile for problems an LLM typically introduces. `context` is the build context
+// (the repository root), used to verify that COPY/ADD sources exist.
+pub fn lint(content: &str, context: &Path) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    // Markdown fences or prose around the file
+    for (index, line) in content.lines().enumerate() {
+        if line.trim_start().starts_with("```") {
+            violations.push(Violation {
+                line: index + 1,
+                rule: "no-fences",
+                severity: Severity::Error,
+                message: "Markdown code fence in Dockerfile".to_string(),
+            });
+        }
+    }
+
+    let instructions = parse(content);
+    let mut stages = HashSet::new();
+    let mut seen_from = false;
+
+    for instruction in &instructions {
+        let keyword = instruction.keyword.as_str();
+        if keyword.starts_with("```") {
+            continue; // Already reported as a fence
+        }
+        if !INSTRUCTIONS.contains(&keyword) {
+            violations.push(Violation {
+                line: instruction.line,
+                rule: "unknown-instruction",
+                severity: Severity::Error,
+                message: format!("'{}' is not a Dockerfile instruction", keyword),
+            });
+            continue;
+        }
+
+        if !seen_from && keyword != "FROM" && keyword != "ARG" {
+            violations.push(Violation {
+                line: instruction.line,
+                rule: "from-first",
+                severity: Severity::Error,
+                message: format!("{} before the first FROM", keyword),
+            });
+        }
+
+        match keyword {
+            "FROM" => {
+                seen_from = true;
+                lint_from(instruction, &mut stages, &mut violations);
+            }
+            "COPY" | "ADD" => lint_copy_sources(instruction, context, &mut violations),
+            _ => {}
+        }
+    }
+
+    if !seen_from {
+        violations.push(Violation {
+            line: 1,
+            rule: "missing-from",
+            severity: Severity::Error,
+            message: "No FROM instruction".to_string(),
+        });
+    }
+
+    violations
+}
+
+// Base images must be pinned to a tag or digest other than 'latest'
+fn lint_from(instruction: &Instruction, stages: &mut HashSet<String>, violations: &mut Vec<Violation>) {
+    let words: Vec<&str> = instruction.args.split_whitespace()
+        .filter(|word| !word.starts_with("--"))
+        .collect();
+    let Some(image) = words.first() else {
+        violations.push(Violation {
+            line: instruction.line,
+            rule: "missing-from",
+            severity: Severity::Error,
+            message: "FROM without an image".to_string(),
+        });
+        return;
+    };
+    if words.len() >= 3 && words[1].eq_ignore_ascii_case("as") {
+        stages.insert(words[2].to_lowercase());
+    }
+
+    // Earlier stages, scratch and images chosen through build args are fine
+    if stages.contains(&image.to_lowercase()) || *image == "scratch" || image.contains('$') || image.contains('@') {
+        return;
+    }
+    let name = image.rsplit('/').next().unwrap_or(image);
+    match name.split_once(':') {
+        Some((_, tag)) if tag != "latest" => {}
+        _ => violations.push(Violation {
+            line: instruction.line,
+            rule: "pinned-base",
+            severity: Severity::Warning,
+            message: format!("Base image '{}' is not pinned to a version tag", image),
+        }),
+    }
+}
+
+// COPY/ADD sources must exist in the build context
+fn lint_copy_sources(instruction: &Instruction, context: &Path, violations: &mut Vec<Violation>) {
+    let args = instruction.args.as_str();
+    if args.contains("--from=") || args.contains("<<") {
+        return;
+    }
+
+    // Exec form: COPY ["src", "dest"]
+    let words: Vec<String> = if args.starts_with('[') {
+        serde_json::from_str(args).unwrap_or_default()
+    } else {
+        args.split_whitespace()
+            .filter(|word| !word.starts_with("--"))
+            .map(|word| word.to_string())
+            .collect()
+    };
+    if words.len() < 2 {
+        return;
+    }
+
+    for source in &words[..words.len() - 1] {
+        let is_pattern = source.contains(['*', '?', '[', '$']);
+        let is_url = source.starts_with("http://") || source.starts_with("https://") || source.starts_with("git@");
+        if is_pattern || is_url {
+            continue;
+        }
+        if !context.join(source.trim_start_matches('/')).exists() {
+            violations.push(Violation {
+                line: instruction.line,
+                rule: "copy-source-exists",
+                severity: Severity::Error,
+                message: format!("{} source '{}' does not exist in the repository", instruction.keyword, source),
+            });
+        }
+    }
+}
+
+// Human-readable report, also used as feedback for the repair agent
+pub fn format_report(violations: &[Violation]) -> String {
+    if violations.is_empty() {
+        return "No problems found.\n".to_string();
+    }
+    violations.iter().map(|violation| format!("{}\n", violation)).collect()
+}
+
+pub fn has_errors(violations: &[Violation]) -> bool {
+    violations.iter().any(|violation| violation.severity == Severity::Error)
+}
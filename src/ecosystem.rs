@@ -0,0 +1,383 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// What a manifest file says about how the project is built and started
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Ecosystem {
+    pub language: String,
+    pub runtime_version: Option<String>,
+    pub package_manager: String,
+    pub manifest: String, // Manifest file the information comes from, relative to the repo root
+    pub scripts: Vec<String>, // Named commands, e.g. "start: node server.js"
+    pub entrypoints: Vec<String>, // Files or binaries that start the application
+    pub lockfiles: Vec<String>,
+    pub dependencies: Vec<String>, // Names of the direct dependencies
+}
+
+// Read every known manifest at the repository root, in order of how common they are
+pub fn detect_ecosystems(root: &Path) -> Vec<Ecosystem> {
+    [
+        detect_node(root),
+        detect_python(root),
+        detect_rust(root),
+        detect_go(root),
+        detect_java(root),
+        detect_ruby(root),
+        detect_php(root),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn read(root: &Path, file: &str) -> Option<String> {
+    fs::read_to_string(root.join(file)).ok()
+}
+
+// Names of the given files that exist at the root
+fn existing(root: &Path, files: &[&str]) -> Vec<String> {
+    files.iter()
+        .filter(|file| root.join(file).exists())
+        .map(|file| file.to_string())
+        .collect()
+}
+
+// Version written in a plain file like .nvmrc or .python-version
+fn version_file(root: &Path, file: &str) -> Option<String> {
+    read(root, file)
+        .and_then(|content| content.lines().next().map(|line| line.trim().to_string()))
+        .filter(|version| !version.is_empty())
+}
+
+fn json_keys(value: &Value) -> Vec<String> {
+    value.as_object().map(|map| map.keys().cloned().collect()).unwrap_or_default()
+}
+
+fn toml_keys(value: Option<&toml::Value>) -> Vec<String> {
+    value.and_then(|value| value.as_table())
+        .map(|table| table.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+// First value following `key` on a line, e.g. `ruby "3.2.2"` or `go 1.22`
+fn value_after(content: &str, key: &str) -> Option<String> {
+    content.lines()
+        .map(|line| line.trim())
+        .find_map(|line| line.strip_prefix(key))
+        .and_then(|rest| {
+            let value = rest.trim_start_matches([' ', '=', '(']).split([',', ')', '#']).next()?.trim();
+            let value = value.trim_matches(|c| c == '"' || c == '\'');
+            (!value.is_empty()).then(|| value.to_string())
+        })
+}
+
+// Text between <tag> and </tag>
+fn xml_tag(content: &str, tag: &str) -> Option<String> {
+    let start = content.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = content[start..].find(&format!("</{}>", tag))? + start;
+    Some(content[start..end].trim().to_string())
+}
+
+fn detect_node(root: &Path) -> Option<Ecosystem> {
+    let manifest: Value = serde_json::from_str(&read(root, "package.json")?).ok()?;
+    let lockfiles = existing(root, &["package-lock.json", "yarn.lock", "pnpm-lock.yaml", "bun.lockb", "bun.lock"]);
+
+    // The packageManager field ("pnpm@9.1.0") wins over lockfile guessing
+    let package_manager = manifest["packageManager"].as_str()
+        .and_then(|value| value.split('@').next())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| match lockfiles.first().map(|file| file.as_str()) {
+            Some("yarn.lock") => "yarn".to_string(),
+            Some("pnpm-lock.yaml") => "pnpm".to_string(),
+            Some("bun.lockb") | Some("bun.lock") => "bun".to_string(),
+            _ => "npm".to_string(),
+        });
+
+    let mut dependencies = json_keys(&manifest["dependencies"]);
+    let dev_dependencies = json_keys(&manifest["devDependencies"]);
+    let language = if root.join("tsconfig.json").exists() || dev_dependencies.iter().any(|dep| dep == "typescript") {
+        "TypeScript"
+    } else {
+        "JavaScript"
+    };
+    dependencies.sort();
+
+    let scripts = manifest["scripts"].as_object()
+        .map(|scripts| {
+            scripts.iter()
+                .map(|(name, command)| format!("{}: {}", name, command.as_str().unwrap_or_default()))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut entrypoints: Vec<String> = manifest["main"].as_str().map(|main| vec![main.to_string()]).unwrap_or_default();
+    match &manifest["bin"] {
+        Value::String(bin) => entrypoints.push(bin.clone()),
+        Value::Object(bins) => entrypoints.extend(bins.values().filter_map(|bin| bin.as_str().map(|bin| bin.to_string()))),
+        _ => {}
+    }
+
+    Some(Ecosystem {
+        language: language.to_string(),
+        runtime_version: manifest["engines"]["node"].as_str()
+            .map(|version| version.to_string())
+            .or_else(|| version_file(root, ".nvmrc"))
+            .or_else(|| version_file(root, ".node-version")),
+        package_manager,
+        manifest: "package.json".to_string(),
+        scripts,
+        entrypoints,
+        lockfiles,
+        dependencies,
+    })
+}
+
+fn detect_python(root: &Path) -> Option<Ecosystem> {
+    let pyproject = read(root, "pyproject.toml").and_then(|content| content.parse::<toml::Value>().ok());
+    let requirements = read(root, "requirements.txt");
+    let setup_py = read(root, "setup.py");
+    let manifest = if pyproject.is_some() {
+        "pyproject.toml"
+    } else if requirements.is_some() {
+        "requirements.txt"
+    } else if setup_py.is_some() {
+        "setup.py"
+    } else {
+        return None;
+    };
+
+    let lockfiles = existing(root, &["poetry.lock", "Pipfile.lock", "uv.lock", "pdm.lock"]);
+    let mut runtime_version = None;
+    let mut dependencies = Vec::new();
+    let mut scripts = Vec::new();
+    let mut package_manager = "pip".to_string();
+
+    if let Some(pyproject) = &pyproject {
+        let project = pyproject.get("project");
+        let poetry = pyproject.get("tool").and_then(|tool| tool.get("poetry"));
+        if poetry.is_some() || lockfiles.iter().any(|file| file == "poetry.lock") {
+            package_manager = "poetry".to_string();
+        } else if lockfiles.iter().any(|file| file == "uv.lock") {
+            package_manager = "uv".to_string();
+        } else if lockfiles.iter().any(|file| file == "pdm.lock") {
+            package_manager = "pdm".to_string();
+        }
+
+        runtime_version = project.and_then(|project| project.get("requires-python"))
+            .or_else(|| poetry.and_then(|poetry| poetry.get("dependencies")).and_then(|deps| deps.get("python")))
+            .and_then(|version| version.as_str())
+            .map(|version| version.to_string());
+
+        // PEP 621 dependencies are requirement strings, Poetry's are a table
+        if let Some(list) = project.and_then(|project| project.get("dependencies")).and_then(|deps| deps.as_array()) {
+            dependencies.extend(list.iter().filter_map(|dep| dep.as_str()).map(requirement_name));
+        }
+        dependencies.extend(
+            toml_keys(poetry.and_then(|poetry| poetry.get("dependencies")))
+                .into_iter()
+                .filter(|name| name != "python"),
+        );
+
+        for table in [project.and_then(|project| project.get("scripts")), poetry.and_then(|poetry| poetry.get("scripts"))]
+            .into_iter()
+            .flatten()
+            .filter_map(|scripts| scripts.as_table())
+        {
+            scripts.extend(table.iter().map(|(name, target)| format!("{}: {}", name, target.as_str().unwrap_or_default())));
+        }
+    } else if root.join("Pipfile").exists() {
+        package_manager = "pipenv".to_string();
+    }
+
+    if let Some(requirements) = &requirements {
+        dependencies.extend(
+            requirements.lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('-'))
+                .map(requirement_name),
+        );
+    }
+    if runtime_version.is_none() {
+        runtime_version = version_file(root, ".python-version")
+            .or_else(|| read(root, "runtime.txt").map(|runtime| runtime.trim().trim_start_matches("python-").to_string()))
+            .or_else(|| setup_py.as_deref().and_then(|setup| value_after(setup, "python_requires")));
+    }
+    dependencies.sort();
+    dependencies.dedup();
+
+    Some(Ecosystem {
+        language: "Python".to_string(),
+        runtime_version,
+        package_manager,
+        manifest: manifest.to_string(),
+        scripts,
+        entrypoints: existing(root, &["main.py", "app.py", "manage.py", "wsgi.py", "asgi.py", "server.py", "run.py"]),
+        lockfiles,
+        dependencies,
+    })
+}
+
+// "flask[async]>=2.0 ; python_version>'3'" -> "flask"
+fn requirement_name(requirement: &str) -> String {
+    requirement.split(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'))
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+fn detect_rust(root: &Path) -> Option<Ecosystem> {
+    let manifest = read(root, "Cargo.toml")?.parse::<toml::Value>().ok()?;
+    let package = manifest.get("package");
+
+    let mut entrypoints: Vec<String> = manifest.get("bin")
+        .and_then(|bins| bins.as_array())
+        .map(|bins| bins.iter().filter_map(|bin| bin.get("name")?.as_str().map(|name| name.to_string())).collect())
+        .unwrap_or_default();
+    if entrypoints.is_empty() && root.join("src/main.rs").exists() {
+        if let Some(name) = package.and_then(|package| package.get("name")).and_then(|name| name.as_str()) {
+            entrypoints.push(name.to_string());
+        }
+    }
+    // Workspaces list their members instead of a package
+    let scripts = manifest.get("workspace")
+        .and_then(|workspace| workspace.get("members"))
+        .and_then(|members| members.as_array())
+        .map(|members| members.iter().filter_map(|member| member.as_str()).map(|member| format!("workspace member: {}", member)).collect())
+        .unwrap_or_default();
+
+    Some(Ecosystem {
+        language: "Rust".to_string(),
+        runtime_version: package.and_then(|package| package.get("rust-version"))
+            .and_then(|version| version.as_str())
+            .map(|version| version.to_string())
+            .or_else(|| version_file(root, "rust-toolchain")),
+        package_manager: "cargo".to_string(),
+        manifest: "Cargo.toml".to_string(),
+        scripts,
+        entrypoints,
+        lockfiles: existing(root, &["Cargo.lock"]),
+        dependencies: toml_keys(manifest.get("dependencies")),
+    })
+}
+
+fn detect_go(root: &Path) -> Option<Ecosystem> {
+    let manifest = read(root, "go.mod")?;
+
+    // Commands live in main.go or cmd/<name>/
+    let mut entrypoints = existing(root, &["main.go"]);
+    if let Ok(entries) = fs::read_dir(root.join("cmd")) {
+        let mut commands: Vec<String> = entries.filter_map(Result::ok)
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| format!("cmd/{}", entry.file_name().to_string_lossy()))
+            .collect();
+        commands.sort();
+        entrypoints.extend(commands);
+    }
+    let dependencies = manifest.lines()
+        .map(|line| line.trim())
+        .filter(|line| line.contains(" v") && !line.starts_with("module") && !line.starts_with("go "))
+        .filter_map(|line| line.trim_start_matches("require").split_whitespace().next().map(|name| name.to_string()))
+        .filter(|name| name != "(" && name != ")")
+        .collect();
+
+    Some(Ecosystem {
+        language: "Go".to_string(),
+        runtime_version: value_after(&manifest, "go "),
+        package_manager: "go".to_string(),
+        manifest: "go.mod".to_string(),
+        scripts: value_after(&manifest, "module ").map(|module| vec![format!("module: {}", module)]).unwrap_or_default(),
+        entrypoints,
+        lockfiles: existing(root, &["go.sum"]),
+        dependencies,
+    })
+}
+
+fn detect_java(root: &Path) -> Option<Ecosystem> {
+    if let Some(pom) = read(root, "pom.xml") {
+        let runtime_version = ["java.version", "maven.compiler.release", "maven.compiler.source"]
+            .iter()
+            .find_map(|tag| xml_tag(&pom, tag));
+        return Some(Ecosystem {
+            language: "Java".to_string(),
+            runtime_version,
+            package_manager: "maven".to_string(),
+            manifest: "pom.xml".to_string(),
+            scripts: existing(root, &["mvnw"]).into_iter().map(|wrapper| format!("wrapper: ./{}", wrapper)).collect(),
+            entrypoints: xml_tag(&pom, "mainClass").into_iter().collect(),
+            lockfiles: Vec::new(),
+            dependencies: Vec::new(),
+        });
+    }
+
+    let manifest = existing(root, &["build.gradle.kts", "build.gradle"]).into_iter().next()?;
+    let gradle = read(root, &manifest)?;
+    let language = if manifest.ends_with(".kts") || gradle.contains("kotlin(") || gradle.contains("org.jetbrains.kotlin") {
+        "Kotlin"
+    } else {
+        "Java"
+    };
+    // sourceCompatibility = JavaVersion.VERSION_17, '17' or languageVersion.set(JavaLanguageVersion.of(17))
+    let runtime_version = value_after(&gradle, "sourceCompatibility")
+        .map(|version| version.trim_start_matches("JavaVersion.VERSION_").replace('_', "."))
+        .or_else(|| {
+            let start = gradle.find("JavaLanguageVersion.of(")? + "JavaLanguageVersion.of(".len();
+            gradle[start..].split(')').next().map(|version| version.trim().to_string())
+        });
+
+    Some(Ecosystem {
+        language: language.to_string(),
+        runtime_version,
+        package_manager: "gradle".to_string(),
+        manifest,
+        scripts: existing(root, &["gradlew"]).into_iter().map(|wrapper| format!("wrapper: ./{}", wrapper)).collect(),
+        entrypoints: value_after(&gradle, "mainClass").into_iter().collect(),
+        lockfiles: existing(root, &["gradle.lockfile"]),
+        dependencies: Vec::new(),
+    })
+}
+
+fn detect_ruby(root: &Path) -> Option<Ecosystem> {
+    let gemfile = read(root, "Gemfile")?;
+    let dependencies = gemfile.lines()
+        .filter_map(|line| value_after(line.trim(), "gem "))
+        .collect();
+
+    Some(Ecosystem {
+        language: "Ruby".to_string(),
+        runtime_version: value_after(&gemfile, "ruby ").or_else(|| version_file(root, ".ruby-version")),
+        package_manager: "bundler".to_string(),
+        manifest: "Gemfile".to_string(),
+        scripts: Vec::new(),
+        entrypoints: existing(root, &["config.ru", "bin/rails", "app.rb"]),
+        lockfiles: existing(root, &["Gemfile.lock"]),
+        dependencies,
+    })
+}
+
+fn detect_php(root: &Path) -> Option<Ecosystem> {
+    let manifest: Value = serde_json::from_str(&read(root, "composer.json")?).ok()?;
+    let scripts = manifest["scripts"].as_object()
+        .map(|scripts| scripts.iter().map(|(name, command)| format!("{}: {}", name, command)).collect())
+        .unwrap_or_default();
+
+    Some(Ecosystem {
+        language: "PHP".to_string(),
+        runtime_version: manifest["require"]["php"].as_str().map(|version| version.to_string()),
+        package_manager: "composer".to_string(),
+        manifest: "composer.json".to_string(),
+        scripts,
+        entrypoints: existing(root, &["public/index.php", "index.php", "artisan"]),
+        lockfiles: existing(root, &["composer.lock"]),
+        dependencies: json_keys(&manifest["require"]).into_iter().filter(|name| name != "php").collect(),
+    })
+}
+
+// Compact JSON for the agents' prompts
+pub fn summarize(ecosystems: &[Ecosystem]) -> String {
+    if ecosystems.is_empty() {
+        return "No known manifest files found.".to_string();
+    }
+    serde_json::to_string_pretty(ecosystems).unwrap_or_default()
+}
@@ -0,0 +1,390 @@
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use walkdir::WalkDir;
+
+use crate::config::CONTAINER_ENGINE;
+
+// Label put on every image, container and volume autocontain creates, "autocontain.repo=<key>"
+pub const REPO_LABEL: &str = "autocontain.repo";
+// Label compose puts on the containers, networks and volumes of a project
+pub const COMPOSE_PROJECT_LABEL: &str = "com.docker.compose.project";
+
+// Supported CLIs, in the order they are looked for on PATH
+const ENGINES: [(&str, EngineKind); 3] = [
+    ("docker", EngineKind::Docker),
+    ("podman", EngineKind::Podman),
+    ("nerdctl", EngineKind::Nerdctl),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineKind {
+    Docker,
+    Podman,
+    Nerdctl,
+}
+
+// Result of one container engine invocation, stdout and stderr merged
+pub struct CommandOutput {
+    pub success: bool,
+    pub log: String,
+}
+
+// Kinds of resources an installation can create
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    Container,
+    Image,
+    Volume,
+    Network,
+}
+
+// State of a container found by `inspect`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerState {
+    Running,
+    Stopped,
+}
+
+// The container CLI used to build and run images: docker, podman or nerdctl
+pub struct ContainerEngine {
+    pub kind: EngineKind,
+    pub binary: String, // Name on PATH, or the path given in AUTOCONTAIN_ENGINE
+    pub rootless: bool,
+    pub compose: Vec<String>, // Compose command, e.g. ["docker", "compose"] or ["podman-compose"]
+}
+
+// Full path of an executable found on PATH
+fn find_on_path(name: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(format!("{}{}", name, env::consts::EXE_SUFFIX)))
+        .find(|path| path.is_file())
+}
+
+// Whether `binary args` runs and exits successfully, output discarded
+fn succeeds(binary: &str, args: &[&str]) -> bool {
+    Command::new(binary)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+// Non-empty lines `binary args` prints, none when it cannot be run
+fn command_lines(binary: &str, args: &[&str]) -> Vec<String> {
+    let Ok(output) = Command::new(binary).args(args).stderr(Stdio::null()).output() else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+impl ContainerEngine {
+    // The engine from AUTOCONTAIN_ENGINE, otherwise the first of docker, podman and nerdctl on PATH
+    // (docker when none is installed, so scripts can still be generated)
+    pub fn detect() -> Self {
+        let binary = CONTAINER_ENGINE.clone().unwrap_or_else(|| {
+            ENGINES.iter()
+                .find(|(engine, _)| find_on_path(engine).is_some())
+                .map_or("docker", |(engine, _)| engine)
+                .to_string()
+        });
+        ContainerEngine::new(&binary)
+    }
+
+    // The engine detected on first use, shared by everything a run does (detecting runs the CLI)
+    pub fn detected() -> &'static Self {
+        static DETECTED: OnceLock<ContainerEngine> = OnceLock::new();
+        DETECTED.get_or_init(ContainerEngine::detect)
+    }
+
+    // The engine for a given CLI, e.g. the one recorded when a repository was installed
+    pub fn new(binary: &str) -> Self {
+        let name = Path::new(binary).file_name().and_then(|f| f.to_str()).unwrap_or(binary);
+        let kind = ENGINES.iter()
+            .find(|(engine, _)| name.contains(engine))
+            .map_or(EngineKind::Docker, |(_, kind)| *kind);
+
+        // Podman runs rootless unless started as root; docker and nerdctl are assumed to use a daemon
+        let rootless = kind == EngineKind::Podman && Command::new(binary)
+            .args(["info", "--format", "{{.Host.Security.Rootless}}"])
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "true");
+
+        // Older podman versions have no `podman compose`, only the separate podman-compose tool
+        let compose = if kind == EngineKind::Podman
+            && !succeeds(binary, &["compose", "version"])
+            && find_on_path("podman-compose").is_some()
+        {
+            vec!["podman-compose".to_string()]
+        } else {
+            vec![binary.to_string(), "compose".to_string()]
+        };
+
+        ContainerEngine { kind, binary: binary.to_string(), rootless, compose }
+    }
+
+    // e.g. "podman (rootless) with podman-compose"
+    pub fn describe(&self) -> String {
+        format!("{}{} with {}", self.binary, if self.rootless { " (rootless)" } else { "" }, self.compose.join(" "))
+    }
+
+    // Run the engine with the output shown to the user, failing when it exits with an error
+    fn run_visible(&self, args: &[&str]) -> io::Result<()> {
+        let status = Command::new(&self.binary).args(args).status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("'{} {}' exited with {}", self.binary, args[0], status)));
+        }
+        Ok(())
+    }
+
+    // Build `dockerfile` with `context` as build context, tag the image and label it with `labels`
+    pub fn build(&self, dockerfile: &Path, context: &Path, tag: &str, labels: &[String]) -> io::Result<CommandOutput> {
+        println!("Building image '{}' with {}...", tag, self.binary);
+        let output = Command::new(&self.binary)
+            .arg("build")
+            .arg("-f")
+            .arg(dockerfile)
+            .arg("-t")
+            .arg(tag)
+            .args(labels.iter().flat_map(|label| ["--label", label.as_str()]))
+            .arg(context)
+            .output()?;
+
+        let mut log = String::from_utf8_lossy(&output.stdout).to_string();
+        log.push_str(&String::from_utf8_lossy(&output.stderr));
+        Ok(CommandOutput { success: output.status.success(), log })
+    }
+
+    pub fn image_exists(&self, image: &str) -> bool {
+        succeeds(&self.binary, &["image", "inspect", image])
+    }
+
+    // State of the container, None when it does not exist
+    pub fn inspect(&self, container: &str) -> Option<ContainerState> {
+        let output = Command::new(&self.binary)
+            .args(["container", "inspect", "-f", "{{.State.Running}}", container])
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+        match String::from_utf8_lossy(&output.stdout).trim() {
+            "true" => Some(ContainerState::Running),
+            _ => Some(ContainerState::Stopped),
+        }
+    }
+
+    // Start a detached container; `options` are the run flags (ports, env file, volumes, labels)
+    pub fn run(&self, container: &str, image: &str, options: &[String]) -> io::Result<()> {
+        let mut args = vec!["run", "-d", "--name", container];
+        args.extend(options.iter().map(|option| option.as_str()));
+        args.push(image);
+        self.run_visible(&args)
+    }
+
+    pub fn start(&self, container: &str) -> io::Result<()> {
+        self.run_visible(&["start", container])
+    }
+
+    pub fn stop(&self, container: &str) -> io::Result<()> {
+        self.run_visible(&["stop", container])
+    }
+
+    // Remove the container, stopping it first if it runs
+    pub fn rm(&self, container: &str) -> io::Result<()> {
+        self.run_visible(&["rm", "-f", container])
+    }
+
+    pub fn logs(&self, container: &str, follow: bool) -> io::Result<()> {
+        let mut args = vec!["logs"];
+        if follow {
+            args.push("--follow");
+        }
+        args.push(container);
+        self.run_visible(&args)
+    }
+
+    // Names of the resources of a kind, only those carrying `label` ("key=value") when given.
+    // Empty when the engine cannot be reached.
+    fn names(&self, kind: ResourceKind, label: Option<&str>) -> Vec<String> {
+        let (command, format): (&[&str], &str) = match kind {
+            ResourceKind::Container => (&["ps", "-a"], "{{.Names}}"),
+            ResourceKind::Image => (&["images"], "{{.Repository}}:{{.Tag}}"),
+            ResourceKind::Volume => (&["volume", "ls"], "{{.Name}}"),
+            ResourceKind::Network => (&["network", "ls"], "{{.Name}}"),
+        };
+        let mut args = command.to_vec();
+        let filter = label.map(|label| format!("label={}", label));
+        if let Some(filter) = &filter {
+            args.extend(["--filter", filter.as_str()]);
+        }
+        args.extend(["--format", format]);
+        command_lines(&self.binary, &args)
+            .into_iter()
+            .filter(|name| !name.contains("<none>"))
+            .collect()
+    }
+
+    // Names of every resource of a kind
+    pub fn list(&self, kind: ResourceKind) -> Vec<String> {
+        self.names(kind, None)
+    }
+
+    // Names of the resources of a kind carrying `label` ("key=value")
+    pub fn list_labeled(&self, kind: ResourceKind, label: &str) -> Vec<String> {
+        self.names(kind, Some(label))
+    }
+
+    // The image of every container, as (container, image) pairs
+    pub fn container_images(&self) -> Vec<(String, String)> {
+        command_lines(&self.binary, &["ps", "-a", "--format", "{{.Names}} {{.Image}}"])
+            .iter()
+            .filter_map(|line| line.split_once(' ').map(|(name, image)| (name.to_string(), image.trim().to_string())))
+            .collect()
+    }
+
+    pub fn create_volume(&self, volume: &str, labels: &[String]) -> io::Result<()> {
+        let mut args = vec!["volume", "create"];
+        args.extend(labels.iter().flat_map(|label| ["--label", label.as_str()]));
+        args.push(volume);
+        let output = Command::new(&self.binary).args(&args).output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!("'{} volume create' exited with {}", self.binary, output.status)));
+        }
+        Ok(())
+    }
+
+    pub fn remove_network(&self, network: &str) -> io::Result<()> {
+        self.run_visible(&["network", "rm", network])
+    }
+
+    pub fn remove_volume(&self, volume: &str) -> io::Result<()> {
+        self.run_visible(&["volume", "rm", volume])
+    }
+
+    pub fn remove_image(&self, image: &str) -> io::Result<()> {
+        self.run_visible(&["image", "rm", image])
+    }
+
+    // Disk space used by a resource in bytes, 0 when the engine does not tell. A volume is measured
+    // on disk, which only works when its directory is readable (e.g. rootless podman).
+    pub fn size(&self, kind: ResourceKind, name: &str) -> u64 {
+        let args: &[&str] = match kind {
+            ResourceKind::Container => &["container", "inspect", "--size", "-f", "{{.SizeRw}}"],
+            ResourceKind::Image => &["image", "inspect", "-f", "{{.Size}}"],
+            ResourceKind::Volume => &["volume", "inspect", "-f", "{{.Mountpoint}}"],
+            ResourceKind::Network => return 0,
+        };
+        let Ok(output) = Command::new(&self.binary).args(args).arg(name).stderr(Stdio::null()).output() else {
+            return 0;
+        };
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if kind == ResourceKind::Volume {
+            return WalkDir::new(value)
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter_map(|entry| entry.metadata().ok())
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len())
+                .sum();
+        }
+        value.parse().unwrap_or(0)
+    }
+
+    // The compose command for `file` and `project` followed by `args`
+    fn compose_command(&self, file: &Path, project: &str, args: &[&str]) -> Command {
+        let mut command = Command::new(&self.compose[0]);
+        command.args(&self.compose[1..])
+            .arg("-f")
+            .arg(file)
+            .args(["-p", project])
+            .args(args);
+        command
+    }
+
+    // Whether the compose project has containers
+    pub fn compose_is_up(&self, file: &Path, project: &str) -> bool {
+        self.compose_command(file, project, &["ps", "-q"])
+            .stderr(Stdio::null())
+            .output()
+            .is_ok_and(|output| output.status.success() && !output.stdout.trim_ascii().is_empty())
+    }
+
+    // Run a compose subcommand with the output shown to the user
+    fn compose_visible(&self, file: &Path, project: &str, args: &[&str]) -> io::Result<()> {
+        let status = self.compose_command(file, project, args).status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("'{} {}' exited with {}", self.compose.join(" "), args[0], status)));
+        }
+        Ok(())
+    }
+
+    pub fn compose_up(&self, file: &Path, project: &str) -> io::Result<()> {
+        self.compose_visible(file, project, &["up", "-d", "--build"])
+    }
+
+    pub fn compose_start(&self, file: &Path, project: &str) -> io::Result<()> {
+        self.compose_visible(file, project, &["start"])
+    }
+
+    pub fn compose_stop(&self, file: &Path, project: &str) -> io::Result<()> {
+        self.compose_visible(file, project, &["stop"])
+    }
+
+    pub fn compose_logs(&self, file: &Path, project: &str, follow: bool) -> io::Result<()> {
+        self.compose_visible(file, project, if follow { &["logs", "--follow"] } else { &["logs"] })
+    }
+}
+
+// Maximum lines of build output sent back to the agent
+const MAX_LOG_LINES: usize = 80;
+
+// "3/7" or "3", the step numbers of a step header
+fn is_step_number(text: &str) -> bool {
+    !text.is_empty() && text.split('/').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+// Id of a BuildKit step header, e.g. "#8" for "#8 [build 3/7] RUN npm ci"
+fn buildkit_step(line: &str) -> Option<&str> {
+    let (id, rest) = line.trim_start().split_once(' ')?;
+    let (label, _) = rest.strip_prefix('[')?.split_once(']')?;
+    let digits = id.strip_prefix('#')?;
+    (is_step_number(digits) && is_step_number(label.rsplit(' ').next()?)).then_some(id)
+}
+
+// Header of a build step: classic docker "Step 3/7 : RUN ...", podman/buildah "STEP 3/7: RUN ..."
+// or BuildKit "#8 [3/7] RUN ..."
+fn is_step_header(line: &str) -> bool {
+    let line = line.trim_start();
+    let numbered = |rest: &str, separator: &str| rest.split_once(separator).is_some_and(|(number, _)| is_step_number(number));
+    match (line.strip_prefix("Step "), line.strip_prefix("STEP ")) {
+        (Some(rest), _) => numbered(rest, " :"),
+        (_, Some(rest)) => numbered(rest, ":"),
+        _ => buildkit_step(line).is_some(),
+    }
+}
+
+// Cut a build log down to the step that failed: from the failing step's header to the end, limited
+// to the last MAX_LOG_LINES lines. BuildKit marks the failing step with "#8 ERROR: ..."; for the
+// other builders the failing step is the last one started.
+pub fn failing_step_log(log: &str) -> String {
+    let lines: Vec<&str> = log.lines().collect();
+    let failed_buildkit_step = lines.iter().rev().find_map(|line| {
+        let (id, rest) = line.trim_start().split_once(' ')?;
+        (id.starts_with('#') && rest.starts_with("ERROR")).then_some(id)
+    });
+    let start = failed_buildkit_step
+        .and_then(|id| lines.iter().position(|line| buildkit_step(line) == Some(id)))
+        .or_else(|| lines.iter().rposition(|line| is_step_header(line)))
+        .unwrap_or(0);
+    let start = start.max(lines.len().saturating_sub(MAX_LOG_LINES));
+    lines[start..].join("\n")
+}
@@ -0,0 +1,48 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set while an LLM request is running; Ctrl-C then cancels the request instead of exiting
+static IN_FLIGHT: AtomicBool = AtomicBool::new(false);
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+// Install the Ctrl-C handler. Outside of a request (or on a second Ctrl-C) it exits as usual.
+pub fn install_handler() {
+    let result = ctrlc::set_handler(|| {
+        if IN_FLIGHT.load(Ordering::SeqCst) && !CANCELLED.load(Ordering::SeqCst) {
+            eprintln!("\nCancelling request... (press Ctrl-C again to quit)");
+            cancel_request();
+        } else {
+            process::exit(130);
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("Warning: failed to install Ctrl-C handler: {}", e);
+    }
+}
+
+// Marks a request as in flight for as long as it is alive
+pub struct RequestGuard;
+
+impl RequestGuard {
+    pub fn begin() -> Self {
+        CANCELLED.store(false, Ordering::SeqCst);
+        IN_FLIGHT.store(true, Ordering::SeqCst);
+        RequestGuard
+    }
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT.store(false, Ordering::SeqCst);
+    }
+}
+
+// Cancel the request in flight, as the first Ctrl-C does
+pub fn cancel_request() {
+    CANCELLED.store(true, Ordering::SeqCst);
+}
+
+// Whether the user asked to cancel the current request
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
@@ -1,105 +1,365 @@
 mod agents;
+mod analysis;
 mod parser;
 mod db;
+mod discovery;
+mod dockerfile;
+mod ecosystem;
 mod models;
 mod chat;
 mod config;
+mod runplan;
+mod services;
+mod templates;
 
+pub mod budget;
+pub mod build;
+pub mod cache;
+pub mod engine;
+pub mod interrupt;
+pub mod lifecycle;
+pub mod llm;
+pub mod replay;
 pub mod repo;
+pub mod review;
+pub mod safety;
+pub mod source;
+pub mod update;
 pub mod utils;
 
 use std::error::Error;
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
 use std::fs;
 use std::io::{self, Write};
 
 use rusqlite::Connection;
 
-use agents::{documentation_analysis_agent, docker_file_generation_agent, run_script_generation_agent};
-use repo::{check_github_repo, clone_repo, cleanup_repos, find_and_merge_content, apply_tag, view_basic_analysis, view_tree_structure, install_repo, parse_repo};
-use db::initialize_db;
+use budget::{documentation_budget, fit_documents, print_usage_report};
+use agents::{documentation_analysis_agent, docker_file_generation_agent, dockerfile_repair_agent, run_script_generation_agent};
+use analysis::RepoAnalysis;
+use discovery::{select_docker_files, DockerSelection};
+use ecosystem::{detect_ecosystems, Ecosystem};
+use engine::ContainerEngine;
+use services::{AppService, Component};
+use source::{head_commit, probe_remote, Metadata, RepoSource};
+use repo::{clone_repo, set_persist_tag, cleanup_repos, find_and_merge_content, apply_tag, view_basic_analysis, view_tree_structure, install_repo, parse_repo};
+use db::{get_imported_modules, initialize_db};
 use chat::chat_with_assistant;
 
 fn agents_caller(
-    local_path: PathBuf, // Repository's path on machine
-    md_content: String, // Markdown content
-    docker_content: &mut HashMap<String, String>, // Docker-related content
-    scripts_path: PathBuf, // Path to store repo analysis result and installation script returned from OpenAI API
-) -> bool {
-    // Merge all docker contents into 1 string
-    let docker_combined = docker_content.values().cloned().collect::<Vec<String>>().join("\n\n");
-    // Merge docker content and markdown content into 1 string
-    let combined_content = format!("Markdown content:\n{}\n\nDocker content:\n{}", md_content, docker_combined);
-
-    // Call the analysis agent to give a basic view about the repository
-    let result = documentation_analysis_agent(&combined_content).and_then(|analysis| {
-        // When received result from the agent
-        // Write to analysis.md
-        fs::write(scripts_path.join("analysis.md"), &analysis)?;
-        
-        // Call another agent to generate a Dockerfile if no docker-related contents is found
-        if docker_content.is_empty() {
-            println!("No Docker-related files found. Generating Dockerfile.");
-            let generated_dockerfile = docker_file_generation_agent(&analysis)?;
-            fs::write(local_path.join("Dockerfile"), &generated_dockerfile)?;
-            docker_content.insert("Dockerfile".to_string(), generated_dockerfile);
+    local_path: &Path, // Repository's path on machine
+    md_documents: Vec<(PathBuf, String)>, // Markdown files and their content
+    selection: &DockerSelection, // Dockerfile and compose file chosen from the repository
+    code_imports: &[String], // Modules imported by the repository's code
+    scripts_path: &Path, // Path to store repo analysis result and installation script returned from the LLM
+    options: &RunOptions,
+) -> Result<(), Box<dyn Error>> {
+    // Read the chosen docker files, Dockerfile first so identical repos give identical prompts
+    let read_chosen = |path: &Option<PathBuf>| -> io::Result<Option<(PathBuf, String)>> {
+        match path {
+            Some(path) => Ok(Some((local_path.join(path), fs::read_to_string(local_path.join(path))?))),
+            None => Ok(None),
         }
-        // Currently assume the name of the Dockerfile is 'Dockerfile'
-        let dockerfile_path = local_path.join("Dockerfile");
-        let dockerfile_path_str = dockerfile_path.to_str().unwrap();
-        // Get docker_compose path (if there is any) from the docker_content HashMap
-        let docker_compose_path = docker_content.keys()
-            .find(|key| key.ends_with(".yml") || key.ends_with(".yaml"))
-            .map(|key| local_path.join(key));
-        let docker_compose_path_str = docker_compose_path.as_deref().and_then(|p| p.to_str());
-        // Call another agent to generate the run script to install the container from docker-related file
-        let run_script = run_script_generation_agent(&docker_content, dockerfile_path_str, docker_compose_path_str)?;
-        fs::write(scripts_path.join("run.sh"), run_script)?;
-
-        Ok::<(), Box<dyn Error>>(())
-    });
-
-    if let Err(e) = result {
-        eprintln!("Error in calling agents: {}", e);
-        return false;
+    };
+    let chosen_dockerfile = read_chosen(&selection.dockerfile)?;
+    let compose = read_chosen(&selection.compose)?;
+    let docker_combined = chosen_dockerfile.iter().chain(compose.iter())
+        .map(|(path, content)| format!("# {}\n{}", path.strip_prefix(local_path).unwrap_or(path).display(), content))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    // Read the manifest files so the agents know the stack instead of guessing it from prose
+    let ecosystems = detect_ecosystems(local_path);
+    fs::write(scripts_path.join("ecosystem.json"), serde_json::to_string_pretty(&ecosystems)?)?;
+    let ecosystem_summary = ecosystem::summarize(&ecosystems);
+
+    let analysis = if options.no_llm {
+        // Without the LLM the analysis only knows what the manifests say
+        templates::heuristic_analysis(&ecosystems)
+    } else {
+        // Fit the markdown into what is left of the token budget, most useful documents first
+        let fixed_content = format!("{}\n{}", docker_combined, ecosystem_summary);
+        let md_content = fit_documents(local_path, md_documents, documentation_budget(&fixed_content))?;
+        // Merge manifest summary, docker content and markdown content into 1 string
+        let combined_content = format!(
+            "Detected ecosystems (from manifest files):\n{}\n\nMarkdown content:\n{}\n\nDocker content:\n{}",
+            ecosystem_summary, md_content, docker_combined
+        );
+        // Call the analysis agent to give a basic view about the repository
+        documentation_analysis_agent(&combined_content)?
+    };
+    // Write the structured result to analysis.json and a readable version to analysis.md
+    fs::write(scripts_path.join("analysis.json"), serde_json::to_string_pretty(&analysis)?)?;
+    fs::write(scripts_path.join("analysis.md"), analysis.render_markdown())?;
+
+    // Backing services (databases, caches, queues) and separate applications (frontend/backend)
+    // cannot run from a single container, they get a compose file
+    let components = if selection.is_empty() { services::detect_components(local_path) } else { Vec::new() };
+    let mut service_ecosystems = ecosystems.clone();
+    service_ecosystems.extend(components.iter().flat_map(|component| component.ecosystems.iter().cloned()));
+    let detections = services::detect_services(local_path, &service_ecosystems, code_imports);
+    for detection in &detections {
+        println!("Detected service {} ({}).", detection.service.name, detection.reason);
     }
-    
-    true
+
+    // Generate a Dockerfile (from a template, or with another agent) if no docker-related file is found.
+    // The generated file lives in the scripts folder so the source tree stays as cloned
+    // and a re-run sends the same prompts (hitting the response cache).
+    let dockerfile = if selection.is_empty() && components.is_empty() {
+        println!("No Docker-related files found. Generating Dockerfile.");
+        let generated_dockerfile = generate_dockerfile(&analysis, &ecosystems, local_path, scripts_path, options)?;
+        fs::write(scripts_path.join("Dockerfile"), &generated_dockerfile)?;
+        Some((scripts_path.join("Dockerfile"), generated_dockerfile))
+    } else {
+        chosen_dockerfile
+    };
+
+    // Generate compose.yaml in the scripts folder when the repository does not ship one but needs it
+    let compose = match compose {
+        Some(compose) => Some(compose),
+        None if !components.is_empty() || (!detections.is_empty() && dockerfile.is_some()) => {
+            let apps = match &dockerfile {
+                Some((dockerfile_path, _)) if components.is_empty() => vec![AppService {
+                    name: "app".to_string(),
+                    context: local_path.to_path_buf(),
+                    dockerfile: dockerfile_path.clone(),
+                    ports: analysis.exposed_ports.clone(),
+                }],
+                _ => component_services(local_path, scripts_path, &components)?,
+            };
+            let content = services::compose_file(&apps, &detections);
+            let compose_path = scripts_path.join("compose.yaml");
+            fs::write(&compose_path, &content)?;
+            println!("Generated {} with {} service(s).", compose_path.display(), apps.len() + detections.len());
+            Some((compose_path, content))
+        }
+        None => None,
+    };
+
+    // Build run.sh from the docker files and the analysis; the agent writes it only if that fails
+    let engine = ContainerEngine::detected();
+    println!("Container engine: {}.", engine.describe());
+    let dockerfile_for_plan = dockerfile.as_ref().map(|(path, content)| (path.as_path(), content.as_str()));
+    let compose_path = compose.as_ref().map(|(path, _)| path.as_path());
+    let run_script = match runplan::build_run_plan(local_path, scripts_path, dockerfile_for_plan, compose_path, &analysis) {
+        Ok(plan) => {
+            plan.save(scripts_path)?;
+            plan.render_script(engine)
+        }
+        Err(e) if !options.no_llm => {
+            eprintln!("Warning: {} Asking the run script agent instead.", e);
+            // A plan left from an earlier run would be installed instead of the agent's script
+            fs::remove_file(scripts_path.join("run_plan.json")).ok();
+            let dockerfile = dockerfile.as_ref().map(|(path, content)| (path.to_str().unwrap(), content.as_str()));
+            let compose = compose.as_ref().map(|(path, content)| (path.to_str().unwrap(), content.as_str()));
+            // Call another agent to generate the run script to install the container from docker-related file
+            run_script_generation_agent(dockerfile, local_path.to_str().unwrap(), compose, engine)?
+        }
+        Err(e) => return Err(e),
+    };
+    fs::write(scripts_path.join("run.sh"), run_script)?;
+
+    Ok(())
 }
 
-pub fn process_repository(link: &str, persist: bool, depth: usize) -> Result<(String, PathBuf, PathBuf, Connection), Box<dyn Error>> {
-    // Check if the GitHub repository exists
-    if !check_github_repo(link)? {
-        eprintln!("Repository link is invalid or inaccessible.");
-        return Err("Repository link is invalid or inaccessible.".into());
+// Application containers of a multi-service repository: each component's own Dockerfile, or one
+// generated from its stack's template into the scripts folder (<component>.Dockerfile)
+fn component_services(local_path: &Path, scripts_path: &Path, components: &[Component]) -> Result<Vec<AppService>, Box<dyn Error>> {
+    let mut apps = Vec::new();
+    for component in components {
+        let context = local_path.join(&component.path);
+        let analysis = templates::heuristic_analysis(&component.ecosystems);
+        let dockerfile = if context.join("Dockerfile").is_file() {
+            context.join("Dockerfile")
+        } else if let Some((stack, content)) = templates::dockerfile(&component.ecosystems, &analysis) {
+            println!("Using the {} Dockerfile template for {}.", stack, component.name);
+            let path = scripts_path.join(format!("{}.Dockerfile", component.name));
+            fs::write(&path, content)?;
+            path
+        } else {
+            eprintln!("Warning: no Dockerfile or template for {}, it is left out of compose.yaml.", component.name);
+            continue;
+        };
+        apps.push(AppService { name: component.name.clone(), context, dockerfile, ports: analysis.exposed_ports });
+    }
+    if apps.is_empty() {
+        return Err("None of the repository's components has a Dockerfile or a matching template.".into());
     }
+    Ok(apps)
+}
 
-    // Clone the repository (or skip if already cloned)
-    let (repo_name, local_path) = clone_repo(link, persist)?;
+// Use the template of a recognized stack, and the Dockerfile agent for anything else (unless --no-llm)
+fn generate_dockerfile(
+    analysis: &RepoAnalysis,
+    ecosystems: &[Ecosystem],
+    local_path: &Path,
+    scripts_path: &Path,
+    options: &RunOptions,
+) -> Result<String, Box<dyn Error>> {
+    if let Some((stack, dockerfile)) = templates::dockerfile(ecosystems, analysis) {
+        println!("Using the {} Dockerfile template.", stack);
+        let report = dockerfile::format_report(&dockerfile::lint(&dockerfile, local_path));
+        fs::write(scripts_path.join("dockerfile_lint.txt"), report)?;
+        return Ok(dockerfile);
+    }
+    if options.no_llm {
+        return Err("No Dockerfile template matches this repository; run without --no-llm to generate one.".into());
+    }
+    generate_valid_dockerfile(analysis, ecosystems, local_path, scripts_path, options.repair_rounds)
+}
+
+// Generate a Dockerfile and lint it, asking the agent to repair it until it has no errors or the rounds
+// run out (warnings alone are not worth a round). The final lint report is saved as dockerfile_lint.txt
+// in the scripts folder.
+fn generate_valid_dockerfile(
+    analysis: &RepoAnalysis,
+    ecosystems: &[Ecosystem],
+    local_path: &Path,
+    scripts_path: &Path,
+    repair_rounds: usize,
+) -> Result<String, Box<dyn Error>> {
+    let mut dockerfile = docker_file_generation_agent(analysis, ecosystems)?;
+    let mut violations = dockerfile::lint(&dockerfile, local_path);
+
+    for round in 1..=repair_rounds {
+        if !dockerfile::has_errors(&violations) {
+            break;
+        }
+        println!("Generated Dockerfile has {} problem(s), repair round {}/{}.", violations.len(), round, repair_rounds);
+        dockerfile = dockerfile_repair_agent(&dockerfile, &dockerfile::format_report(&violations), analysis)?;
+        violations = dockerfile::lint(&dockerfile, local_path);
+    }
+
+    let report = dockerfile::format_report(&violations);
+    fs::write(scripts_path.join("dockerfile_lint.txt"), &report)?;
+    if dockerfile::has_errors(&violations) {
+        eprintln!("Warning: generated Dockerfile still has problems:\n{}", report);
+    }
+    Ok(dockerfile)
+}
+
+// Analyze a local checkout and write docker_files.json, ecosystem.json, analysis.json/analysis.md, run_plan.json, run.sh (and a Dockerfile
+// and compose.yaml if the repo needs them but has none) to scripts_path.
+// Does not clone or touch the database, so it can run against any directory.
+pub fn generate_scripts(
+    local_path: &Path,
+    scripts_path: &Path,
+    code_imports: &[String], // Modules imported by the code, from the parsed code database
+    options: &RunOptions,
+) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(scripts_path)?;
+    // Find documentation, and the Dockerfile and compose file to use
+    let (md_documents, _) = find_and_merge_content(local_path, options.depth)?;
+    let selection = select_docker_files(local_path, options.dockerfile.as_deref(), options.compose.as_deref(), options.interactive)?;
+    selection.save(scripts_path)?;
+
+    // Call the agents, then report what they cost
+    let result = agents_caller(local_path, md_documents, &selection, code_imports, scripts_path, options);
+    print_usage_report();
+    result
+}
+
+// Options of the `run` command
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    pub persist: bool, // Keep the repo and generated content after exiting
+    pub depth: usize, // How deep to search for markdown files
+    pub no_cache: bool, // Ignore cached LLM responses (fresh ones are still saved)
+    pub repair_rounds: usize, // Repair attempts for a generated Dockerfile that fails the linter
+    pub fix_build_attempts: usize, // Builds to try (fixing the Dockerfile in between) before run.sh; 0 disables
+    pub no_llm: bool, // Only use templates and heuristics, never call the LLM
+    pub dockerfile: Option<String>, // Dockerfile to use, relative to the repository root
+    pub compose: Option<String>, // Compose file to use, relative to the repository root
+    pub interactive: bool, // Ask which file to use when the repository has several
+    pub yes: bool, // Install without asking to confirm the commands
+    pub dry_run: bool, // Only print the commands installing would run
+    pub git_ref: Option<String>, // Branch, tag or commit to check out instead of the default branch
+    pub full_history: bool, // Clone every commit instead of only the latest one
+    pub sparse: bool, // Only check out the files the analysis reads until installing
+    pub submodules: bool, // Clone the repository's submodules
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            persist: false,
+            depth: 0,
+            no_cache: false,
+            repair_rounds: 2,
+            fix_build_attempts: 0,
+            no_llm: false,
+            dockerfile: None,
+            compose: None,
+            interactive: false,
+            yes: false,
+            dry_run: false,
+            git_ref: None,
+            full_history: false,
+            sparse: false,
+            submodules: false,
+        }
+    }
+}
+
+pub fn process_repository(source: &RepoSource, options: &RunOptions) -> Result<(String, PathBuf, PathBuf, Connection), Box<dyn Error>> {
+    let persist = options.persist;
+    cache::set_lookups_enabled(!options.no_cache);
+
+    let repo_name = source.key().to_string();
+    let local_path = match source {
+        RepoSource::Remote { url, .. } => {
+            // Check that the repository exists and can be read, like `git ls-remote`
+            if let Err(e) = probe_remote(url) {
+                eprintln!("Repository '{}' is invalid or inaccessible: {}", url, e.message());
+                return Err("Repository link is invalid or inaccessible.".into());
+            }
+            // Clone the repository (or skip if already cloned)
+            clone_repo(url, &repo_name, options)?
+        }
+        // Checking out a ref would change the user's working tree
+        RepoSource::Local { .. } if options.git_ref.is_some() => {
+            return Err("--ref cannot be used with a local directory, check the ref out there first.".into());
+        }
+        RepoSource::Local { path, .. } => {
+            println!("Analyzing local directory {} in place.", path.display());
+            set_persist_tag(&repo_name, persist);
+            path.clone()
+        }
+    };
 
     // Initialize and connect to the database
     let conn = Connection::open("autocontain.db").expect("Failed to connect to database.");
     initialize_db(&conn).expect("Failed to initialize database.");
 
+    // Record the analyzed commit, so the analysis can be reproduced with --ref
+    let scripts_path = Path::new("scripts").join(repo_name.clone());
+    let metadata = Metadata {
+        source: match source {
+            RepoSource::Remote { url, .. } => url.clone(),
+            RepoSource::Local { path, .. } => path.display().to_string(),
+        },
+        git_ref: options.git_ref.clone(),
+        commit: head_commit(&local_path),
+        depth: options.depth,
+        full_history: options.full_history,
+        sparse: options.sparse,
+        submodules: options.submodules,
+    };
+    metadata.save(&scripts_path)?;
+    if let Some(commit) = &metadata.commit {
+        println!("Analyzing commit {}.", commit);
+    }
+
     // Parsing the repo to the database
-    parse_repo(&repo_name, &local_path.to_string_lossy().to_string(), &conn);
+    let repo_id = parse_repo(&repo_name, local_path.to_string_lossy().as_ref(), &metadata, &conn);
+    let code_imports = get_imported_modules(&conn, repo_id).unwrap_or_default();
 
-    // Generating scripts part
-    let scripts_path = Path::new("scripts").join(repo_name.clone());
-    if !scripts_path.exists() {
-        fs::create_dir_all(&scripts_path)?;
-        // Analyze documentation and Docker-related files
-        let (md_content, _, mut docker_content) = find_and_merge_content(&local_path, depth)?;
-        
-        // Call the agents
-        if agents_caller(local_path.clone(), md_content, &mut docker_content, scripts_path.clone()) {
-            println!("Repository processed successfully, files saved in '{}'.", scripts_path.display());
-        } else {
-            println!("Repository processed, failed to call OpenAI.");
+    // Generating scripts part. Agents always run; unchanged prompts are answered from the response cache.
+    match generate_scripts(&local_path, &scripts_path, &code_imports, options) {
+        Ok(()) => println!("Repository processed successfully, files saved in '{}'.", scripts_path.display()),
+        Err(e) => {
+            eprintln!("Error in calling agents: {}", e);
+            println!("Repository processed, failed to call the LLM.");
         }
-    } else {
-        println!("Scripts already exists. Not calling agents.")
     }
 
     // Apply tag if --persist is specified
@@ -110,7 +370,7 @@ pub fn process_repository(link: &str, persist: bool, depth: usize) -> Result<(St
     Ok((repo_name, local_path, scripts_path, conn))
 }
 
-pub fn run_menu(persist: bool, local_path: &Path, scripts_path: &Path, conn: &Connection) {
+pub fn run_menu(options: &RunOptions, local_path: &Path, scripts_path: &Path, conn: &Connection) {
     loop {
         // Display the menu
         println!("Choose an option:");
@@ -136,14 +396,15 @@ pub fn run_menu(persist: bool, local_path: &Path, scripts_path: &Path, conn: &Co
         match input {
             "0" => {
                 println!("Exiting program...");
-                if !persist {
+                if !options.persist {
                     cleanup_repos();
                 }
                 break;
             },
             "1" => view_basic_analysis(scripts_path),
             "2" => view_tree_structure(local_path),
-            "3" => install_repo(scripts_path),
+            "3" => install_repo(local_path, scripts_path, options),
+            "4" if options.no_llm => println!("The assistant is not available with --no-llm."),
             "4" => chat_with_assistant(conn),
             _ => println!("Invalid choice, please try again."),
         }
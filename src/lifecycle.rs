@@ -0,0 +1,308 @@
+use std::error::Error;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{ContainerEngine, ContainerState, ResourceKind, COMPOSE_PROJECT_LABEL};
+use crate::runplan::RunPlan;
+use crate::source::installed_keys;
+
+// What installing a repository created, saved as resources.json in the scripts folder.
+// The lifecycle commands only ever touch these.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Resources {
+    pub engine: String, // CLI the resources were created with
+    pub compose: Option<PathBuf>, // Compose file, when installed with compose
+    pub project: Option<String>, // Compose project name
+    #[serde(default)]
+    pub labels: Vec<String>, // "key=value" labels the resources carry
+    #[serde(default)]
+    pub commit: Option<String>, // Commit the containers were last installed from
+    pub containers: Vec<String>,
+    pub images: Vec<String>,
+    pub volumes: Vec<String>,
+    pub networks: Vec<String>,
+}
+
+fn merge(names: &mut Vec<String>, new: Vec<String>) {
+    for name in new {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+}
+
+impl Resources {
+    pub fn load(scripts_path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(scripts_path.join("resources.json")).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self, scripts_path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(scripts_path.join("resources.json"), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.containers.is_empty() && self.images.is_empty() && self.volumes.is_empty() && self.networks.is_empty()
+    }
+
+    // Resources of a kind the engine has with one of the repository's labels
+    pub fn labeled(&self, engine: &ContainerEngine, kind: ResourceKind) -> Vec<String> {
+        let mut names = Vec::new();
+        for label in &self.labels {
+            merge(&mut names, engine.list_labeled(kind, label));
+        }
+        names
+    }
+}
+
+// Record what the install created, added to what earlier installs recorded: the container, image
+// and volumes the plan names, and whatever carries the repository's labels (the compose project's
+// containers, networks and volumes). Base images pulled by the build and what other tools created
+// meanwhile are left out. A run.sh written by the agent labels nothing, so nothing is recorded.
+pub fn record_install(scripts_path: &Path, engine: &ContainerEngine, plan: Option<&RunPlan>) -> Result<Resources, Box<dyn Error>> {
+    let mut resources = Resources::load(scripts_path).unwrap_or_default();
+    resources.engine = engine.binary.clone();
+    if let Some(plan) = plan {
+        match &plan.compose {
+            Some(compose) => {
+                resources.compose = Some(compose.clone());
+                resources.project = Some(plan.container.clone());
+                merge(&mut resources.labels, vec![format!("{}={}", COMPOSE_PROJECT_LABEL, plan.container)]);
+            }
+            None => {
+                merge(&mut resources.labels, plan.labels.clone());
+                if engine.inspect(&plan.container).is_some() {
+                    merge(&mut resources.containers, vec![plan.container.clone()]);
+                }
+                if plan.dockerfile.is_some() && engine.image_exists(&plan.image) {
+                    merge(&mut resources.images, vec![format!("{}:latest", plan.image)]);
+                }
+                let volumes = engine.list(ResourceKind::Volume);
+                merge(&mut resources.volumes, plan.volumes.iter().map(|volume| volume.name.clone()).filter(|name| volumes.contains(name)).collect());
+            }
+        }
+    }
+    let containers = resources.labeled(engine, ResourceKind::Container);
+    let images = resources.labeled(engine, ResourceKind::Image);
+    let volumes = resources.labeled(engine, ResourceKind::Volume);
+    let networks = resources.labeled(engine, ResourceKind::Network);
+    merge(&mut resources.containers, containers);
+    merge(&mut resources.images, images);
+    merge(&mut resources.volumes, volumes);
+    merge(&mut resources.networks, networks);
+    resources.save(scripts_path)?;
+    Ok(resources)
+}
+
+// The recorded resources of an installed repository and the engine that created them
+fn load_installed(scripts_path: &Path) -> Result<(Resources, ContainerEngine), Box<dyn Error>> {
+    let name = scripts_path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let resources = Resources::load(scripts_path)
+        .filter(|resources| !resources.is_empty())
+        .ok_or_else(|| format!("Nothing is recorded for '{}', install it first.", name))?;
+    let engine = ContainerEngine::new(&resources.engine);
+    Ok((resources, engine))
+}
+
+// The compose file and project to use instead of single containers, if the file still exists
+fn compose_project(resources: &Resources) -> Option<(&Path, &str)> {
+    match (&resources.compose, &resources.project) {
+        (Some(file), Some(project)) if file.is_file() => Some((file.as_path(), project.as_str())),
+        _ => None,
+    }
+}
+
+// Print every installed repository with the state of its containers
+pub fn show_status(scripts_base: &Path) -> Result<(), Box<dyn Error>> {
+    let repos: Vec<(String, Resources)> = installed_keys(scripts_base)
+        .into_iter()
+        .filter_map(|key| {
+            let resources = Resources::load(&scripts_base.join(&key)).filter(|resources| !resources.is_empty())?;
+            Some((key, resources))
+        })
+        .collect();
+    if repos.is_empty() {
+        println!("No installed containers.");
+        return Ok(());
+    }
+
+    for (name, resources) in repos {
+        let engine = ContainerEngine::new(&resources.engine);
+        println!("- {} ({})", name, engine.binary);
+        for container in &resources.containers {
+            let state = match engine.inspect(container) {
+                Some(ContainerState::Running) => "running",
+                Some(ContainerState::Stopped) => "stopped",
+                None => "removed",
+            };
+            println!("    {:<40} {}", container, state);
+        }
+        println!(
+            "    {} image(s), {} volume(s), {} network(s)",
+            resources.images.len(),
+            resources.volumes.len(),
+            resources.networks.len()
+        );
+    }
+    Ok(())
+}
+
+pub fn show_logs(scripts_path: &Path, follow: bool) -> Result<(), Box<dyn Error>> {
+    let (resources, engine) = load_installed(scripts_path)?;
+    if let Some((file, project)) = compose_project(&resources) {
+        engine.compose_logs(file, project, follow)?;
+        return Ok(());
+    }
+    // Following only works for one container at a time
+    let containers = if follow { &resources.containers[..resources.containers.len().min(1)] } else { &resources.containers[..] };
+    for container in containers {
+        if resources.containers.len() > 1 {
+            println!("==> {} <==", container);
+        }
+        engine.logs(container, follow)?;
+    }
+    Ok(())
+}
+
+pub fn stop(scripts_path: &Path) -> Result<(), Box<dyn Error>> {
+    let (resources, engine) = load_installed(scripts_path)?;
+    if let Some((file, project)) = compose_project(&resources) {
+        engine.compose_stop(file, project)?;
+    } else {
+        for container in resources.containers.iter().filter(|container| engine.inspect(container) == Some(ContainerState::Running)) {
+            engine.stop(container)?;
+        }
+    }
+    println!("Stopped.");
+    Ok(())
+}
+
+pub fn start(scripts_path: &Path) -> Result<(), Box<dyn Error>> {
+    let (resources, engine) = load_installed(scripts_path)?;
+    if resources.containers.is_empty() {
+        return Err("No containers to start, install the repository again.".into());
+    }
+    if let Some((file, project)) = compose_project(&resources) {
+        engine.compose_start(file, project)?;
+    } else {
+        for container in resources.containers.iter().filter(|container| engine.inspect(container) == Some(ContainerState::Stopped)) {
+            engine.start(container)?;
+        }
+    }
+    println!("Started.");
+    Ok(())
+}
+
+// Recorded names that still carry one of the repository's labels. Those without it were created
+// by something else (or recorded before installs were labeled) and are dropped from the record.
+fn owned(resources: &Resources, engine: &ContainerEngine, kind: ResourceKind, names: Vec<String>) -> Vec<String> {
+    let labeled = resources.labeled(engine, kind);
+    let existing = engine.list(kind);
+    names.into_iter()
+        .filter(|name| {
+            let owned = labeled.contains(name);
+            if !owned && existing.contains(name) {
+                println!("Leaving {} alone, it was not created by autocontain.", name);
+            }
+            owned
+        })
+        .collect()
+}
+
+// Remove the recorded containers and networks still present, returning the space their
+// writable layers used
+fn remove_containers_and_networks(resources: &mut Resources, engine: &ContainerEngine) -> u64 {
+    let mut reclaimed = 0;
+    let containers = mem::take(&mut resources.containers);
+    for container in owned(resources, engine, ResourceKind::Container, containers) {
+        let size = engine.size(ResourceKind::Container, &container);
+        match engine.rm(&container) {
+            Ok(()) => reclaimed += size,
+            Err(e) => eprintln!("Failed to remove container {}: {}", container, e),
+        }
+    }
+    let networks = mem::take(&mut resources.networks);
+    for network in owned(resources, engine, ResourceKind::Network, networks) {
+        if let Err(e) = engine.remove_network(&network) {
+            eprintln!("Failed to remove network {}: {}", network, e);
+        }
+    }
+    reclaimed
+}
+
+// Remove the containers and networks; images and volumes are kept so installing again is quick
+pub fn down(scripts_path: &Path) -> Result<(), Box<dyn Error>> {
+    let (mut resources, engine) = load_installed(scripts_path)?;
+    remove_containers_and_networks(&mut resources, &engine);
+    resources.save(scripts_path)?;
+    println!("Containers and networks removed.");
+    Ok(())
+}
+
+// "app" -> "app:latest", as containers list the image they were started from
+fn with_tag(image: &str) -> String {
+    match image.rsplit_once(':') {
+        Some((_, tag)) if !tag.contains('/') => image.to_string(),
+        _ => format!("{}:latest", image),
+    }
+}
+
+// Remove everything the repository's installs created (images too unless `keep_images`) and
+// return the disk space reclaimed in bytes. Only resources carrying the repository's labels are
+// removed, and images are kept while containers of something else use them. Resources that fail
+// to be removed are reported and skipped, so removing a repository never stops half-way.
+pub fn teardown(scripts_path: &Path, keep_images: bool) -> Result<u64, Box<dyn Error>> {
+    let Some(mut resources) = Resources::load(scripts_path) else {
+        return Ok(0);
+    };
+    let engine = ContainerEngine::new(&resources.engine);
+    let mut reclaimed = remove_containers_and_networks(&mut resources, &engine);
+
+    let volumes = mem::take(&mut resources.volumes);
+    for volume in owned(&resources, &engine, ResourceKind::Volume, volumes) {
+        let size = engine.size(ResourceKind::Volume, &volume);
+        match engine.remove_volume(&volume) {
+            Ok(()) => reclaimed += size,
+            Err(e) => eprintln!("Failed to remove volume {}: {}", volume, e),
+        }
+    }
+    if !keep_images {
+        // The repository's containers are gone, so whatever still uses an image belongs to something else
+        let in_use: Vec<String> = engine.container_images().iter().map(|(_, image)| with_tag(image)).collect();
+        let images = mem::take(&mut resources.images);
+        for image in owned(&resources, &engine, ResourceKind::Image, images) {
+            if in_use.contains(&with_tag(&image)) {
+                println!("Keeping image {}, containers not created by autocontain use it.", image);
+                resources.images.push(image);
+                continue;
+            }
+            let size = engine.size(ResourceKind::Image, &image);
+            match engine.remove_image(&image) {
+                Ok(()) => reclaimed += size,
+                Err(e) => eprintln!("Failed to remove image {}: {}", image, e),
+            }
+        }
+    }
+    resources.save(scripts_path)?;
+    Ok(reclaimed)
+}
+
+// e.g. 1536 -> "1.5 KB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
@@ -0,0 +1,557 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::config::{
+    LLM_API_KEY, LLM_BASE_URL, LLM_CONNECT_TIMEOUT, LLM_FIXTURES_DIR, LLM_MAX_RETRIES, LLM_MODE,
+    LLM_PROVIDER, LLM_TIMEOUT,
+};
+use crate::interrupt::is_cancelled;
+use crate::replay::{RecordingProvider, ReplayProvider};
+
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+// A single chat completion request, independent of the backend it is sent to
+pub struct ChatRequest<'a> {
+    pub agent: &'a str, // Which agent (or chat step) is asking, used to key fixtures
+    pub model: &'a str,
+    pub messages: &'a [Value],
+    pub temperature: f64,
+    pub max_tokens: u32,
+    pub json_schema: Option<&'a Value>, // Constrain the answer to this JSON schema ({"name", "strict", "schema"})
+}
+
+// Tokens billed for one call, as reported by the provider
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+// The assistant's text together with what it cost
+pub struct Completion {
+    pub content: String,
+    pub usage: Usage,
+}
+
+// Common interface of every LLM backend the agents can talk to
+pub trait LlmProvider: Send + Sync {
+    // Name shown in logs and error messages
+    fn name(&self) -> &str;
+
+    // Send the request and return the assistant's text
+    fn complete(&self, request: &ChatRequest) -> Result<Completion, Box<dyn Error>>;
+
+    // Same as `complete`, but hands each piece of text to `on_token` as soon as it arrives.
+    // Backends without streaming deliver the whole answer at once.
+    fn stream(&self, request: &ChatRequest, on_token: &mut dyn FnMut(&str)) -> Result<Completion, Box<dyn Error>> {
+        let completion = self.complete(request)?;
+        on_token(&completion.content);
+        Ok(completion)
+    }
+}
+
+// Failures of an LLM call, split by what the user can do about them
+#[derive(Debug)]
+pub enum LlmError {
+    Auth(String), // Missing, invalid or unauthorized API key
+    Quota(String), // Billing quota exhausted, retrying will not help
+    RateLimited(String), // Too many requests, still failing after all retries
+    Server(StatusCode, String), // 5xx from the provider, still failing after all retries
+    InvalidRequest(StatusCode, String), // Any other 4xx, e.g. unknown model or context too long
+    Transport(String), // Connection failures and timeouts
+    MalformedResponse(String), // The body does not have the expected shape
+    Truncated(String), // The model stopped at max_tokens, the output is incomplete
+    Cancelled, // The user pressed Ctrl-C
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LlmError::Auth(message) => write!(f, "authentication failed: {}", message),
+            LlmError::Quota(message) => write!(f, "quota exceeded: {}", message),
+            LlmError::RateLimited(message) => write!(f, "rate limited: {}", message),
+            LlmError::Server(status, message) => write!(f, "server error ({}): {}", status, message),
+            LlmError::InvalidRequest(status, message) => write!(f, "request rejected ({}): {}", status, message),
+            LlmError::Transport(message) => write!(f, "transport error: {}", message),
+            LlmError::MalformedResponse(message) => write!(f, "malformed response: {}", message),
+            LlmError::Truncated(agent) => write!(
+                f,
+                "response for '{}' was cut off at max_tokens, refusing to use incomplete output",
+                agent
+            ),
+            LlmError::Cancelled => write!(f, "request cancelled"),
+        }
+    }
+}
+
+impl Error for LlmError {}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_decode() {
+            LlmError::MalformedResponse(error.to_string())
+        } else {
+            LlmError::Transport(error.to_string())
+        }
+    }
+}
+
+impl LlmError {
+    // Whether the same request may succeed if sent again later
+    fn is_retryable(&self) -> bool {
+        matches!(self, LlmError::RateLimited(_) | LlmError::Server(..) | LlmError::Transport(_))
+    }
+}
+
+// One HTTP client (connection pool) shared by every provider, with the configured timeouts
+static HTTP_CLIENT: Lazy<Client> = Lazy::new(|| {
+    Client::builder()
+        .timeout(*LLM_TIMEOUT)
+        .connect_timeout(*LLM_CONNECT_TIMEOUT)
+        .build()
+        .expect("Failed to build HTTP client")
+});
+
+// Provider selected through LLM_PROVIDER, built once on first use
+pub static PROVIDER: Lazy<Result<Box<dyn LlmProvider>, String>> = Lazy::new(provider_from_config);
+
+// Build the provider described by the environment (.env), honoring LLM_MODE
+fn provider_from_config() -> Result<Box<dyn LlmProvider>, String> {
+    match LLM_MODE.as_str() {
+        "live" => live_provider_from_config(),
+        "replay" => Ok(Box::new(ReplayProvider::new(LLM_FIXTURES_DIR.clone()))),
+        "record" => Ok(Box::new(RecordingProvider::new(
+            live_provider_from_config()?,
+            LLM_FIXTURES_DIR.clone(),
+        ))),
+        other => Err(format!("Unknown LLM_MODE '{}', expected 'live', 'record' or 'replay'", other)),
+    }
+}
+
+// Build the network-backed provider selected by LLM_PROVIDER
+fn live_provider_from_config() -> Result<Box<dyn LlmProvider>, String> {
+    live_provider(&LLM_PROVIDER, LLM_BASE_URL.clone(), LLM_API_KEY.clone())
+}
+
+// Build a network-backed provider by name, with the default base URL of the hosted APIs
+pub fn live_provider(provider: &str, base_url: Option<String>, api_key: Option<String>) -> Result<Box<dyn LlmProvider>, String> {
+    match provider {
+        "openai" => {
+            let api_key = api_key.ok_or("OPENAI_API_KEY not found in .env")?;
+            Ok(Box::new(OpenAiProvider::new(
+                "openai",
+                base_url.unwrap_or_else(|| OPENAI_BASE_URL.to_string()),
+                Some(api_key),
+            )))
+        }
+        // llama.cpp server, Ollama, vLLM and friends: same API, own base URL, key optional
+        "openai-compatible" => {
+            let base_url = base_url.ok_or("LLM_BASE_URL must be set for the openai-compatible provider")?;
+            Ok(Box::new(OpenAiProvider::new("openai-compatible", base_url, api_key)))
+        }
+        "anthropic" => {
+            let api_key = api_key.ok_or("ANTHROPIC_API_KEY not found in .env")?;
+            Ok(Box::new(AnthropicProvider::new(
+                base_url.unwrap_or_else(|| ANTHROPIC_BASE_URL.to_string()),
+                api_key,
+            )))
+        }
+        other => Err(format!(
+            "Unknown LLM_PROVIDER '{}', expected 'openai', 'openai-compatible' or 'anthropic'",
+            other
+        )),
+    }
+}
+
+// Stable short hash of the prompt messages, used to key fixtures
+pub fn prompt_hash(messages: &[Value]) -> String {
+    let serialized = serde_json::to_string(messages).unwrap_or_default();
+    let digest = Sha256::digest(serialized.as_bytes());
+    digest.iter().take(8).map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Extract the error message from an API error body, falling back to the raw body
+fn api_error_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|value| value["error"]["message"].as_str().map(|message| message.to_string()))
+        .unwrap_or_else(|| body.trim().to_string())
+}
+
+// Map a non-success HTTP status to an error, returning how long the server asked us to wait
+fn classify_failure(response: Response) -> (LlmError, Option<Duration>) {
+    let status = response.status();
+    let retry_after = response.headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    let body = response.text().unwrap_or_default();
+    let message = api_error_message(&body);
+
+    let error = match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => LlmError::Auth(message),
+        StatusCode::TOO_MANY_REQUESTS if body.contains("insufficient_quota") => LlmError::Quota(message),
+        StatusCode::TOO_MANY_REQUESTS => LlmError::RateLimited(message),
+        // 529 is Anthropic's "overloaded"
+        _ if status.is_server_error() || status.as_u16() == 529 => LlmError::Server(status, message),
+        _ => LlmError::InvalidRequest(status, message),
+    };
+    (error, retry_after)
+}
+
+// Retry-After is either a number of seconds or an HTTP date; a date in the past means now
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = parse_http_date(value)?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO))
+}
+
+// Parse the HTTP date format servers send, e.g. "Wed, 21 Oct 2015 07:28:00 GMT"
+fn parse_http_date(value: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let [_, day, month, year, time, "GMT"] = parts.as_slice() else {
+        return None;
+    };
+    let day: i64 = day.parse().ok().filter(|day| (1..=31).contains(day))?;
+    let month = MONTHS.iter().position(|name| name == month)? as i64 + 1;
+    let year: i64 = year.parse().ok().filter(|year| *year >= 1970)?;
+    let clock: Vec<u64> = time.split(':').map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    let [hours, minutes, seconds] = clock.as_slice() else {
+        return None;
+    };
+    if *hours > 23 || *minutes > 59 || *seconds > 60 {
+        return None;
+    }
+
+    // Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+    let year = if month <= 2 { year - 1 } else { year };
+    let (era, year_of_era) = (year / 400, year % 400);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146097 + day_of_era - 719468) as u64;
+
+    Some(UNIX_EPOCH + Duration::from_secs(days * 86400 + hours * 3600 + minutes * 60 + seconds))
+}
+
+// Wait for `delay`, checking for Ctrl-C in short slices. Returns false if the user cancelled.
+fn sleep_unless_cancelled(delay: Duration) -> bool {
+    const SLICE: Duration = Duration::from_millis(100);
+
+    let mut remaining = delay;
+    while !remaining.is_zero() {
+        if is_cancelled() {
+            return false;
+        }
+        let slice = remaining.min(SLICE);
+        thread::sleep(slice);
+        remaining -= slice;
+    }
+    !is_cancelled()
+}
+
+// Send the request, retrying rate limits, server errors and transport failures with exponential
+// backoff. A Retry-After header from the server takes precedence over the computed delay.
+fn send_with_retry(provider: &str, request: RequestBuilder) -> Result<Response, LlmError> {
+    let mut attempt = 0;
+    loop {
+        let builder = request.try_clone()
+            .ok_or_else(|| LlmError::Transport("request cannot be retried".to_string()))?;
+        let (error, retry_after) = match builder.send() {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) => classify_failure(response),
+            Err(e) => (LlmError::from(e), None),
+        };
+
+        if !error.is_retryable() || attempt >= *LLM_MAX_RETRIES {
+            return Err(error);
+        }
+        let backoff = Duration::from_secs(1 << attempt.min(5));
+        let delay = retry_after.unwrap_or(backoff).min(Duration::from_secs(120));
+        attempt += 1;
+        eprintln!(
+            "{} request failed ({}), retrying in {}s (attempt {}/{})",
+            provider, error, delay.as_secs(), attempt, *LLM_MAX_RETRIES
+        );
+        if !sleep_unless_cancelled(delay) {
+            return Err(LlmError::Cancelled);
+        }
+    }
+}
+
+// Read a server-sent event stream, calling `on_event` with each event name and JSON payload.
+// An event's data may span several `data:` lines and ends at a blank line.
+// Stops at the end of the stream, at OpenAI's "[DONE]" marker or when the user cancels.
+fn read_events(response: Response, mut on_event: impl FnMut(&str, &Value)) -> Result<(), LlmError> {
+    let mut event = String::new();
+    let mut data = String::new();
+    // The trailing blank line dispatches an event left open when the stream ends
+    for line in BufReader::new(response).lines().chain(std::iter::once(Ok(String::new()))) {
+        if is_cancelled() {
+            return Err(LlmError::Cancelled);
+        }
+        let line = line.map_err(|e| LlmError::Transport(e.to_string()))?;
+
+        if let Some(name) = line.strip_prefix("event:") {
+            event = name.trim().to_string();
+        } else if let Some(part) = line.strip_prefix("data:") {
+            if !data.is_empty() {
+                data.push('\n');
+            }
+            data.push_str(part.strip_prefix(' ').unwrap_or(part));
+        } else if line.is_empty() {
+            let payload = data.trim();
+            if payload == "[DONE]" {
+                break;
+            }
+            if !payload.is_empty() {
+                let payload: Value = serde_json::from_str(payload)
+                    .map_err(|e| LlmError::MalformedResponse(format!("bad stream event '{}': {}", payload, e)))?;
+                on_event(&event, &payload);
+            }
+            event.clear();
+            data.clear();
+        }
+    }
+    Ok(())
+}
+
+//---------------- OpenAI and OpenAI-compatible servers -----------------
+
+pub struct OpenAiProvider {
+    name: String,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl OpenAiProvider {
+    pub fn new(name: &str, base_url: String, api_key: Option<String>) -> Self {
+        OpenAiProvider {
+            name: name.to_string(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        }
+    }
+}
+
+impl OpenAiProvider {
+    fn request_builder(&self, request: &ChatRequest, stream: bool) -> RequestBuilder {
+        let mut body = json!({
+            "model": request.model,
+            "messages": request.messages,
+            "temperature": request.temperature,
+            "max_tokens": request.max_tokens
+        });
+        if let Some(json_schema) = request.json_schema {
+            body["response_format"] = json!({"type": "json_schema", "json_schema": json_schema});
+        }
+        if stream {
+            body["stream"] = json!(true);
+            // Only OpenAI itself is known to accept this; it adds token usage to the last event
+            if self.name == "openai" {
+                body["stream_options"] = json!({"include_usage": true});
+            }
+        }
+
+        let builder = HTTP_CLIENT
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&body);
+        match &self.api_key {
+            Some(api_key) => builder.bearer_auth(api_key),
+            None => builder,
+        }
+    }
+}
+
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn complete(&self, request: &ChatRequest) -> Result<Completion, Box<dyn Error>> {
+        let builder = self.request_builder(request, false);
+        let response = send_with_retry(&self.name, builder)?.json::<Value>().map_err(LlmError::from)?;
+
+        // Extract and return the assistant's response content
+        let choice = &response["choices"][0];
+        let content = choice["message"]["content"].as_str()
+            .ok_or_else(|| LlmError::MalformedResponse(format!("no message content in {}", response)))?;
+        if choice["finish_reason"] == "length" {
+            return Err(LlmError::Truncated(request.agent.to_string()).into());
+        }
+        let usage = Usage {
+            prompt_tokens: response["usage"]["prompt_tokens"].as_u64().unwrap_or(0),
+            completion_tokens: response["usage"]["completion_tokens"].as_u64().unwrap_or(0),
+        };
+        Ok(Completion { content: content.to_string(), usage })
+    }
+
+    fn stream(&self, request: &ChatRequest, on_token: &mut dyn FnMut(&str)) -> Result<Completion, Box<dyn Error>> {
+        let builder = self.request_builder(request, true);
+        let response = send_with_retry(&self.name, builder)?;
+
+        let mut content = String::new();
+        let mut usage = Usage::default();
+        let mut truncated = false;
+        read_events(response, |_, chunk| {
+            let choice = &chunk["choices"][0];
+            if let Some(token) = choice["delta"]["content"].as_str() {
+                on_token(token);
+                content.push_str(token);
+            }
+            if choice["finish_reason"] == "length" {
+                truncated = true;
+            }
+            if let Some(prompt_tokens) = chunk["usage"]["prompt_tokens"].as_u64() {
+                usage.prompt_tokens = prompt_tokens;
+                usage.completion_tokens = chunk["usage"]["completion_tokens"].as_u64().unwrap_or(0);
+            }
+        })?;
+
+        if truncated {
+            return Err(LlmError::Truncated(request.agent.to_string()).into());
+        }
+        Ok(Completion { content, usage })
+    }
+}
+
+//---------------- Anthropic Messages API -----------------
+
+pub struct AnthropicProvider {
+    base_url: String,
+    api_key: String,
+}
+
+impl AnthropicProvider {
+    pub fn new(base_url: String, api_key: String) -> Self {
+        AnthropicProvider {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        }
+    }
+}
+
+// Anthropic takes the system prompt as a separate field instead of a message
+fn split_system_messages(messages: &[Value]) -> (String, Vec<Value>) {
+    let mut system = Vec::new();
+    let mut rest = Vec::new();
+    for message in messages {
+        if message["role"] == "system" {
+            system.push(message["content"].as_str().unwrap_or_default());
+        } else {
+            rest.push(message.clone());
+        }
+    }
+    (system.join("\n\n"), rest)
+}
+
+impl AnthropicProvider {
+    fn request_builder(&self, request: &ChatRequest, stream: bool) -> RequestBuilder {
+        let (mut system, messages) = split_system_messages(request.messages);
+        // No constrained decoding here, so the schema is spelled out in the system prompt
+        if let Some(json_schema) = request.json_schema {
+            system.push_str(&format!(
+                "\n\nRespond with a single JSON object and nothing else. It must match this JSON schema:\n{}",
+                json_schema["schema"]
+            ));
+        }
+        let mut body = json!({
+            "model": request.model,
+            "messages": messages,
+            "temperature": request.temperature,
+            "max_tokens": request.max_tokens
+        });
+        if !system.is_empty() {
+            body["system"] = json!(system);
+        }
+        if stream {
+            body["stream"] = json!(true);
+        }
+
+        HTTP_CLIENT
+            .post(format!("{}/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&body)
+    }
+}
+
+impl LlmProvider for AnthropicProvider {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    fn complete(&self, request: &ChatRequest) -> Result<Completion, Box<dyn Error>> {
+        let builder = self.request_builder(request, false);
+        let response = send_with_retry("anthropic", builder)?.json::<Value>().map_err(LlmError::from)?;
+
+        // Concatenate the text blocks of the reply
+        let blocks = response["content"].as_array()
+            .ok_or_else(|| LlmError::MalformedResponse(format!("no content blocks in {}", response)))?;
+        let text = blocks.iter()
+            .filter_map(|block| block["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("");
+        if response["stop_reason"] == "max_tokens" {
+            return Err(LlmError::Truncated(request.agent.to_string()).into());
+        }
+        let usage = Usage {
+            prompt_tokens: response["usage"]["input_tokens"].as_u64().unwrap_or(0),
+            completion_tokens: response["usage"]["output_tokens"].as_u64().unwrap_or(0),
+        };
+        Ok(Completion { content: text, usage })
+    }
+
+    fn stream(&self, request: &ChatRequest, on_token: &mut dyn FnMut(&str)) -> Result<Completion, Box<dyn Error>> {
+        let builder = self.request_builder(request, true);
+        let response = send_with_retry("anthropic", builder)?;
+
+        let mut content = String::new();
+        let mut usage = Usage::default();
+        let mut truncated = false;
+        let mut stream_error = None;
+        read_events(response, |event, data| match event {
+            "message_start" => {
+                usage.prompt_tokens = data["message"]["usage"]["input_tokens"].as_u64().unwrap_or(0);
+            }
+            "content_block_delta" => {
+                if let Some(token) = data["delta"]["text"].as_str() {
+                    on_token(token);
+                    content.push_str(token);
+                }
+            }
+            "message_delta" => {
+                truncated = data["delta"]["stop_reason"] == "max_tokens";
+                usage.completion_tokens = data["usage"]["output_tokens"].as_u64().unwrap_or(0);
+            }
+            "error" => {
+                stream_error = Some(api_error_message(&data.to_string()));
+            }
+            _ => {}
+        })?;
+
+        if let Some(message) = stream_error {
+            return Err(LlmError::Server(StatusCode::OK, message).into());
+        }
+        if truncated {
+            return Err(LlmError::Truncated(request.agent.to_string()).into());
+        }
+        Ok(Completion { content, usage })
+    }
+}
@@ -1,9 +1,24 @@
 use std::process;
 use std::env;
+use std::io::{self, IsTerminal};
+use std::path::Path;
 
-use autocontain::{process_repository, run_menu};
+use autocontain::{process_repository, run_menu, RunOptions};
+use autocontain::{interrupt, lifecycle, update};
+use autocontain::source::RepoSource;
 use autocontain::utils::print_usage_and_exit;
-use autocontain::repo::{remove_repo, get_all_repos};
+use autocontain::repo::{get_all_repos, installed_repos, migrate_layout, remove_repo, resolve_repo};
+
+// The key of the installed repository the argument names, exits when there is none
+fn resolve_or_exit(repo_name: &str) -> String {
+    match resolve_repo(repo_name) {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
 
 fn main() {
     // Parse command-line arguments
@@ -12,31 +27,86 @@ fn main() {
         print_usage_and_exit();
     }
 
+    // Repositories used to be kept by name only
+    migrate_layout();
+
     match args[1].as_str() {
         "rm" => { // Remove repository from machine, one at a time
-            let repo_name = &args[2];
-            remove_repo(repo_name);
+            let keep_images = args[2..].iter().any(|arg| arg == "--keep-images");
+            let Some(repo_name) = args[2..].iter().find(|arg| !arg.starts_with("--")) else {
+                print_usage_and_exit();
+                return;
+            };
+            remove_repo(&resolve_or_exit(repo_name), keep_images);
         }
         "run" => { // Clone the repository, parse the code and generate Docker-related file (if none were found)
-            let link = &args[2];
-            // Validate GitHub link format
-            if !link.starts_with("https://github.com/") {
-                eprintln!("Invalid GitHub repository link.");
-                process::exit(1);
-            }
+            // Ctrl-C cancels a running LLM request instead of exiting
+            interrupt::install_handler();
+            let Some(link) = args.get(2) else {
+                print_usage_and_exit();
+                return;
+            };
+            // A git URL, a host shortcut such as gh:owner/repo, or a local directory
+            let source = match RepoSource::parse(link) {
+                Ok(source) => source,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
             // Default values
-            let mut persist = false;
-            let mut depth = 0;
+            let mut options = RunOptions { interactive: io::stdin().is_terminal(), ..RunOptions::default() };
 
             // Get tags
-            for arg in &args[3..] {
+            let mut rest = args[3..].iter();
+            while let Some(arg) = rest.next() {
                 match arg.as_str() {
                     // Install the repository permanantly
-                    "--persist" => persist = true,
+                    "--persist" => options.persist = true,
+                    // Ignore cached LLM responses for this run
+                    "--no-cache" => options.no_cache = true,
+                    // Generate everything from templates and manifest heuristics, without the LLM
+                    "--no-llm" => options.no_llm = true,
+                    // Install without confirming the reviewed commands
+                    "--yes" | "-y" => options.yes = true,
+                    // Show the commands installing would run, without running them
+                    "--dry-run" => options.dry_run = true,
+                    // How much of the repository to clone
+                    "--full-history" => options.full_history = true,
+                    "--sparse" => options.sparse = true,
+                    "--submodules" => options.submodules = true,
+                    // Branch, tag or commit to analyze instead of the default branch
+                    "--ref" => match rest.next() {
+                        Some(value) => options.git_ref = Some(value.to_string()),
+                        None => print_usage_and_exit(),
+                    },
+                    _ if arg.starts_with("--ref=") => {
+                        options.git_ref = arg.strip_prefix("--ref=").map(|value| value.to_string());
+                    }
+                    // Dockerfile or compose file to use when the repository ships several
+                    _ if arg.starts_with("--dockerfile=") => {
+                        options.dockerfile = arg.strip_prefix("--dockerfile=").map(|value| value.to_string());
+                    }
+                    _ if arg.starts_with("--compose=") => {
+                        options.compose = arg.strip_prefix("--compose=").map(|value| value.to_string());
+                    }
                     // How deep the program should search for Markdown files.
                     _ if arg.starts_with("--depth=") => {
                         if let Some(value) = arg.strip_prefix("--depth=") {
-                            depth = value.parse::<usize>().unwrap_or(0);
+                            options.depth = value.parse::<usize>().unwrap_or(0);
+                        }
+                    }
+                    // How many times a generated Dockerfile is sent back to the agent to fix lint errors
+                    _ if arg.starts_with("--repair-rounds=") => {
+                        if let Some(value) = arg.strip_prefix("--repair-rounds=") {
+                            options.repair_rounds = value.parse::<usize>().unwrap_or(options.repair_rounds);
+                        }
+                    }
+                    // Build the image before installing, feeding build errors back to the Dockerfile agent
+                    "--fix-build" => options.fix_build_attempts = 3,
+                    _ if arg.starts_with("--fix-build=") => {
+                        if let Some(value) = arg.strip_prefix("--fix-build=") {
+                            options.fix_build_attempts = value.parse::<usize>().unwrap_or(3);
                         }
                     }
                     // Invalid tags for run command
@@ -48,14 +118,70 @@ fn main() {
             }
 
             // Main function to pre-process the repository
-            let (_, local_path, scripts_path, conn) = process_repository(link, persist, depth)
-                .expect("Failed to process repository.");
+            let (_, local_path, scripts_path, conn) = match process_repository(&source, &options) {
+                Ok(processed) => processed,
+                Err(e) => {
+                    eprintln!("Failed to process repository: {}", e);
+                    process::exit(1);
+                }
+            };
             // Run the cli menu
-            run_menu(persist, &local_path, &scripts_path, &conn);
+            run_menu(&options, &local_path, &scripts_path, &conn);
+        }
+        "update" => { // Pull installed repositories and analyze them again where they changed
+            // Ctrl-C cancels a running LLM request instead of exiting
+            interrupt::install_handler();
+            let options = RunOptions {
+                interactive: io::stdin().is_terminal(),
+                no_llm: args[2..].iter().any(|arg| arg == "--no-llm"),
+                ..RunOptions::default()
+            };
+            let keys = if args[2..].iter().any(|arg| arg == "--all") {
+                installed_repos()
+            } else {
+                let Some(repo_name) = args[2..].iter().find(|arg| !arg.starts_with("--")) else {
+                    print_usage_and_exit();
+                    return;
+                };
+                vec![resolve_or_exit(repo_name)]
+            };
+            let mut failed = false;
+            for key in keys {
+                if let Err(e) = update::update_repo(&key, &options) {
+                    eprintln!("Failed to update '{}': {}", key, e);
+                    failed = true;
+                }
+            }
+            if failed {
+                process::exit(1);
+            }
         }
         "list" => { // List all repositories installed
             get_all_repos();
         }
+        "status" => { // State of the containers of every installed repository
+            if let Err(e) = lifecycle::show_status(Path::new("scripts")) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        "logs" | "stop" | "start" | "down" => { // Manage the containers a repository's install created
+            let Some(repo_name) = args.get(2) else {
+                print_usage_and_exit();
+                return;
+            };
+            let scripts_path = Path::new("scripts").join(resolve_or_exit(repo_name));
+            let result = match args[1].as_str() {
+                "logs" => lifecycle::show_logs(&scripts_path, args[3..].iter().any(|arg| arg == "--follow" || arg == "-f")),
+                "stop" => lifecycle::stop(&scripts_path),
+                "start" => lifecycle::start(&scripts_path),
+                _ => lifecycle::down(&scripts_path),
+            };
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
         _ => { // Invalid argument after cargo run --
             eprintln!("Invalid argument '{}'", args[1]);
             print_usage_and_exit();
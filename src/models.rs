@@ -5,6 +5,8 @@ pub struct Repository {
     pub id: Option<i32>, 
     pub name: String,
     pub description: Option<String>,
+    pub git_ref: Option<String>, // Branch, tag or commit asked for with --ref
+    pub commit: Option<String>, // Commit the code was parsed from
 }
 
 #[derive(Debug)]
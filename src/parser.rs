@@ -6,7 +6,7 @@ use tree_sitter::{Node, Parser};
 use walkdir::WalkDir;
 
 use crate::models::{Class, Function};
-use crate::db::{insert_class, insert_function, insert_dependencies};
+use crate::db::{insert_class, insert_function, insert_dependencies, insert_imports};
 
 // Initializes a tree-sitter parser for Python.
 fn initialize_parser() -> Parser {
@@ -22,7 +22,7 @@ pub fn parse_repository(repo_path: &str, conn: &Connection, repo_id: i32) {
     // Walk through each file in the directory and parse Python files
     for entry in WalkDir::new(repo_path) {
         let entry = entry.expect("Failed to access entry");
-        if entry.path().extension().map_or(false, |ext| ext == "py") {
+        if entry.path().extension().is_some_and(|ext| ext == "py") {
             let code = fs::read_to_string(entry.path()).expect("Failed to read file");
             parse_file(&code, &mut parser, conn, repo_id, entry.path().to_str().unwrap());
         }
@@ -35,6 +35,47 @@ fn parse_file(code: &str, parser: &mut Parser, conn: &Connection, repo_id: i32,
     let root_node = tree.root_node();
 
     extract_classes_and_functions(root_node, code, conn, repo_id, file_path);
+    let imports = extract_imports(root_node, code);
+    insert_imports(conn, repo_id, file_path, &imports).expect("Failed to insert imports");
+}
+
+// Extracts the top-level module of every absolute import in the file, wherever the import
+// statement is (module level, inside try blocks or functions)
+fn extract_imports(root: Node, code: &str) -> Vec<String> {
+    fn top_level_module(node: Node, code: &str) -> Option<String> {
+        let name = node.utf8_text(code.as_bytes()).ok()?;
+        name.split('.').next().filter(|module| !module.is_empty()).map(|module| module.to_string())
+    }
+
+    fn traverse(node: Node, code: &str, imports: &mut HashSet<String>) {
+        match node.kind() {
+            "import_statement" => {
+                let mut cursor = node.walk();
+                for child in node.children_by_field_name("name", &mut cursor) {
+                    // `import a.b as c` wraps the dotted name in an aliased_import
+                    let dotted = child.child_by_field_name("name").unwrap_or(child);
+                    imports.extend(top_level_module(dotted, code));
+                }
+            }
+            "import_from_statement" => {
+                if let Some(module) = node.child_by_field_name("module_name").filter(|module| module.kind() == "dotted_name") {
+                    imports.extend(top_level_module(module, code));
+                }
+            }
+            _ => {
+                let mut cursor = node.walk();
+                for child in node.children(&mut cursor) {
+                    traverse(child, code, imports);
+                }
+            }
+        }
+    }
+
+    let mut imports = HashSet::new();
+    traverse(root, code, &mut imports);
+    let mut imports: Vec<String> = imports.into_iter().collect();
+    imports.sort();
+    imports
 }
 
 // Extracts classes and functions information and stores in sqlite database
@@ -64,11 +105,11 @@ fn extract_classes_and_functions(root: Node, code: &str, conn: &Connection, repo
 fn process_function_definition(node: Node, code: &str, conn: &Connection, repo_id: i32, class_id: Option<i32>, file_path: &str) {
     let func = create_function_struct(node, code, repo_id, class_id, file_path);
     // Insert the function data into the database
-    insert_function(conn, &func).expect("Failed to insert function");
+    let function_id = insert_function(conn, &func).expect("Failed to insert function");
     // Insert function dependencies into the database
     let func_name = func.name.clone();
     let dependencies = extract_dependencies(node, code);
-    insert_dependencies(conn, &func_name, class_id, &dependencies).expect("Failed to insert dependencies");
+    insert_dependencies(conn, function_id, &func_name, class_id, &dependencies).expect("Failed to insert dependencies");
 }
 
 // Helper function to process methods within a class node
@@ -0,0 +1,110 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::llm::{prompt_hash, ChatRequest, Completion, LlmProvider, Usage};
+
+// Fixtures are stored as '<fixtures_dir>/<agent>/<prompt hash>.json'.
+// A '<fixtures_dir>/<agent>/default.json' answers any prompt of that agent without an exact match.
+fn fixture_path(dir: &Path, agent: &str, hash: &str) -> PathBuf {
+    dir.join(agent).join(format!("{}.json", hash))
+}
+
+// Serves canned responses from the fixtures directory, never touches the network
+pub struct ReplayProvider {
+    dir: PathBuf,
+}
+
+impl ReplayProvider {
+    pub fn new(dir: PathBuf) -> Self {
+        ReplayProvider { dir }
+    }
+}
+
+impl LlmProvider for ReplayProvider {
+    fn name(&self) -> &str {
+        "replay"
+    }
+
+    fn complete(&self, request: &ChatRequest) -> Result<Completion, Box<dyn Error>> {
+        let hash = prompt_hash(request.messages);
+        let exact = fixture_path(&self.dir, request.agent, &hash);
+        let fallback = fixture_path(&self.dir, request.agent, "default");
+
+        let path = if exact.exists() {
+            exact
+        } else if fallback.exists() {
+            fallback
+        } else {
+            return Err(format!(
+                "No fixture for agent '{}' (prompt hash {}) in {}",
+                request.agent, hash, self.dir.display()
+            ).into());
+        };
+
+        let fixture: Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        let content = fixture["response"]
+            .as_str()
+            .ok_or_else(|| format!("Fixture {} has no 'response' string", path.display()))?;
+        // Replayed calls report the usage that was recorded, if any
+        let usage = Usage {
+            prompt_tokens: fixture["usage"]["prompt_tokens"].as_u64().unwrap_or(0),
+            completion_tokens: fixture["usage"]["completion_tokens"].as_u64().unwrap_or(0),
+        };
+        Ok(Completion { content: content.to_string(), usage })
+    }
+}
+
+// Forwards to a live provider and saves every response as a fixture for later replay
+pub struct RecordingProvider {
+    inner: Box<dyn LlmProvider>,
+    dir: PathBuf,
+}
+
+impl RecordingProvider {
+    pub fn new(inner: Box<dyn LlmProvider>, dir: PathBuf) -> Self {
+        RecordingProvider { inner, dir }
+    }
+}
+
+impl LlmProvider for RecordingProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn complete(&self, request: &ChatRequest) -> Result<Completion, Box<dyn Error>> {
+        let completion = self.inner.complete(request)?;
+        self.record(request, &completion)?;
+        Ok(completion)
+    }
+
+    fn stream(&self, request: &ChatRequest, on_token: &mut dyn FnMut(&str)) -> Result<Completion, Box<dyn Error>> {
+        let completion = self.inner.stream(request, on_token)?;
+        self.record(request, &completion)?;
+        Ok(completion)
+    }
+}
+
+impl RecordingProvider {
+    fn record(&self, request: &ChatRequest, completion: &Completion) -> Result<(), Box<dyn Error>> {
+        let path = fixture_path(&self.dir, request.agent, &prompt_hash(request.messages));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let fixture = json!({
+            "agent": request.agent,
+            "model": request.model,
+            "messages": request.messages,
+            "response": completion.content,
+            "usage": {
+                "prompt_tokens": completion.usage.prompt_tokens,
+                "completion_tokens": completion.usage.completion_tokens,
+            },
+        });
+        fs::write(&path, serde_json::to_string_pretty(&fixture)?)?;
+        println!("Recorded response for agent '{}' to {}", request.agent, path.display());
+        Ok(())
+    }
+}
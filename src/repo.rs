@@ -1,60 +1,89 @@
 use std::collections::{HashMap, HashSet};
+use std::error::Error;
 use std::fs::{self, OpenOptions};
-use std::io::{self, BufRead, Write, Read};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
 
-use reqwest::StatusCode;
+use git2::build::{CheckoutBuilder, RepoBuilder};
 use git2::Repository;
 use rusqlite::Connection;
 
+use crate::build::{active_dockerfile, build_with_fixes};
+use crate::config::SAFETY_POLICY;
+use crate::engine::ContainerEngine;
+use crate::lifecycle::{format_size, record_install, teardown, Resources};
+use crate::review::{confirm, print_commands, print_script, review_commands, script_commands, ReviewedCommand};
+use crate::source::{
+    checkout_ref, complete_checkout, fetch_options, installed_keys, is_local_url, sparse_checkout, update_submodules,
+    without_path_hash, Metadata, RepoSource,
+};
+use crate::safety::{analyze_commands, analyze_compose, analyze_dockerfile, is_blocked, Finding, Policy};
+use crate::runplan::RunPlan;
 use crate::utils::run_script;
 use crate::parser::parse_repository;
-use crate::db::insert_repository;
+use crate::db::{insert_repository, rename_repository};
 use crate::models;
+use crate::RunOptions;
 
-// Check if the GitHub repository exists by sending an HTTP request
-pub fn check_github_repo(link: &str) -> Result<bool, reqwest::Error> {
-    let res = reqwest::blocking::get(link)?;
-    Ok(res.status() != StatusCode::NOT_FOUND)
-}
-
-// Clones the GitHub repository to the 'source' directory and manages tagging based on the persist flag
-pub fn clone_repo(link: &str, persist: bool) -> Result<(String, PathBuf), git2::Error> {
+// Clones the repository to the 'source' directory (only the latest commit unless --full-history,
+// only the files the analysis reads with --sparse), checks out --ref when given, updates submodules
+// with --submodules and manages tagging based on the persist flag
+pub fn clone_repo(url: &str, repo_name: &str, options: &RunOptions) -> Result<PathBuf, git2::Error> {
     let base_path = Path::new("source");
     if !base_path.exists() {
         fs::create_dir(base_path).expect("Failed to create 'source' folder");
     }
-
-    // Extract repository's name from the provided link
-    let repo_name = link.trim_end_matches('/').split('/').last().unwrap().to_string();
-    let local_path = base_path.join(&repo_name);
-
-    // Load tags once and pass it to add_tag/remove_tag functions
-    let mut tags = load_tags();
+    let local_path = base_path.join(repo_name);
 
     // Clone if the repository does not exist locally
     if !local_path.exists() {
         println!("Cloning repository into: {:?}", local_path.display());
-        Repository::clone(link, &local_path)?;
+        let mut fetch = fetch_options();
+        if !options.full_history && !is_local_url(url) {
+            fetch.depth(1);
+        }
+        let mut checkout = CheckoutBuilder::new();
+        if options.sparse {
+            // Checked out below, once the files are known
+            checkout.dry_run();
+        }
+        let repo = RepoBuilder::new().fetch_options(fetch).with_checkout(checkout).clone(url, &local_path)?;
+        if options.sparse {
+            sparse_checkout(&repo)?;
+        }
         println!("Repository successfully cloned.");
     } else {
         println!("Repository '{}' already exists; skipping clone.", repo_name);
     }
+    let repo = Repository::open(&local_path)?;
+    if let Some(git_ref) = &options.git_ref {
+        let commit = checkout_ref(&repo, git_ref)?;
+        println!("Checked out '{}' at commit {}.", git_ref, commit);
+    }
+    if options.submodules {
+        update_submodules(&repo)?;
+    }
+
+    set_persist_tag(repo_name, options.persist);
+    Ok(local_path)
+}
+
+// Tag the repository when --persist is set so its files are kept, untag it otherwise
+pub fn set_persist_tag(repo_name: &str, persist: bool) {
+    // Load tags once and pass it to add_tag/remove_tag functions
+    let mut tags = load_tags();
 
     // Update tags based on the persist flag
     if persist {
         println!("Persist flag is set, adding tag for '{}'", repo_name);
-        add_tag(&repo_name, &mut tags);
+        add_tag(repo_name, &mut tags);
     } else {
         println!("Persist flag is not set, removing tag for '{}'", repo_name);
-        remove_tag(&repo_name, &mut tags);
+        remove_tag(repo_name, &mut tags);
     }
 
     // Save tags to the file after modifications
     save_tags(&tags);
-
-    // Return `repo_name` and `local_path` along with `Ok`
-    Ok((repo_name, local_path))
 }
 
 // Adds a repository name to the tags HashSet
@@ -84,7 +113,7 @@ fn load_tags() -> HashSet<String> {
     let file = fs::File::open(path).expect("Failed to open tags.txt.");
     let reader = io::BufReader::new(file);
 
-    reader.lines().filter_map(|line| line.ok()).collect()
+    reader.lines().map_while(Result::ok).collect()
 }
 
 // Saves the current tags HashSet to tags.txt, overwriting any existing contents
@@ -101,56 +130,223 @@ fn save_tags(tags: &HashSet<String>) {
     }
 }
 
-// Cleans up 'scripts/{repo_name}' and 'source/{repo_name} directory if repo_name is not tagged
+// Tear down the containers, images, volumes and networks the repository's installs created
+fn remove_resources(repo_name: &str, scripts_path: &Path, keep_images: bool) {
+    match teardown(scripts_path, keep_images) {
+        Ok(0) => {}
+        Ok(reclaimed) => println!("Removed the container resources of '{}', reclaimed {}.", repo_name, format_size(reclaimed)),
+        Err(e) => eprintln!("Failed to remove the container resources of '{}': {}", repo_name, e),
+    }
+}
+
+// Remove 'base/<key>' and the owner and host folders it leaves empty
+fn remove_repo_dir(base: &Path, key: &str) -> io::Result<()> {
+    fs::remove_dir_all(base.join(key))?;
+    for parent in Path::new(key).ancestors().skip(1).filter(|parent| !parent.as_os_str().is_empty()) {
+        // Stops at the first folder still holding other repositories
+        if fs::remove_dir(base.join(parent)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Cleans up 'scripts/{key}' and 'source/{key}' directory if the repository is not tagged,
+// together with the containers, images and volumes installing them created
 pub fn cleanup_repos() {
     let tags = load_tags();
     
     // Clean up the 'scripts' folder
     let scripts_base_path = Path::new("scripts");
-    if scripts_base_path.exists() {
-        for entry in fs::read_dir(scripts_base_path).expect("Failed to read 'scripts' directory") {
-            if let Ok(entry) = entry {
-                if let Ok(repo_name) = entry.file_name().into_string() {
-                    if !tags.contains(&repo_name) {
-                        println!("Removing scripts folder for repository: {}", repo_name);
-                        fs::remove_dir_all(entry.path()).expect("Failed to remove scripts folder.");
-                    }
-                } else {
-                    eprintln!("Warning: Skipping non-UTF-8 filename in 'scripts' directory.");
-                }
-            }
+    for key in installed_keys(scripts_base_path) {
+        if !tags.contains(&key) {
+            remove_resources(&key, &scripts_base_path.join(&key), false);
+            println!("Removing scripts folder for repository: {}", key);
+            remove_repo_dir(scripts_base_path, &key).expect("Failed to remove scripts folder.");
         }
     }
 
     // Clean up the 'source' folder
     let source_base_path = Path::new("source");
-    if source_base_path.exists() {
-        for entry in fs::read_dir(source_base_path).expect("Failed to read 'source' directory") {
-            if let Ok(entry) = entry {
-                if let Ok(repo_name) = entry.file_name().into_string() {
-                    if !tags.contains(&repo_name) {
-                        println!("Removing source folder for repository: {}", repo_name);
-                        fs::remove_dir_all(entry.path()).expect("Failed to remove source folder.");
-                    }
-                } else {
-                    eprintln!("Warning: Skipping non-UTF-8 filename in 'source' directory.");
+    for key in installed_keys(source_base_path) {
+        if !tags.contains(&key) {
+            println!("Removing source folder for repository: {}", key);
+            remove_repo_dir(source_base_path, &key).expect("Failed to remove source folder.");
+        }
+    }
+}
+
+// Keys of every repository on the machine, cloned or analyzed in place
+pub fn installed_repos() -> Vec<String> {
+    let mut keys = installed_keys(Path::new("source"));
+    keys.extend(installed_keys(Path::new("scripts")));
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+// The repositories of `installed` that `arg` names: a key ("github.com/owner/app"), the link it was
+// run with, "owner/app" or just "app"
+pub fn matching_repos(arg: &str, installed: &[String]) -> Vec<String> {
+    let arg = arg.trim_end_matches('/');
+    if installed.iter().any(|key| key == arg) {
+        return vec![arg.to_string()];
+    }
+    if let Ok(source) = RepoSource::parse(arg) {
+        return installed.iter().filter(|key| *key == source.key()).cloned().collect();
+    }
+    // Local directories are named without the hash of their path
+    let suffix = format!("/{}", arg);
+    installed.iter().filter(|key| key.ends_with(&suffix) || without_path_hash(key).ends_with(&suffix)).cloned().collect()
+}
+
+// The key of the installed repository `arg` names. When several match, the user is asked which
+// one on a terminal, and has to be more specific otherwise.
+pub fn resolve_repo(arg: &str) -> Result<String, Box<dyn Error>> {
+    let matching = matching_repos(arg, &installed_repos());
+    match matching.len() {
+        0 => return Err(format!("No repository named '{}' installed.", arg).into()),
+        1 => return Ok(matching[0].clone()),
+        _ => {}
+    }
+    if !io::stdin().is_terminal() {
+        return Err(format!("'{}' matches several repositories, use one of: {}", arg, matching.join(", ")).into());
+    }
+
+    println!("'{}' matches several repositories:", arg);
+    for (i, key) in matching.iter().enumerate() {
+        println!("  {}. {}", i + 1, key);
+    }
+    print!("Choose one: ");
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    match input.trim().parse::<usize>() {
+        Ok(n) if (1..=matching.len()).contains(&n) => Ok(matching[n - 1].clone()),
+        _ => Err("Invalid choice.".into()),
+    }
+}
+
+// Replace the paths "source/<name>" and "scripts/<name>" with "source/<key>" and "scripts/<key>"
+fn rewrite_paths(text: &str, name: &str, key: &str) -> String {
+    let mut text = text.to_string();
+    for base in ["source", "scripts"] {
+        let old = format!("{}/{}", base, name);
+        let new = format!("{}/{}", base, key);
+        let mut rewritten = String::new();
+        let mut rest = text.as_str();
+        while let Some(at) = rest.find(&old) {
+            let after = &rest[at + old.len()..];
+            // Not when it is only the start of a longer name, like "source/app2"
+            let whole = after.chars().next().is_none_or(|c| !(c.is_alphanumeric() || "-_.".contains(c)));
+            rewritten.push_str(&rest[..at]);
+            rewritten.push_str(if whole { &new } else { &old });
+            rest = after;
+        }
+        rewritten.push_str(rest);
+        text = rewritten;
+    }
+    text
+}
+
+// Where a repository kept by an older version as 'source/<name>' or 'scripts/<name>' came from:
+// the origin of its clone, or the source recorded in its metadata. `name` is a key for local
+// directories kept before their key had the hash of their path.
+fn legacy_key(name: &str) -> Option<String> {
+    let origin = Repository::open(Path::new("source").join(name)).ok().and_then(|repo| {
+        repo.find_remote("origin").ok()?.url().map(|url| url.to_string())
+    });
+    let source = origin.or_else(|| Metadata::load(&Path::new("scripts").join(name)).map(|metadata| metadata.source))?;
+    RepoSource::parse(&source).ok().map(|source| source.key().to_string())
+}
+
+// Move the repositories older versions kept as 'source/<name>' and 'scripts/<name>' to
+// '<host>/<owner>/<name>', and local directories kept as 'local/<owner>/<name>' to the key with
+// the hash of their path, renaming them in tags.txt and the database and fixing the paths saved in
+// their scripts folder
+pub fn migrate_layout() {
+    // Old scripts folders hold files, old source folders are clones; new ones only hold host folders
+    let legacy = |base: &str, is_legacy: &dyn Fn(&Path) -> bool| -> Vec<String> {
+        match fs::read_dir(base) {
+            Ok(entries) => entries
+                .flatten()
+                .filter(|entry| is_legacy(&entry.path()))
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    };
+    let mut names = legacy("source", &|path| path.join(".git").exists());
+    names.extend(legacy("scripts", &|path| {
+        fs::read_dir(path).is_ok_and(|mut entries| entries.any(|entry| entry.is_ok_and(|entry| entry.path().is_file())))
+    }));
+    // Local keys without the hash are only moved when their directory is still known
+    let mut unhashed: Vec<String> = ["source", "scripts"]
+        .iter()
+        .flat_map(|base| installed_keys(Path::new(base)))
+        .filter(|key| key.starts_with("local/") && without_path_hash(key) == key)
+        .collect();
+    unhashed.retain(|key| legacy_key(key).is_some_and(|new| new != *key));
+    names.extend(unhashed);
+    names.sort();
+    names.dedup();
+    if names.is_empty() {
+        return;
+    }
+
+    let mut tags = load_tags();
+    let conn = Path::new("autocontain.db").exists().then(|| Connection::open("autocontain.db").ok()).flatten();
+    for name in names {
+        let Some(key) = legacy_key(&name) else {
+            eprintln!("Cannot tell where '{}' was cloned from, it is left as it is.", name);
+            continue;
+        };
+        let moves: Vec<(PathBuf, PathBuf)> = ["source", "scripts"]
+            .iter()
+            .map(|base| (Path::new(base).join(&name), Path::new(base).join(&key)))
+            .filter(|(old, _)| old.exists())
+            .collect();
+        if moves.iter().any(|(_, new)| new.exists()) {
+            eprintln!("Cannot move '{}' to '{}', which already exists.", name, key);
+            continue;
+        }
+        for (old, new) in &moves {
+            let moved = new.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::rename(old, new));
+            if let Err(e) = moved {
+                eprintln!("Failed to move {} to {}: {}", old.display(), new.display(), e);
+            }
+        }
+
+        // run.sh, run_plan.json, resources.json and the generated files refer to the old folders
+        let scripts_path = Path::new("scripts").join(&key);
+        for entry in fs::read_dir(&scripts_path).into_iter().flatten().flatten() {
+            if let Ok(content) = fs::read_to_string(entry.path()) {
+                let rewritten = rewrite_paths(&content, &name, &key);
+                if rewritten != content {
+                    fs::write(entry.path(), rewritten).ok();
                 }
             }
         }
+        if tags.remove(&name) {
+            tags.insert(key.clone());
+        }
+        if let Some(conn) = &conn {
+            rename_repository(conn, &name, &key).ok();
+        }
+        println!("Moved repository '{}' to '{}'.", name, key);
     }
+    save_tags(&tags);
 }
 
-// Scans the repository directory to find Markdown and Docker-related files, and returns their content.
-// - Markdown content is concatenated and returned as a single string.
-// - Docker-related files are stored in a HashMap.
-// - Only 1 Dockerfile and compose file, each, is considered.
+// Scans the repository directory to find Markdown files, and returns their content.
+// - Markdown files are returned as (path, content) pairs so they can be ranked and budgeted.
+// - Docker-related files are found separately (see discovery.rs).
 pub fn find_and_merge_content(
     dir: &Path, // Path to the repository
     depth: usize, // How deep the program should search for markdown files.
-) -> Result<(String, usize, HashMap<String, String>), io::Error> {
-    let mut md_content = String::new();
+) -> Result<(Vec<(PathBuf, String)>, usize), io::Error> {
+    let mut md_documents = Vec::new();
     let mut md_file_count = 0;
-    let mut docker_content = HashMap::new();
 
     for entry in fs::read_dir(dir)? {
         let entry = entry?;
@@ -159,30 +355,19 @@ pub fn find_and_merge_content(
         if path.is_dir() {
             if depth > 0 {
                 // Recursively call with reduced depth if depth > 0
-                let (nested_md_content, count, _) = find_and_merge_content(&path, depth - 1)?;
-                md_content.push_str(&nested_md_content);
+                let (nested_md_documents, count) = find_and_merge_content(&path, depth - 1)?;
+                md_documents.extend(nested_md_documents);
                 md_file_count += count;
             }
-        } else {
-            let file_name = path.file_name().and_then(|f| f.to_str()).unwrap_or_default().to_string();
-
-            if file_name.ends_with(".md") {
-                // Recognize Markdown files based on depth level
-                md_file_count += 1;
-                let content = fs::read_to_string(&path)?;
-                md_content.push_str(&content);
-                md_content.push_str("\n\n");
-            } else if depth == 0 && (file_name == "Dockerfile" || file_name.ends_with(".yml") || file_name.ends_with(".yaml")) {
-                // Collect Docker-related files only at the outermost layer (depth 0)
-                let content = fs::read_to_string(&path)?;
-                if file_name == "Dockerfile" || content.contains("services") {
-                    docker_content.insert(file_name, content);
-                }
-            }
+        } else if path.extension().is_some_and(|extension| extension == "md") {
+            // Recognize Markdown files based on depth level
+            md_file_count += 1;
+            let content = fs::read_to_string(&path)?;
+            md_documents.push((path, content));
         }
     }
 
-    Ok((md_content, md_file_count, docker_content))
+    Ok((md_documents, md_file_count))
 }
 
 // View analysis.md in cli
@@ -214,10 +399,10 @@ pub fn view_basic_analysis(scripts_path: &Path) {
 // View repository tree structure in cli
 pub fn view_tree_structure(local_path: &Path) {
     println!("Displaying repository's tree structure...");
-    display_tree_structure(local_path, 0, "");
+    display_tree_structure(local_path, "");
 }
 
-fn display_tree_structure(path: &Path, level: usize, prefix: &str) {
+fn display_tree_structure(path: &Path, prefix: &str) {
     // Directories to exclude from the tree view
     let excluded_dirs = [
         "node_modules", ".github", ".git", "target", ".idea", ".vscode",
@@ -275,25 +460,178 @@ fn display_tree_structure(path: &Path, level: usize, prefix: &str) {
             println!("{}{}─ {}", prefix, if is_last { "└" } else { "├" }, dir_name);
 
             let new_prefix = format!("{}{}", prefix, if is_last { "  " } else { "│ " });
-            display_tree_structure(dir, level + 1, &new_prefix);
+            display_tree_structure(dir, &new_prefix);
         }
     } else {
         println!("Failed to read the directory: {:?}", path);
     }
 }
 
-// Execute run.sh
-pub fn install_repo(scripts_path: &Path) {
-    println!("Installing repository...");
+// Install the container: through the container engine when there is a run plan, by executing
+// run.sh when the script was written by the agent. What will run is shown first and has to be
+// confirmed unless --yes was given; --dry-run only shows it. With --fix-build the image is first
+// built (and the Dockerfile repaired by the agent on failure). Host ports of the plan that are in
+// use by then are moved to free ones.
+pub fn install_repo(local_path: &Path, scripts_path: &Path, options: &RunOptions) {
+    let engine = ContainerEngine::detected();
+    let mut plan = RunPlan::load(scripts_path);
     let script_path = scripts_path.join("run.sh");
-    match run_script(&script_path) {
-        Ok(_) => println!("Docker container installed."),
-        Err(e) => eprintln!("Error installing Docker container: {}.", e),
+    // After an update the image and containers are still those of the commit installed before
+    let commit = Metadata::load(scripts_path).and_then(|metadata| metadata.commit);
+    let installed = Resources::load(scripts_path).filter(|resources| !resources.is_empty());
+    let rebuild = commit.is_some() && installed.is_some_and(|resources| resources.commit != commit);
+    if rebuild {
+        println!("The repository changed since it was installed, the image is built again and the containers replaced.");
+    }
+    // Fixing a failed build needs the LLM
+    let fix_build_attempts = if options.no_llm { 0 } else { options.fix_build_attempts };
+
+    let commands = match &plan {
+        Some(plan) => {
+            let commands = review_commands(&plan.commands(engine));
+            println!("Commands to run with {}:", engine.describe());
+            print_commands(&commands);
+            commands
+        }
+        None => {
+            let script = match fs::read_to_string(&script_path) {
+                Ok(script) => script,
+                Err(e) => {
+                    eprintln!("Failed to read {}: {}.", script_path.display(), e);
+                    return;
+                }
+            };
+            let commands = script_commands(&script);
+            if options.dry_run {
+                println!("Commands in {}:", script_path.display());
+                print_commands(&commands);
+            } else {
+                println!("{}:", script_path.display());
+                print_script(&script, &commands);
+            }
+            commands
+        }
+    };
+    if fix_build_attempts > 0 {
+        println!("The image is built first, up to {} times while the agent fixes the Dockerfile.", fix_build_attempts);
+    }
+
+    // The script and the Dockerfile may come from an LLM that read untrusted content
+    let findings = match safety_findings(local_path, scripts_path, plan.as_ref(), &commands, engine) {
+        Ok(findings) => findings,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    if !findings.is_empty() {
+        println!("Safety check:");
+        for finding in &findings {
+            println!("  {}", finding);
+        }
+    }
+    if is_blocked(&findings) {
+        eprintln!("Refusing to install: the safety policy blocks these commands (see {}).", SAFETY_POLICY.display());
+        return;
+    }
+    if options.dry_run {
+        println!("Dry run, nothing was executed.");
+        return;
+    }
+    let dangerous = commands.iter().any(|command| !command.warnings.is_empty());
+    if options.yes {
+        if dangerous {
+            println!("Running the dangerous commands because of --yes.");
+        }
+    } else if !confirm(if dangerous { "Run these commands despite the warnings?" } else { "Run these commands?" }) {
+        println!("Installation cancelled.");
+        return;
     }
+
+    // A sparse clone has too little to build the image
+    match complete_checkout(local_path) {
+        Ok(true) => println!("Checked out the rest of the repository to build it."),
+        Ok(false) => {}
+        Err(e) => {
+            eprintln!("Failed to check out the rest of the repository: {}.", e.message());
+            return;
+        }
+    }
+
+    println!("Installing repository...");
+    if fix_build_attempts > 0 {
+        match build_with_fixes(local_path, scripts_path, engine, fix_build_attempts) {
+            Ok(true) => {}
+            Ok(false) => {
+                eprintln!("Build still failing after {} attempts, see {}.", fix_build_attempts, scripts_path.join("build_attempts").display());
+                return;
+            }
+            Err(e) => {
+                eprintln!("Error building Docker image: {}.", e);
+                return;
+            }
+        }
+    }
+    if let Some(plan) = plan.as_mut() {
+        if plan.remap_busy_ports(engine) {
+            let saved = plan.save(scripts_path)
+                .and_then(|_| fs::write(&script_path, plan.render_script(engine)).map_err(|e| e.into()));
+            if let Err(e) = saved {
+                eprintln!("Failed to save the new host ports: {}.", e);
+            }
+        }
+    }
+    let result = match &plan {
+        Some(plan) => plan.install(engine, rebuild),
+        None => run_script(&script_path).map_err(|e| e.into()),
+    };
+    // Recorded even when installing failed half-way, so the leftovers can be removed
+    match record_install(scripts_path, engine, plan.as_ref()) {
+        Ok(mut resources) if result.is_ok() => {
+            resources.commit = commit;
+            if let Err(e) = resources.save(scripts_path) {
+                eprintln!("Failed to record the installed commit: {}.", e);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("Failed to record the created resources: {}.", e),
+    }
+    match result {
+        Ok(_) => println!("Container installed."),
+        Err(e) => eprintln!("Error installing container: {}.", e),
+    }
+}
+
+// Run the safety analyzer over what installing would run: the plan's commands or run.sh, and the
+// Dockerfile and compose file they use
+fn safety_findings(
+    local_path: &Path,
+    scripts_path: &Path,
+    plan: Option<&RunPlan>,
+    commands: &[ReviewedCommand],
+    engine: &ContainerEngine,
+) -> Result<Vec<Finding>, Box<dyn Error>> {
+    let policy = Policy::from_config()?;
+    let source = if plan.is_some() { "command" } else { "run.sh" };
+    let mut findings = analyze_commands(source, commands, engine, &policy);
+
+    let dockerfile = match plan {
+        Some(plan) => plan.dockerfile.clone(),
+        None => Some(active_dockerfile(local_path, scripts_path)),
+    };
+    let file_name = |path: &Path| path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    if let Some(path) = dockerfile.filter(|path| path.is_file()) {
+        findings.extend(analyze_dockerfile(&file_name(&path), &fs::read_to_string(&path)?, &policy));
+    }
+    if let Some(path) = plan.and_then(|plan| plan.compose.as_ref()) {
+        findings.extend(analyze_compose(&file_name(path), &fs::read_to_string(path)?, &policy));
+    }
+    Ok(findings)
 }
 
-// Remove the repository from the machine
-pub fn remove_repo(repo_name: &str) {
+// Remove the repository from the machine, with its containers, volumes, networks and (unless
+// `keep_images`) images
+pub fn remove_repo(repo_name: &str, keep_images: bool) {
     println!("Removing repository '{}'", repo_name);
 
     // Check if repo_name is in tags.txt
@@ -301,7 +639,7 @@ pub fn remove_repo(repo_name: &str) {
     let repo_in_tags = if let Ok(file) = fs::File::open(&tags_path) {
         io::BufReader::new(file)
             .lines()
-            .filter_map(Result::ok)
+            .map_while(Result::ok)
             .any(|line| line == repo_name)
     } else {
         eprintln!("Failed to open tags.txt");
@@ -312,11 +650,16 @@ pub fn remove_repo(repo_name: &str) {
     let scripts_dir = PathBuf::from("scripts").join(repo_name);
 
     if repo_in_tags {
-        // If repo_name is in tags.txt, try to remove the directories
-        if let Err(e) = fs::remove_dir_all(&source_dir) {
-            eprintln!("Failed to remove {} in source directory: {}", repo_name, e);
+        // Resources are recorded in the scripts folder, so they go first
+        remove_resources(repo_name, &scripts_dir, keep_images);
+        // If repo_name is in tags.txt, try to remove the directories. Local directories analyzed
+        // in place have no copy in 'source' and are never touched.
+        if source_dir.exists() {
+            if let Err(e) = remove_repo_dir(Path::new("source"), repo_name) {
+                eprintln!("Failed to remove {} in source directory: {}", repo_name, e);
+            }
         }
-        if let Err(e) = fs::remove_dir_all(&scripts_dir) {
+        if let Err(e) = remove_repo_dir(Path::new("scripts"), repo_name) {
             eprintln!("Failed to remove {} in scripts directory: {}", repo_name, e);
         }
         println!("Repository '{}' removed successfully.", repo_name);
@@ -325,7 +668,7 @@ pub fn remove_repo(repo_name: &str) {
         if let Ok(file) = fs::File::open(&tags_path) {
             let lines: Vec<String> = io::BufReader::new(file)
                 .lines()
-                .filter_map(Result::ok)
+                .map_while(Result::ok)
                 .filter(|line| line != repo_name) // Exclude the repo_name
                 .collect();
 
@@ -345,42 +688,29 @@ pub fn remove_repo(repo_name: &str) {
 
 // Get all repositories installed permanantly
 pub fn get_all_repos() {
-    let source_dir = PathBuf::from("source");
-
-    match fs::read_dir(&source_dir) {
-        Ok(entries) => {
-            let mut found_repo = false;
-
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if path.is_dir() {
-                        if let Some(repo_name) = path.file_name().and_then(|name| name.to_str()) {
-                            println!("- {}", repo_name);
-                            found_repo = true;
-                        }
-                    }
-                }
-            }
-            if !found_repo {
-                println!("No repositories installed.");
-            }
-        }
-        Err(e) => eprintln!("Failed to list repositories: {}", e),
+    let keys = installed_repos();
+    if keys.is_empty() {
+        println!("No repositories installed.");
+    }
+    for key in keys {
+        println!("- {}", key);
     }
 }
 
-// Use tree-sitter to parse the code of the repository to the sqlite database
-pub fn parse_repo(repo_name: &str, repo_path: &str, conn: &Connection) {
+// Use tree-sitter to parse the code of the repository to the sqlite database, returns the repository's id
+pub fn parse_repo(repo_name: &str, repo_path: &str, metadata: &Metadata, conn: &Connection) -> i32 {
     // Create a Repository
     let repo = models::Repository {
         id: None,
         name: repo_name.to_string(),
-        description: None
+        description: None,
+        git_ref: metadata.git_ref.clone(),
+        commit: metadata.commit.clone(),
     };
     // Insert the repository into the database and get the repo_id assigned
-    let repo_id = insert_repository(&conn, &repo).expect("Failed to insert repository.");
+    let repo_id = insert_repository(conn, &repo).expect("Failed to insert repository.");
     // Start parsing the repository
-    parse_repository(&repo_path, &conn, repo_id);
+    parse_repository(repo_path, conn, repo_id);
     println!("Parsing completed successfully for repository {}.", repo_name);
+    repo_id
 }
\ No newline at end of file
@@ -0,0 +1,204 @@
+use std::io::{self, IsTerminal, Write};
+
+// A command of run.sh (continuation lines joined) and why it is dangerous, if it is
+pub struct ReviewedCommand {
+    pub line: usize, // First line of the command in the script, 1-based
+    pub last_line: usize, // Last line, after '\' continuations
+    pub text: String,
+    pub warnings: Vec<&'static str>,
+}
+
+// Sockets that give a container control over the host's container engine
+const ENGINE_SOCKETS: [&str; 3] = ["docker.sock", "podman.sock", "containerd.sock"];
+const SHELLS: [&str; 4] = ["sh", "bash", "zsh", "dash"];
+
+// Why a command is dangerous for the host, empty when nothing was found
+pub fn dangers(command: &str) -> Vec<&'static str> {
+    let words: Vec<&str> = command.split_whitespace().map(|word| word.trim_matches(['\'', '"'])).collect();
+    let mut warnings = Vec::new();
+
+    let rm_recursive = words.iter().enumerate().any(|(i, word)| is_command(word, "rm") && rm_is_recursive_and_forced(&words[i + 1..]));
+    if rm_recursive {
+        warnings.push("rm -rf deletes files without asking");
+    }
+
+    // curl ... | sh: a downloaded script piped into a shell
+    let segments: Vec<&str> = command.split('|').collect();
+    let downloads = segments.iter().position(|segment| {
+        segment.split_whitespace().any(|word| word == "curl" || word == "wget")
+    });
+    let piped_to_shell = downloads.is_some_and(|first| {
+        segments[first + 1..].iter().any(|segment| {
+            segment.split_whitespace().find(|word| *word != "sudo").is_some_and(|word| SHELLS.contains(&word))
+        })
+    });
+    // bash <(curl ...), sh -c "$(curl ...)": the same without a pipe
+    let shell_runs_download = words.iter().enumerate().any(|(i, word)| {
+        let argument = if words.get(i + 1) == Some(&"-c") { i + 2 } else { i + 1 };
+        SHELLS.iter().any(|shell| is_command(word, shell)) && downloads_in(&words, argument)
+    });
+    if piped_to_shell || shell_runs_download {
+        warnings.push("runs a script downloaded from the internet");
+    }
+
+    if words.iter().any(|word| *word == "--privileged" || word.starts_with("--privileged=")) {
+        warnings.push("--privileged gives the container full access to the host");
+    }
+
+    let host_network = words.iter().any(|word| matches!(*word, "--network=host" | "--net=host"))
+        || words.windows(2).any(|pair| matches!(pair[0], "--network" | "--net") && pair[1] == "host");
+    if host_network {
+        warnings.push("shares the host network with the container");
+    }
+
+    // -v /:/host, --volume=/:/host, --mount type=bind,source=/,...
+    let mounts_root = words.iter().any(|word| {
+        let value = word.split_once('=').filter(|(flag, _)| flag.starts_with("--")).map_or(*word, |(_, value)| value);
+        value.starts_with("/:") || value.split(',').any(|part| part == "source=/" || part == "src=/")
+    });
+    if mounts_root {
+        warnings.push("mounts the host's root filesystem");
+    }
+
+    if ENGINE_SOCKETS.iter().any(|socket| command.contains(socket)) {
+        warnings.push("gives the container control of the container engine");
+    }
+    warnings
+}
+
+// `rm`, `/bin/rm` and the like
+fn is_command(word: &str, name: &str) -> bool {
+    word.rsplit('/').next() == Some(name)
+}
+
+// Whether the arguments of an rm (up to the end of the command) ask for a forced recursive delete.
+// Flags may be split (-r -f), combined in any order and case (-Rf, -fr) or spelled out (--recursive --force).
+fn rm_is_recursive_and_forced(arguments: &[&str]) -> bool {
+    let (mut recursive, mut force) = (false, false);
+    for argument in arguments {
+        if matches!(*argument, ";" | "&&" | "||" | "|" | "&" | "--") {
+            break;
+        }
+        match argument.strip_prefix("--") {
+            Some("recursive") => recursive = true,
+            Some("force") => force = true,
+            Some(_) => {}
+            None if argument.starts_with('-') => {
+                let flags = argument.to_lowercase();
+                recursive |= flags.contains('r');
+                force |= flags.contains('f');
+            }
+            None => {}
+        }
+        if argument.ends_with(';') {
+            break;
+        }
+    }
+    recursive && force
+}
+
+// Whether the word at `index` substitutes the output of curl or wget: `<(curl`, `$(curl`, `` `curl ``,
+// or one of those openers alone followed by the download command
+fn downloads_in(words: &[&str], index: usize) -> bool {
+    let Some(word) = words.get(index) else {
+        return false;
+    };
+    ["<(", "$(", "`"].iter().any(|opener| match word.strip_prefix(opener) {
+        Some("") => words.get(index + 1).is_some_and(|next| matches!(*next, "curl" | "wget")),
+        Some(command) => matches!(command, "curl" | "wget"),
+        None => false,
+    })
+}
+
+// The commands of a shell script: comments and blank lines skipped, '\' continuations joined
+pub fn script_commands(script: &str) -> Vec<ReviewedCommand> {
+    let mut commands = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (i, line) in script.lines().enumerate() {
+        let trimmed = line.trim();
+        if current.is_none() && (trimmed.is_empty() || trimmed.starts_with('#')) {
+            continue;
+        }
+        let (start, text) = current.get_or_insert_with(|| (i + 1, String::new()));
+        match trimmed.strip_suffix('\\') {
+            Some(part) => {
+                text.push_str(part.trim_end());
+                text.push(' ');
+            }
+            None => {
+                text.push_str(trimmed);
+                commands.push(reviewed(*start, i + 1, text.trim_end()));
+                current = None;
+            }
+        }
+    }
+    // A script ending in '\'
+    if let Some((start, text)) = current {
+        commands.push(reviewed(start, script.lines().count(), text.trim_end()));
+    }
+    commands
+}
+
+fn reviewed(line: usize, last_line: usize, text: &str) -> ReviewedCommand {
+    ReviewedCommand { line, last_line, text: text.to_string(), warnings: dangers(text) }
+}
+
+// Review commands that do not come from a script, numbered in order
+pub fn review_commands(commands: &[String]) -> Vec<ReviewedCommand> {
+    commands.iter().enumerate().map(|(i, command)| reviewed(i + 1, i + 1, command)).collect()
+}
+
+// Red when printing to a terminal
+fn highlight(text: &str) -> String {
+    if io::stdout().is_terminal() {
+        format!("\x1b[1;31m{}\x1b[0m", text)
+    } else {
+        text.to_string()
+    }
+}
+
+// Print the script with line numbers, dangerous commands highlighted and explained below it
+pub fn print_script(script: &str, commands: &[ReviewedCommand]) {
+    let flagged: Vec<&ReviewedCommand> = commands.iter().filter(|command| !command.warnings.is_empty()).collect();
+    for (i, line) in script.lines().enumerate() {
+        let number = i + 1;
+        let is_flagged = flagged.iter().any(|command| (command.line..=command.last_line).contains(&number));
+        let text = format!("{:>4} | {}", number, line);
+        println!("{}", if is_flagged { highlight(&text) } else { text });
+    }
+    print_warnings(&flagged);
+}
+
+// Print resolved commands, one per line, dangerous ones highlighted
+pub fn print_commands(commands: &[ReviewedCommand]) {
+    for command in commands {
+        let text = format!("  $ {}", command.text);
+        println!("{}", if command.warnings.is_empty() { text } else { highlight(&text) });
+    }
+    print_warnings(&commands.iter().filter(|command| !command.warnings.is_empty()).collect::<Vec<_>>());
+}
+
+fn print_warnings(flagged: &[&ReviewedCommand]) {
+    if flagged.is_empty() {
+        return;
+    }
+    println!("{}", highlight("Dangerous commands:"));
+    for command in flagged {
+        for warning in &command.warnings {
+            println!("  {}: {}", command.line, warning);
+        }
+    }
+}
+
+// Ask a yes/no question, no unless the user answers y or yes
+pub fn confirm(question: &str) -> bool {
+    print!("{} [y/N]: ", question);
+    if io::stdout().flush().is_err() {
+        return false;
+    }
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input).is_err() {
+        return false;
+    }
+    matches!(input.trim().to_lowercase().as_str(), "y" | "yes")
+}
@@ -0,0 +1,420 @@
+use std::error::Error;
+use std::fs;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::RepoAnalysis;
+use crate::build::{image_tag, project_name, repo_labels};
+use crate::dockerfile;
+use crate::engine::{ContainerEngine, ContainerState, ResourceKind};
+use crate::services::ENV_EXAMPLES;
+
+// How far above a wanted host port to look for a free one
+const PORT_SEARCH_RANGE: u16 = 100;
+
+// Everything run.sh does, saved as run_plan.json in the scripts folder
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunPlan {
+    pub image: String,
+    pub container: String, // Container name, or compose project name
+    pub dockerfile: Option<PathBuf>,
+    pub build_context: PathBuf,
+    pub compose: Option<PathBuf>,
+    pub ports: Vec<PortMapping>,
+    pub env_file: Option<PathBuf>,
+    pub volumes: Vec<VolumeMount>,
+    #[serde(default)]
+    pub labels: Vec<String>, // Put on the image, the container and the volumes
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortMapping {
+    pub host: u16,
+    pub container: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeMount {
+    pub name: String,
+    pub target: String,
+}
+
+// First host port from `wanted` up that nothing listens on and that is not used by another mapping.
+// Privileged container ports are moved to 8000 + port (80 -> 8080) so no root is needed on the host.
+fn free_host_port(wanted: u16, taken: &[u16]) -> u16 {
+    let start = if wanted < 1024 { wanted + 8000 } else { wanted };
+    (start..=start.saturating_add(PORT_SEARCH_RANGE))
+        .find(|port| !taken.contains(port) && port_is_free(*port))
+        .unwrap_or(start)
+}
+
+fn port_is_free(port: u16) -> bool {
+    TcpListener::bind(("0.0.0.0", port)).is_ok()
+}
+
+// Volume name for a VOLUME path, e.g. "/var/lib/data" -> "<container>-var-lib-data"
+fn volume_name(container: &str, target: &str) -> String {
+    let path: String = target.trim_matches('/')
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    format!("{}-{}", container, if path.is_empty() { "data" } else { &path })
+}
+
+// VOLUME arguments in JSON ("["/a", "/b"]") or plain ("/a /b") form
+fn volume_targets(args: &str) -> Vec<String> {
+    if args.starts_with('[') {
+        serde_json::from_str(args).unwrap_or_default()
+    } else {
+        args.split_whitespace().map(|target| target.to_string()).collect()
+    }
+}
+
+// NAME=value from a line of a shell-style env file, as the engines' --env-file expects it: without
+// `export ` and the quotes around the value. None for blank lines, comments and anything else.
+fn env_assignment(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return None;
+    }
+    let (name, value) = line.strip_prefix("export ").unwrap_or(line).split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    let value = value.trim();
+    let unquoted = ['"', '\'']
+        .iter()
+        .find_map(|&quote| value.strip_prefix(quote)?.strip_suffix(quote))
+        .unwrap_or(value);
+    Some((name.to_string(), unquoted.to_string()))
+}
+
+// app.env from the repository's example env file and the variables found by the analysis.
+// Variables without a known value are left commented out for the user to fill in.
+fn write_env_file(local_path: &Path, scripts_path: &Path, analysis: &RepoAnalysis) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    let mut content = String::new();
+    let mut names = Vec::new();
+    if let Some(example) = ENV_EXAMPLES.iter().find_map(|file| fs::read_to_string(local_path.join(file)).ok()) {
+        for (name, value) in example.lines().filter_map(env_assignment) {
+            content.push_str(&format!("{}={}\n", name, value));
+            names.push(name);
+        }
+    }
+    for var in analysis.env_vars.iter().filter(|var| !names.contains(&var.name)) {
+        if let Some(description) = &var.description {
+            content.push_str(&format!("# {}\n", description));
+        }
+        match &var.default {
+            Some(default) => content.push_str(&format!("{}={}\n", var.name, default)),
+            None => content.push_str(&format!("# {}=\n", var.name)),
+        }
+    }
+    if content.is_empty() {
+        return Ok(None);
+    }
+    let path = scripts_path.join("app.env");
+    fs::write(&path, content)?;
+    Ok(Some(path))
+}
+
+// Plan how to run the repository: with the compose file if there is one, otherwise by building
+// the Dockerfile and running the image with its EXPOSE ports and VOLUMEs
+pub fn build_run_plan(
+    local_path: &Path,
+    scripts_path: &Path,
+    dockerfile: Option<(&Path, &str)>, // Path and content
+    compose: Option<&Path>,
+    analysis: &RepoAnalysis,
+) -> Result<RunPlan, Box<dyn Error>> {
+    let image = image_tag(scripts_path);
+    let mut plan = RunPlan {
+        container: if compose.is_some() { project_name(scripts_path) } else { image.clone() },
+        image,
+        build_context: local_path.to_path_buf(),
+        compose: compose.map(|path| path.to_path_buf()),
+        ..RunPlan::default()
+    };
+    if compose.is_some() {
+        return Ok(plan);
+    }
+    plan.labels = repo_labels(scripts_path);
+
+    let Some((dockerfile_path, content)) = dockerfile else {
+        return Err("No Dockerfile or compose file to run.".into());
+    };
+    let instructions = dockerfile::parse(content);
+    if !instructions.iter().any(|instruction| instruction.keyword == "FROM") {
+        return Err(format!("{} has no FROM instruction.", dockerfile_path.display()).into());
+    }
+    plan.dockerfile = Some(dockerfile_path.to_path_buf());
+
+    // EXPOSE ports of the final stage, plus the ports the analysis found
+    let final_stage = instructions.iter().rposition(|instruction| instruction.keyword == "FROM").unwrap_or(0);
+    let mut ports: Vec<u16> = instructions[final_stage..].iter()
+        .filter(|instruction| instruction.keyword == "EXPOSE")
+        .flat_map(|instruction| instruction.args.split_whitespace().map(|port| port.split('/').next().unwrap_or(port).to_string()).collect::<Vec<_>>())
+        .filter_map(|port| port.parse().ok())
+        .collect();
+    ports.extend(analysis.exposed_ports.iter().copied());
+    let mut taken = Vec::new();
+    for port in ports {
+        if plan.ports.iter().any(|mapping| mapping.container == port) {
+            continue;
+        }
+        let host = free_host_port(port, &taken);
+        taken.push(host);
+        plan.ports.push(PortMapping { host, container: port });
+    }
+
+    for instruction in instructions[final_stage..].iter().filter(|instruction| instruction.keyword == "VOLUME") {
+        for target in volume_targets(&instruction.args) {
+            plan.volumes.push(VolumeMount { name: volume_name(&plan.container, &target), target });
+        }
+    }
+
+    plan.env_file = write_env_file(local_path, scripts_path, analysis)?;
+    Ok(plan)
+}
+
+// Single-quote a value for sh
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+// A value as it would be typed in a shell, quoted only when needed
+fn shell_word(value: &str) -> String {
+    let plain = !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    if plain { value.to_string() } else { shell_quote(value) }
+}
+
+impl RunPlan {
+    pub fn load(scripts_path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(scripts_path.join("run_plan.json")).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self, scripts_path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(scripts_path.join("run_plan.json"), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    // The host ports were free when the plan was made, but something may listen on them by now.
+    // Before a new container is created, the busy ones are moved to the next free port; returns
+    // whether any mapping changed. An existing container of the plan holds its own ports.
+    pub fn remap_busy_ports(&mut self, engine: &ContainerEngine) -> bool {
+        if self.compose.is_some()
+            || self.ports.iter().all(|mapping| port_is_free(mapping.host))
+            || engine.inspect(&self.container).is_some()
+        {
+            return false;
+        }
+        let mut taken = Vec::new();
+        let mut changed = false;
+        for mapping in &mut self.ports {
+            if !port_is_free(mapping.host) {
+                let host = free_host_port(mapping.host, &taken);
+                if host != mapping.host {
+                    println!("Host port {} is in use, publishing port {} on {} instead.", mapping.host, mapping.container, host);
+                    mapping.host = host;
+                    changed = true;
+                }
+            }
+            taken.push(mapping.host);
+        }
+        changed
+    }
+
+    // Flags passed to `run` for the labels, ports, env file and volumes
+    fn run_options(&self) -> Vec<String> {
+        let mut options = Vec::new();
+        for label in &self.labels {
+            options.extend(["--label".to_string(), label.clone()]);
+        }
+        for mapping in &self.ports {
+            options.extend(["-p".to_string(), format!("{}:{}", mapping.host, mapping.container)]);
+        }
+        if let Some(env_file) = &self.env_file {
+            options.extend(["--env-file".to_string(), env_file.display().to_string()]);
+        }
+        for volume in &self.volumes {
+            options.extend(["-v".to_string(), format!("{}:{}", volume.name, volume.target)]);
+        }
+        options
+    }
+
+    // The commands `install` runs when nothing exists yet, for --dry-run and review
+    pub fn commands(&self, engine: &ContainerEngine) -> Vec<String> {
+        let join = |words: Vec<String>| words.iter().map(|word| shell_word(word)).collect::<Vec<_>>().join(" ");
+        if let Some(compose) = &self.compose {
+            let mut words = engine.compose.clone();
+            words.extend(["-f".to_string(), compose.display().to_string(), "-p".to_string(), self.container.clone()]);
+            words.extend(["up", "-d", "--build"].map(String::from));
+            return vec![join(words)];
+        }
+        let labels: Vec<String> = self.labels.iter().flat_map(|label| ["--label".to_string(), label.clone()]).collect();
+        let mut commands = Vec::new();
+        if let Some(dockerfile) = &self.dockerfile {
+            let mut build = vec![
+                engine.binary.clone(),
+                "build".to_string(),
+                "-f".to_string(),
+                dockerfile.display().to_string(),
+                "-t".to_string(),
+                self.image.clone(),
+            ];
+            build.extend(labels.iter().cloned());
+            build.push(self.build_context.display().to_string());
+            commands.push(join(build));
+        }
+        for volume in &self.volumes {
+            let mut create = vec![engine.binary.clone(), "volume".to_string(), "create".to_string()];
+            create.extend(labels.iter().cloned());
+            create.push(volume.name.clone());
+            commands.push(join(create));
+        }
+        let mut run = vec![engine.binary.clone(), "run".to_string(), "-d".to_string(), "--name".to_string(), self.container.clone()];
+        run.extend(self.run_options());
+        run.push(self.image.clone());
+        commands.push(join(run));
+        commands
+    }
+
+    // Do what run.sh does through the engine's operations. `rebuild` does what REBUILD=1 does:
+    // the image is built again and the container replaced (compose projects are brought up again).
+    pub fn install(&self, engine: &ContainerEngine, rebuild: bool) -> Result<(), Box<dyn Error>> {
+        if let Some(compose) = &self.compose {
+            if !rebuild && engine.compose_is_up(compose, &self.container) {
+                println!("Compose project {} is already up.", self.container);
+                return Ok(());
+            }
+            engine.compose_up(compose, &self.container)?;
+            println!("Compose project {} started.", self.container);
+            return Ok(());
+        }
+
+        match engine.inspect(&self.container) {
+            Some(_) if rebuild => {
+                println!("Replacing container {}.", self.container);
+                engine.rm(&self.container)?;
+            }
+            Some(ContainerState::Running) => {
+                println!("Container {} is already running.", self.container);
+                return Ok(());
+            }
+            Some(ContainerState::Stopped) => {
+                println!("Starting existing container {}.", self.container);
+                engine.start(&self.container)?;
+                return Ok(());
+            }
+            None => {}
+        }
+        if let Some(dockerfile) = &self.dockerfile {
+            if rebuild || !engine.image_exists(&self.image) {
+                let output = engine.build(dockerfile, &self.build_context, &self.image, &self.labels)?;
+                print!("{}", output.log);
+                if !output.success {
+                    return Err(format!("Building image '{}' failed.", self.image).into());
+                }
+            }
+        }
+        // Created beforehand, as `run` would create them without the labels
+        let volumes = engine.list(ResourceKind::Volume);
+        for volume in self.volumes.iter().filter(|volume| !volumes.contains(&volume.name)) {
+            engine.create_volume(&volume.name, &self.labels)?;
+        }
+        engine.run(&self.container, &self.image, &self.run_options())?;
+        println!("Container {} started.", self.container);
+        for mapping in &self.ports {
+            println!("Port {} is published on http://localhost:{}", mapping.container, mapping.host);
+        }
+        Ok(())
+    }
+
+    // run.sh for this plan and engine. Running it again reuses what the previous run created: a running
+    // container is left alone, a stopped one is started and an existing image is not rebuilt.
+    // REBUILD=1 rebuilds the image and replaces the container.
+    pub fn render_script(&self, engine: &ContainerEngine) -> String {
+        let mut script = String::from("#!/bin/sh\n# Generated by autocontain, see run_plan.json\nset -e\n\n");
+
+        if let Some(compose) = &self.compose {
+            let compose = shell_quote(&compose.display().to_string());
+            script.push_str(&format!("COMPOSE=\"{}\"\nPROJECT={}\n\n", engine.compose.join(" "), shell_quote(&self.container)));
+            script.push_str(&format!(
+                "if [ -n \"$($COMPOSE -f {compose} -p \"$PROJECT\" ps -q 2>/dev/null)\" ] && [ \"${{REBUILD:-0}}\" != \"1\" ]; then\n\
+                \x20   echo \"Compose project $PROJECT is already up.\"\n\
+                \x20   exit 0\n\
+                fi\n\n\
+                $COMPOSE -f {compose} -p \"$PROJECT\" up -d --build\n\
+                echo \"Compose project $PROJECT started.\"\n",
+                compose = compose
+            ));
+            return script;
+        }
+
+        script.push_str(&format!(
+            "ENGINE={}\nIMAGE={}\nCONTAINER={}\n\n",
+            shell_quote(&engine.binary),
+            shell_quote(&self.image),
+            shell_quote(&self.container)
+        ));
+        script.push_str(
+            "# Reuse the container from a previous run instead of failing on its name\n\
+            if \"$ENGINE\" container inspect \"$CONTAINER\" >/dev/null 2>&1; then\n\
+            \x20   if [ \"${REBUILD:-0}\" = \"1\" ]; then\n\
+            \x20       echo \"Replacing container $CONTAINER.\"\n\
+            \x20       \"$ENGINE\" rm -f \"$CONTAINER\" >/dev/null\n\
+            \x20   elif [ \"$(\"$ENGINE\" container inspect -f '{{.State.Running}}' \"$CONTAINER\")\" = \"true\" ]; then\n\
+            \x20       echo \"Container $CONTAINER is already running.\"\n\
+            \x20       exit 0\n\
+            \x20   else\n\
+            \x20       echo \"Starting existing container $CONTAINER.\"\n\
+            \x20       \"$ENGINE\" start \"$CONTAINER\"\n\
+            \x20       exit 0\n\
+            \x20   fi\n\
+            fi\n\n",
+        );
+        let labels: String = self.labels.iter().map(|label| format!(" --label {}", shell_quote(label))).collect();
+        if let Some(dockerfile) = &self.dockerfile {
+            script.push_str(&format!(
+                "# Build the image unless it exists (REBUILD=1 forces a build)\n\
+                if [ \"${{REBUILD:-0}}\" = \"1\" ] || ! \"$ENGINE\" image inspect \"$IMAGE\" >/dev/null 2>&1; then\n\
+                \x20   \"$ENGINE\" build -t \"$IMAGE\" -f {}{} {}\n\
+                fi\n\n",
+                shell_quote(&dockerfile.display().to_string()),
+                labels,
+                shell_quote(&self.build_context.display().to_string())
+            ));
+        }
+        if !self.volumes.is_empty() {
+            script.push_str("# Create the volumes first, `run` would create them without the labels\n");
+            for volume in &self.volumes {
+                script.push_str(&format!(
+                    "\"$ENGINE\" volume inspect {name} >/dev/null 2>&1 || \"$ENGINE\" volume create{} {name} >/dev/null\n",
+                    labels,
+                    name = volume.name
+                ));
+            }
+            script.push('\n');
+        }
+
+        script.push_str(&format!("\"$ENGINE\" run -d --name \"$CONTAINER\"{}", labels));
+        for mapping in &self.ports {
+            script.push_str(&format!(" \\\n    -p {}:{}", mapping.host, mapping.container));
+        }
+        if let Some(env_file) = &self.env_file {
+            script.push_str(&format!(" \\\n    --env-file {}", shell_quote(&env_file.display().to_string())));
+        }
+        for volume in &self.volumes {
+            script.push_str(&format!(" \\\n    -v {}:{}", volume.name, shell_quote(&volume.target)));
+        }
+        script.push_str(" \\\n    \"$IMAGE\"\n");
+        script.push_str("echo \"Container $CONTAINER started.\"\n");
+        for mapping in &self.ports {
+            script.push_str(&format!("echo \"Port {} is published on http://localhost:{}\"\n", mapping.container, mapping.host));
+        }
+        script
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::config::SAFETY_POLICY;
+use crate::dockerfile;
+use crate::engine::ContainerEngine;
+use crate::review::ReviewedCommand;
+
+// Rules, as named in the policy file
+pub const HOST_COMMAND: &str = "host-command";
+pub const PRIVILEGED: &str = "privileged";
+pub const HOST_MOUNT: &str = "host-mount";
+pub const SECRET_EXFILTRATION: &str = "secret-exfiltration";
+
+// Programs run.sh may call besides the container engine: shell builtins that do not touch the host
+const SHELL_BUILTINS: [&str; 17] = [
+    "echo", "printf", "set", "exit", "[", "[[", "test", "true", "false", "export", "cd", "read",
+    "sleep", "return", "shift", "wait", ":",
+];
+// Words that start a shell construct, skipped to find the program of a command
+const SHELL_KEYWORDS: [&str; 13] = ["if", "then", "else", "elif", "fi", "while", "until", "do", "done", "!", "{", "}", "exec"];
+const COMPOSE_TOOLS: [&str; 2] = ["docker-compose", "podman-compose"];
+const ENGINE_SOCKETS: [&str; 3] = ["docker.sock", "podman.sock", "containerd.sock"];
+// Host files holding credentials
+const SECRET_PATHS: [&str; 10] = [
+    ".ssh/", ".aws/", ".kube/config", ".docker/config.json", ".gnupg", ".netrc", ".git-credentials",
+    ".npmrc", "/etc/shadow", ".config/gcloud",
+];
+const NETWORK_TOOLS: [&str; 5] = ["curl", "wget", "nc", "ncat", "socat"];
+// Flags that make curl or wget send data
+const UPLOAD_FLAGS: [&str; 10] = [
+    "-d", "--data", "--data-binary", "--data-raw", "-F", "--form", "-T", "--upload-file", "--post-data", "--post-file",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Allow,
+    Warn,
+    Block,
+}
+
+// What to do for each rule, read from the policy file:
+//   allow_commands = ["make"]
+//   [rules]
+//   host-command = "block"
+#[derive(Debug, Clone, Deserialize)]
+pub struct Policy {
+    #[serde(default)]
+    pub rules: HashMap<String, Action>,
+    #[serde(default)]
+    pub allow_commands: Vec<String>, // Programs run.sh may call on the host without a finding
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        let rules = [
+            (HOST_COMMAND, Action::Warn),
+            (PRIVILEGED, Action::Block),
+            (HOST_MOUNT, Action::Warn),
+            (SECRET_EXFILTRATION, Action::Block),
+        ];
+        Policy {
+            rules: rules.iter().map(|(rule, action)| (rule.to_string(), *action)).collect(),
+            allow_commands: Vec::new(),
+        }
+    }
+}
+
+impl Policy {
+    // The policy file, rules it leaves out keep their default action
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file: Policy = toml::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| format!("Invalid safety policy {}: {}", path.display(), e))?;
+        let mut policy = Policy::default();
+        policy.rules.extend(file.rules);
+        policy.allow_commands = file.allow_commands;
+        Ok(policy)
+    }
+
+    // The policy from AUTOCONTAIN_SAFETY_POLICY (safety_policy.toml by default), the defaults when there is none
+    pub fn from_config() -> Result<Self, Box<dyn Error>> {
+        if SAFETY_POLICY.exists() {
+            Policy::load(&SAFETY_POLICY)
+        } else {
+            Ok(Policy::default())
+        }
+    }
+
+    fn action(&self, rule: &str) -> Action {
+        self.rules.get(rule).copied().unwrap_or(Action::Warn)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub location: String, // File and line, e.g. "run.sh:12"
+    pub rule: &'static str,
+    pub action: Action,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = match self.action {
+            Action::Allow => "allow",
+            Action::Warn => "warning",
+            Action::Block => "blocked",
+        };
+        write!(f, "{}: {} [{}] {}", self.location, action, self.rule, self.message)
+    }
+}
+
+pub fn is_blocked(findings: &[Finding]) -> bool {
+    findings.iter().any(|finding| finding.action == Action::Block)
+}
+
+// Collects findings, dropping the ones whose rule the policy allows
+struct Report<'a> {
+    policy: &'a Policy,
+    findings: Vec<Finding>,
+}
+
+impl Report<'_> {
+    fn add(&mut self, location: String, rule: &'static str, message: String) {
+        let action = self.policy.action(rule);
+        if action != Action::Allow {
+            self.findings.push(Finding { location, rule, action, message });
+        }
+    }
+}
+
+fn unquote(word: &str) -> &str {
+    word.trim_matches(['\'', '"'])
+}
+
+// "$ENGINE", "${ENGINE}" -> "ENGINE"
+fn variable_name(word: &str) -> Option<&str> {
+    let name = unquote(word).strip_prefix('$')?;
+    Some(name.strip_prefix('{').and_then(|name| name.strip_suffix('}')).unwrap_or(name))
+}
+
+// "NAME=value" -> ("NAME", "value")
+fn assignment(word: &str) -> Option<(&str, &str)> {
+    let (name, value) = word.split_once('=')?;
+    let is_name = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    is_name.then_some((name, value))
+}
+
+// The variable assignments and the program of a simple command, after any shell keywords.
+// A quoted value may span several words: COMPOSE="docker compose".
+fn split_command(words: &[String]) -> (Vec<(String, String)>, Option<&str>) {
+    let mut assignments = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        if SHELL_KEYWORDS.contains(&words[i].as_str()) {
+            i += 1;
+            continue;
+        }
+        let Some((name, value)) = assignment(&words[i]) else {
+            return (assignments, Some(words[i].as_str()));
+        };
+        let mut value = value.to_string();
+        if let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') {
+            while (value.len() < 2 || !value.ends_with(quote)) && i + 1 < words.len() {
+                i += 1;
+                value.push(' ');
+                value.push_str(&words[i]);
+            }
+        }
+        assignments.push((name.to_string(), unquote(&value).to_string()));
+        i += 1;
+    }
+    (assignments, None)
+}
+
+// The simple commands of a command line: split at ';', '&&', '||', '|' and '$(...)'
+fn simple_commands(command: &str) -> Vec<Vec<String>> {
+    command
+        .replace("&&", "\n")
+        .split(['\n', ';', '|', '`', '(', ')'])
+        .map(|part| part.split_whitespace().map(|word| word.to_string()).collect::<Vec<_>>())
+        .filter(|words| !words.is_empty())
+        .collect()
+}
+
+// Host paths used as the source of a bind mount: -v /src:/dst, --volume=$HOME:/dst, --mount type=bind,...
+fn host_mounts(words: &[&str]) -> Vec<String> {
+    let mut mounts = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        let word = unquote(word);
+        let value = match word {
+            "-v" | "--volume" | "--mount" => words.get(i + 1).map(|next| unquote(next)),
+            _ => word.strip_prefix("--volume=").or_else(|| word.strip_prefix("--mount=")),
+        };
+        let Some(value) = value else {
+            continue;
+        };
+        if value.contains("type=bind") {
+            mounts.push(value.to_string());
+        } else if let Some((source, _)) = value.split_once(':') {
+            if source.starts_with(['/', '~', '$']) {
+                mounts.push(source.to_string());
+            }
+        }
+    }
+    mounts
+}
+
+fn privileged_flags(words: &[&str]) -> Vec<String> {
+    let mut flags = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        let word = unquote(word);
+        let next = words.get(i + 1).map(|next| unquote(next)).unwrap_or_default();
+        let flagged = match word {
+            "--privileged" | "--security=insecure" => true,
+            "--network=host" | "--net=host" | "--pid=host" | "--ipc=host" | "--userns=host" | "--uts=host" => true,
+            "--network" | "--net" | "--pid" | "--ipc" | "--userns" | "--uts" => next == "host",
+            "--security-opt" => next.contains("unconfined") || next.contains("disable"),
+            _ => {
+                word.starts_with("--cap-add")
+                    || word.starts_with("--device")
+                    || (word.starts_with("--security-opt=") && (word.contains("unconfined") || word.contains("disable")))
+            }
+        };
+        if flagged {
+            flags.push(if word.starts_with("--") && !word.contains('=') && !next.starts_with('-') && !next.is_empty() {
+                format!("{} {}", word, next)
+            } else {
+                word.to_string()
+            });
+        }
+    }
+    flags
+}
+
+// Secret files read, and data sent out by curl/wget/nc, anywhere in the command line
+fn exfiltration(command: &str) -> Option<String> {
+    if let Some(path) = SECRET_PATHS.iter().find(|path| command.contains(*path)) {
+        return Some(format!("reads credentials from '{}'", path));
+    }
+    let commands = simple_commands(command);
+    let sends_data = commands.iter().any(|words| {
+        let tool = words.iter().map(|word| unquote(word)).find(|word| NETWORK_TOOLS.contains(word));
+        // curl and wget only send data with an upload flag, nc and socat always do
+        match tool {
+            Some("curl") | Some("wget") => words.iter().any(|word| {
+                let word = unquote(word);
+                UPLOAD_FLAGS.iter().any(|flag| word == *flag || word.starts_with(&format!("{}=", flag)))
+            }),
+            Some(_) => true,
+            None => false,
+        }
+    });
+    let reads_environment = commands.iter().any(|words| words.iter().any(|word| matches!(unquote(word), "env" | "printenv")));
+    if sends_data {
+        let what = if reads_environment { "the environment" } else { "data" };
+        return Some(format!("sends {} to the network", what));
+    }
+    None
+}
+
+// Check the commands of run.sh (or of a run plan) found in `source`
+pub fn analyze_commands(source: &str, commands: &[ReviewedCommand], engine: &ContainerEngine, policy: &Policy) -> Vec<Finding> {
+    let mut report = Report { policy, findings: Vec::new() };
+    let is_engine = |program: &str| {
+        program == engine.binary
+            || program == engine.compose[0]
+            || ["docker", "podman", "nerdctl"].contains(&program)
+            || COMPOSE_TOOLS.contains(&program)
+    };
+    // Variables holding the engine, e.g. ENGINE='docker' or COMPOSE="docker compose"
+    let mut engine_variables: Vec<String> = Vec::new();
+
+    for command in commands {
+        let location = format!("{}:{}", source, command.line);
+        for words in simple_commands(&command.text) {
+            let (assignments, program) = split_command(&words);
+            let Some(program) = program else {
+                // Only assignments
+                for (name, value) in assignments {
+                    if value.split_whitespace().next().is_some_and(&is_engine) {
+                        engine_variables.push(name);
+                    }
+                }
+                continue;
+            };
+            let allowed = match variable_name(program) {
+                Some(name) => engine_variables.iter().any(|variable| variable == name),
+                None => {
+                    let program = unquote(program);
+                    is_engine(program) || SHELL_BUILTINS.contains(&program) || policy.allow_commands.iter().any(|allowed| allowed == program)
+                }
+            };
+            if !allowed && !unquote(program).is_empty() {
+                report.add(location.clone(), HOST_COMMAND, format!("runs '{}' on the host, outside the container engine", unquote(program)));
+            }
+        }
+
+        let words: Vec<&str> = command.text.split_whitespace().collect();
+        for flag in privileged_flags(&words) {
+            report.add(location.clone(), PRIVILEGED, format!("'{}' lifts the container's isolation", flag));
+        }
+        if let Some(socket) = ENGINE_SOCKETS.iter().find(|socket| command.text.contains(*socket)) {
+            report.add(location.clone(), PRIVILEGED, format!("exposes the engine socket '{}' to the container", socket));
+        }
+        for mount in host_mounts(&words) {
+            report.add(location.clone(), HOST_MOUNT, format!("mounts the host path '{}' into the container", mount));
+        }
+        if let Some(message) = exfiltration(&command.text) {
+            report.add(location, SECRET_EXFILTRATION, message);
+        }
+    }
+    report.findings
+}
+
+// Check a Dockerfile: RUN flags that lift the build sandbox and instructions that read or send secrets
+pub fn analyze_dockerfile(source: &str, content: &str, policy: &Policy) -> Vec<Finding> {
+    let mut report = Report { policy, findings: Vec::new() };
+    for instruction in dockerfile::parse(content) {
+        let location = format!("{}:{}", source, instruction.line);
+        // RUN flags come before the command; --mount=type=secret is the safe way to use credentials
+        let flags: Vec<&str> = instruction.args.split_whitespace().take_while(|word| word.starts_with("--")).collect();
+        if instruction.keyword == "RUN" {
+            for flag in privileged_flags(&flags) {
+                report.add(location.clone(), PRIVILEGED, format!("'RUN {}' lifts the build's isolation", flag));
+            }
+        }
+        if matches!(instruction.keyword.as_str(), "RUN" | "COPY" | "ADD" | "CMD" | "ENTRYPOINT") {
+            let command: Vec<&str> = instruction.args.split_whitespace().skip(flags.len()).collect();
+            if let Some(message) = exfiltration(&command.join(" ")) {
+                report.add(location, SECRET_EXFILTRATION, message);
+            }
+        }
+    }
+    report.findings
+}
+
+// Check a compose file line by line for privileged services and host mounts
+pub fn analyze_compose(source: &str, content: &str, policy: &Policy) -> Vec<Finding> {
+    let mut report = Report { policy, findings: Vec::new() };
+    for (i, line) in content.lines().enumerate() {
+        let location = format!("{}:{}", source, i + 1);
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once(':').map_or((line, ""), |(key, value)| (key.trim(), unquote(value.trim())));
+        match key {
+            "privileged" if value == "true" => report.add(location, PRIVILEGED, "'privileged: true' lifts the container's isolation".to_string()),
+            "network_mode" | "pid" | "ipc" | "userns_mode" if value == "host" => {
+                report.add(location, PRIVILEGED, format!("'{}: host' shares the host's namespace", key))
+            }
+            "cap_add" | "devices" => report.add(location, PRIVILEGED, format!("'{}' gives the container host capabilities", key)),
+            _ => {
+                let item = unquote(line.trim_start_matches('-').trim());
+                if ENGINE_SOCKETS.iter().any(|socket| item.contains(socket)) {
+                    report.add(location, PRIVILEGED, "exposes the engine socket to the container".to_string());
+                } else if line.starts_with('-') && item.contains(':') && item.starts_with(['/', '~']) {
+                    let source = item.split(':').next().unwrap_or(item);
+                    report.add(location, HOST_MOUNT, format!("mounts the host path '{}' into the container", source));
+                } else if let Some(message) = exfiltration(line) {
+                    report.add(location, SECRET_EXFILTRATION, message);
+                }
+            }
+        }
+    }
+    report.findings
+}
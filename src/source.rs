@@ -0,0 +1,425 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+
+use git2::build::CheckoutBuilder;
+use git2::{
+    Commit, Cred, CredentialType, Direction, FetchOptions, Object, Oid, Progress, Remote, RemoteCallbacks, Repository,
+    SubmoduleUpdateOptions, TreeWalkMode, TreeWalkResult,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::discovery::{DEVCONTAINER_DIR, DOCKER_DIRS};
+use crate::lifecycle::format_size;
+
+// Host shortcuts accepted in place of a URL, e.g. "gh:owner/repo"
+const HOST_SHORTCUTS: [(&str, &str); 3] = [
+    ("gh:", "https://github.com/"),
+    ("gl:", "https://gitlab.com/"),
+    ("bb:", "https://bitbucket.org/"),
+];
+const URL_SCHEMES: [&str; 6] = ["https://", "http://", "ssh://", "git://", "git+ssh://", "file://"];
+
+// Repositories are kept in 'source' and 'scripts' under a key "<host>/<owner>/<name>", so
+// repositories with the same name from different owners or hosts never share a folder. Directories
+// on this machine (and file:// URLs) use "local" as host, their parent directory as owner and their
+// name followed by a hash of their path.
+const LOCAL_HOST: &str = "local";
+// Owner of repositories whose URL has none, e.g. "git://host/repo"
+const NO_OWNER: &str = "_";
+
+// Where the repository to analyze comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepoSource {
+    Remote { url: String, key: String }, // Cloned into 'source/<key>'
+    Local { path: PathBuf, key: String }, // A directory on this machine, analyzed in place
+}
+
+fn make_key(host: &str, owner: &str, name: &str) -> Option<String> {
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    let valid = |part: &str| !part.is_empty() && part != "." && part != "..";
+    let owner = if owner.is_empty() { NO_OWNER } else { owner };
+    (valid(host) && valid(owner) && valid(name)).then(|| format!("{}/{}/{}", host, owner, name))
+}
+
+// "/home/me/projects/app" -> "local/projects/app-1a2b3c4d". The hash of the whole path tells apart
+// directories with the same name and parent name, like "/home/me/work/app" and "/srv/work/app".
+fn key_from_path(path: &str) -> Option<String> {
+    let mut segments = path.split(['/', '\\']).filter(|segment| !segment.is_empty()).rev();
+    let name = segments.next()?;
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    let digest = Sha256::digest(path.as_bytes());
+    let hash: String = digest.iter().take(4).map(|byte| format!("{:02x}", byte)).collect();
+    make_key(LOCAL_HOST, segments.next().unwrap_or(NO_OWNER), &format!("{}-{}", name, hash))
+}
+
+// The key of a local directory without the hash of its path: "local/projects/app-1a2b3c4d" ->
+// "local/projects/app". Other keys are returned as they are.
+pub fn without_path_hash(key: &str) -> &str {
+    match key.rsplit_once('-') {
+        Some((name, hash)) if key.starts_with("local/") && hash.len() == 8 && hash.chars().all(|c| c.is_ascii_hexdigit()) => name,
+        _ => key,
+    }
+}
+
+// "https://github.com/owner/repo.git" or "git@github.com:owner/repo" -> "github.com/owner/repo".
+// GitLab subgroups are joined into the owner: "gitlab.com/group/sub/repo" -> "gitlab.com/group-sub/repo".
+fn key_from_url(url: &str) -> Option<String> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return key_from_path(path);
+    }
+    let (authority, path) = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').unwrap_or((rest, "")),
+        None => url.split_once(':')?,
+    };
+    // Without the user and the port
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+    let (name, owners) = segments.split_last()?;
+    make_key(host, &owners.join("-"), name)
+}
+
+// scp-like ssh address: "user@host:path"
+fn is_scp_address(arg: &str) -> bool {
+    match arg.split_once(':') {
+        Some((host, path)) => host.contains('@') && !host.contains('/') && !path.is_empty(),
+        None => false,
+    }
+}
+
+impl RepoSource {
+    // Understand the `run` argument: an existing directory, a host shortcut, or any git URL
+    pub fn parse(arg: &str) -> Result<Self, String> {
+        let path = Path::new(arg);
+        if path.is_dir() {
+            let path = path.canonicalize().map_err(|e| format!("Cannot open '{}': {}", arg, e))?;
+            let key = key_from_path(&path.to_string_lossy())
+                .ok_or_else(|| format!("Cannot name the repository at '{}'.", arg))?;
+            return Ok(RepoSource::Local { path, key });
+        }
+
+        let url = match HOST_SHORTCUTS.iter().find_map(|(prefix, base)| arg.strip_prefix(prefix).map(|rest| (base, rest))) {
+            Some((base, rest)) if rest.split('/').filter(|part| !part.is_empty()).count() == 2 => format!("{}{}", base, rest),
+            Some(_) => return Err(format!("'{}' should look like gh:owner/repo.", arg)),
+            None if URL_SCHEMES.iter().any(|scheme| arg.starts_with(scheme)) || is_scp_address(arg) => arg.to_string(),
+            None => return Err(format!("'{}' is neither a directory nor a git URL.", arg)),
+        };
+        let key = key_from_url(url.trim_end_matches('/')).ok_or_else(|| format!("Cannot name the repository at '{}'.", url))?;
+        Ok(RepoSource::Remote { url, key })
+    }
+
+    // "<host>/<owner>/<name>"
+    pub fn key(&self) -> &str {
+        match self {
+            RepoSource::Remote { key, .. } | RepoSource::Local { key, .. } => key,
+        }
+    }
+}
+
+// Keys of the repositories kept in `base` ('source' or 'scripts'), sorted
+pub fn installed_keys(base: &Path) -> Vec<String> {
+    let subdirs = |path: &Path| -> Vec<String> {
+        match fs::read_dir(path) {
+            Ok(entries) => entries
+                .flatten()
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    };
+    let mut keys = Vec::new();
+    for host in subdirs(base) {
+        for owner in subdirs(&base.join(&host)) {
+            for name in subdirs(&base.join(&host).join(&owner)) {
+                keys.push(format!("{}/{}/{}", host, owner, name));
+            }
+        }
+    }
+    keys.sort();
+    keys
+}
+
+// Credentials for private repositories: the ssh agent for ssh URLs, git's credential helpers for https
+pub fn remote_callbacks<'a>() -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(|url, username, allowed| {
+        if allowed.contains(CredentialType::SSH_KEY) {
+            return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            let config = git2::Config::open_default()?;
+            return Cred::credential_helper(&config, url, username);
+        }
+        Cred::default()
+    });
+    callbacks
+}
+
+// e.g. "Receiving objects [##########          ]  50% (500/1000), 1.2 MB"
+pub fn progress_line(received: usize, total: usize, bytes: usize) -> String {
+    const WIDTH: usize = 20;
+    let filled = (received * WIDTH).checked_div(total).unwrap_or(0);
+    format!(
+        "Receiving objects [{}{}] {:>3}% ({}/{}), {}",
+        "#".repeat(filled),
+        " ".repeat(WIDTH - filled),
+        (received * 100).checked_div(total).unwrap_or(0),
+        received,
+        total,
+        format_size(bytes as u64)
+    )
+}
+
+// Credentials, and a progress bar on the terminal while objects are received
+pub fn fetch_options<'a>() -> FetchOptions<'a> {
+    let mut callbacks = remote_callbacks();
+    if io::stderr().is_terminal() {
+        let mut done = false;
+        callbacks.transfer_progress(move |stats: Progress| {
+            if !done && stats.total_objects() > 0 {
+                eprint!("\r{}", progress_line(stats.received_objects(), stats.total_objects(), stats.received_bytes()));
+                done = stats.received_objects() == stats.total_objects();
+                if done {
+                    eprintln!();
+                }
+            }
+            true
+        });
+    }
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks);
+    options
+}
+
+// file:// URLs and paths, which git cannot clone shallowly
+pub fn is_local_url(url: &str) -> bool {
+    url.starts_with("file://") || Path::new(url).exists()
+}
+
+// Check that `url` is a reachable git repository with a default branch, like `git ls-remote`.
+// Works offline for file:// URLs and local paths.
+pub fn probe_remote(url: &str) -> Result<(), git2::Error> {
+    let mut remote = Remote::create_detached(url)?;
+    let connection = remote.connect_auth(Direction::Fetch, Some(remote_callbacks()), None)?;
+    // Asked instead of listing the refs: git2 cannot list the refs of an empty repository
+    connection.default_branch()
+        .map(|_| ())
+        .map_err(|_| git2::Error::from_str("the repository has no default branch, is it empty?"))
+}
+
+// Where the analyzed code came from and how it was cloned and analyzed, saved as metadata.json in
+// the scripts folder so an analysis can be reproduced from the same commit and updated the same way
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub source: String, // URL or local directory
+    pub git_ref: Option<String>, // Branch, tag or commit given with --ref
+    pub commit: Option<String>, // Commit that was analyzed, none for directories outside git
+    #[serde(default)]
+    pub depth: usize, // --depth the Markdown files were searched to
+    #[serde(default)]
+    pub full_history: bool,
+    #[serde(default)]
+    pub sparse: bool,
+    #[serde(default)]
+    pub submodules: bool,
+}
+
+impl Metadata {
+    pub fn load(scripts_path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(scripts_path.join("metadata.json")).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self, scripts_path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(scripts_path)?;
+        fs::write(scripts_path.join("metadata.json"), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// Fetch `refspecs` from origin, shallow clones staying shallow
+fn fetch_origin(repo: &Repository, refspecs: &[String]) -> Result<(), git2::Error> {
+    let mut options = fetch_options();
+    if repo.is_shallow() {
+        options.depth(1);
+    }
+    repo.find_remote("origin")?.fetch(refspecs, Some(&mut options), None)
+}
+
+// A branch of the origin remote, a tag or a (possibly abbreviated) commit
+fn resolve_ref<'r>(repo: &'r Repository, git_ref: &str) -> Option<Object<'r>> {
+    [format!("refs/remotes/origin/{}", git_ref), format!("refs/tags/{}", git_ref), git_ref.to_string()]
+        .iter()
+        .find_map(|spec| repo.revparse_single(spec).ok())
+}
+
+// Check out `git_ref` (detached) and return its commit. Refs the clone does not know yet, like a tag
+// pushed since cloning or anything missing from a shallow clone, are fetched from origin first.
+pub fn checkout_ref(repo: &Repository, git_ref: &str) -> Result<Oid, git2::Error> {
+    let object = match resolve_ref(repo, git_ref) {
+        Some(object) => object,
+        None => {
+            let refspecs = [
+                format!("+refs/heads/{0}:refs/remotes/origin/{0}", git_ref),
+                format!("+refs/tags/{0}:refs/tags/{0}", git_ref),
+            ];
+            fetch_origin(repo, &refspecs)?;
+            // A commit no branch or tag points to can only be fetched by its full id
+            if resolve_ref(repo, git_ref).is_none() {
+                fetch_origin(repo, &[git_ref.to_string()]).ok();
+            }
+            resolve_ref(repo, git_ref)
+                .ok_or_else(|| git2::Error::from_str(&format!("no branch, tag or commit named '{}'", git_ref)))?
+        }
+    };
+    let commit = object.peel_to_commit()?;
+    checkout_commit(repo, &commit)?;
+    repo.set_head_detached(commit.id())?;
+    Ok(commit.id())
+}
+
+// Check out the files of `commit`, only those the analysis reads when the clone is sparse
+fn checkout_commit(repo: &Repository, commit: &Commit) -> Result<(), git2::Error> {
+    let mut checkout = CheckoutBuilder::new();
+    checkout.force();
+    if is_sparse(repo) {
+        // The files of the current commit too, so those `commit` does not have are deleted
+        let mut paths = sparse_paths(commit)?;
+        if let Ok(head) = repo.head().and_then(|head| head.peel_to_commit()) {
+            paths.extend(sparse_paths(&head)?);
+        }
+        for path in paths {
+            checkout.path(path);
+        }
+    }
+    repo.checkout_tree(commit.as_object(), Some(&mut checkout))
+}
+
+// Whether `git_ref` names a tag or a commit rather than a branch of origin, the way `checkout_ref`
+// resolved it: branches first, then tags, then commit ids
+fn is_pinned(repo: &Repository, git_ref: &str) -> bool {
+    if repo.find_reference(&format!("refs/remotes/origin/{}", git_ref)).is_ok() {
+        return false;
+    }
+    let is_commit_id = (4..=40).contains(&git_ref.len()) && git_ref.chars().all(|c| c.is_ascii_hexdigit());
+    repo.find_reference(&format!("refs/tags/{}", git_ref)).is_ok()
+        || (is_commit_id && repo.revparse_single(git_ref).and_then(|object| object.peel_to_commit()).is_ok())
+}
+
+// Fetch the branch the clone follows (`git_ref` when it names a branch, the checked out branch
+// otherwise) and fast-forward to it. Returns the commit checked out afterwards, none when the clone
+// is pinned to a tag or commit. Shallow clones have no history to check that the branch was not
+// rewritten, so they simply move to its new commit.
+pub fn follow_branch(repo: &Repository, git_ref: Option<&str>) -> Result<Option<Oid>, git2::Error> {
+    let head = repo.head()?;
+    let branch = match git_ref {
+        Some(git_ref) if is_pinned(repo, git_ref) => return Ok(None),
+        Some(git_ref) => git_ref.to_string(),
+        None if head.is_branch() => head.shorthand().unwrap_or_default().to_string(),
+        None => return Ok(None),
+    };
+    fetch_origin(repo, &[format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch)])?;
+    let Ok(upstream) = repo.find_reference(&format!("refs/remotes/origin/{}", branch)) else {
+        return Ok(None);
+    };
+    let old = head.peel_to_commit()?;
+    let new = upstream.peel_to_commit()?;
+    if new.id() == old.id() {
+        return Ok(Some(new.id()));
+    }
+    if !repo.is_shallow() && !repo.graph_descendant_of(new.id(), old.id())? {
+        return Err(git2::Error::from_str(&format!("'{}' was rewritten and cannot be fast-forwarded", branch)));
+    }
+    checkout_commit(repo, &new)?;
+    if head.is_branch() {
+        repo.find_reference(head.name().unwrap_or_default())?.set_target(new.id(), "autocontain: fast-forward")?;
+    } else {
+        repo.set_head_detached(new.id())?;
+    }
+    Ok(Some(new.id()))
+}
+
+// Files added, modified or deleted between two commits
+pub fn changed_files(repo: &Repository, from: Oid, to: Oid) -> Result<Vec<String>, git2::Error> {
+    let old = repo.find_commit(from)?.tree()?;
+    let new = repo.find_commit(to)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&old), Some(&new), None)?;
+    Ok(diff.deltas()
+        .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()).map(|path| path.to_string_lossy().to_string()))
+        .collect())
+}
+
+// Commit checked out in `path`, none when it is not a git repository or has no commits
+pub fn head_commit(path: &Path) -> Option<String> {
+    let repo = Repository::open(path).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    Some(commit.id().to_string())
+}
+
+// Whether the analysis reads `path` (relative to the repository root): files at most one directory
+// deep (manifests, env examples, ORM configs, component directories), docker files in their usual
+// directories, Markdown anywhere and the Python code the parser puts in the database, whose imports
+// the service detection uses
+pub fn read_by_analysis(path: &str) -> bool {
+    let parts: Vec<&str> = path.split('/').collect();
+    let in_docker_dir = DOCKER_DIRS.contains(&parts[0]) || parts[0] == DEVCONTAINER_DIR;
+    parts.len() <= 2 || (in_docker_dir && parts.len() <= 3) || path.ends_with(".md") || path.ends_with(".py")
+}
+
+// Files of `commit` a sparse checkout writes
+fn sparse_paths(commit: &Commit) -> Result<Vec<String>, git2::Error> {
+    let mut paths = Vec::new();
+    commit.tree()?.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() != Some(git2::ObjectType::Tree) {
+            let path = format!("{}{}", dir, entry.name().unwrap_or_default());
+            if read_by_analysis(&path) {
+                paths.push(path);
+            }
+        }
+        TreeWalkResult::Ok
+    })?;
+    Ok(paths)
+}
+
+// Sparse clones are marked in their git config until the rest is checked out
+const SPARSE_KEY: &str = "autocontain.sparse";
+
+fn is_sparse(repo: &Repository) -> bool {
+    repo.config().and_then(|config| config.get_bool(SPARSE_KEY)).unwrap_or(false)
+}
+
+// Check out only the files the analysis reads, for large repositories
+pub fn sparse_checkout(repo: &Repository) -> Result<(), git2::Error> {
+    repo.config()?.set_bool(SPARSE_KEY, true)?;
+    checkout_commit(repo, &repo.head()?.peel_to_commit()?)
+}
+
+// Check out everything a sparse clone left out, as building the image needs it. Returns whether
+// there was anything to do.
+pub fn complete_checkout(path: &Path) -> Result<bool, git2::Error> {
+    // Local directories analyzed in place need not be git repositories
+    let Ok(repo) = Repository::open(path) else {
+        return Ok(false);
+    };
+    if !is_sparse(&repo) {
+        return Ok(false);
+    }
+    repo.config()?.set_bool(SPARSE_KEY, false)?;
+    repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+    Ok(true)
+}
+
+// Clone the submodules, and theirs, at the commits the repository pins
+pub fn update_submodules(repo: &Repository) -> Result<(), git2::Error> {
+    for mut submodule in repo.submodules()? {
+        println!("Updating submodule {}.", submodule.path().display());
+        let mut options = SubmoduleUpdateOptions::new();
+        options.fetch(fetch_options());
+        submodule.update(true, Some(&mut options))?;
+        update_submodules(&submodule.open()?)?;
+    }
+    Ok(())
+}
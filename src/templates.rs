@@ -0,0 +1,257 @@
+use crate::analysis::{RepoAnalysis, Runtime};
+use crate::ecosystem::Ecosystem;
+
+// Default runtime versions when the manifests do not state one
+const NODE_VERSION: &str = "20";
+const PYTHON_VERSION: &str = "3.12";
+const RUST_VERSION: &str = "1";
+const GO_VERSION: &str = "1.22";
+
+// Deterministic multi-stage Dockerfile for the first recognized stack, with the stack's name.
+// None when no template fits (unknown language, or no way to tell how the application starts),
+// in which case the Dockerfile agent is used.
+pub fn dockerfile(ecosystems: &[Ecosystem], analysis: &RepoAnalysis) -> Option<(&'static str, String)> {
+    ecosystems.iter().find_map(|ecosystem| match ecosystem.language.as_str() {
+        "JavaScript" | "TypeScript" => node_dockerfile(ecosystem, analysis).map(|content| ("Node.js", content)),
+        "Python" => python_dockerfile(ecosystem, analysis).map(|content| ("Python", content)),
+        "Rust" => rust_dockerfile(ecosystem, analysis).map(|content| ("Rust", content)),
+        "Go" => go_dockerfile(ecosystem, analysis).map(|content| ("Go", content)),
+        _ => None,
+    })
+}
+
+// Leading version components of a requirement, e.g. (">=3.10,<4", 2) -> "3.10", ("^18.17.0", 1) -> "18"
+fn version_prefix(spec: Option<&str>, parts: usize) -> Option<String> {
+    let spec = spec?;
+    let start = spec.find(|c: char| c.is_ascii_digit())?;
+    let version: String = spec[start..].chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+    let prefix: Vec<&str> = version.split('.').filter(|part| !part.is_empty()).take(parts).collect();
+    (!prefix.is_empty()).then(|| prefix.join("."))
+}
+
+// CMD in exec form when the command is plain words, through a shell otherwise
+fn cmd_instruction(command: &str) -> String {
+    let words: Vec<&str> = if command.contains(['&', '|', ';', '$', '>', '<', '"', '\'']) {
+        vec!["sh", "-c", command]
+    } else {
+        command.split_whitespace().collect()
+    };
+    format!("CMD {}", serde_json::to_string(&words).unwrap_or_default())
+}
+
+fn expose_lines(analysis: &RepoAnalysis) -> String {
+    analysis.exposed_ports.iter().map(|port| format!("EXPOSE {}\n", port)).collect()
+}
+
+fn has_script(ecosystem: &Ecosystem, name: &str) -> bool {
+    ecosystem.scripts.iter().any(|script| script.starts_with(&format!("{}:", name)))
+}
+
+fn has_lockfile(ecosystem: &Ecosystem, file: &str) -> bool {
+    ecosystem.lockfiles.iter().any(|lockfile| lockfile == file)
+}
+
+// How the application is started, from the manifest first and the analysis second
+pub fn start_command(ecosystem: &Ecosystem, analysis: &RepoAnalysis) -> Option<String> {
+    let from_manifest = match ecosystem.language.as_str() {
+        "JavaScript" | "TypeScript" => {
+            if has_script(ecosystem, "start") {
+                Some(format!("{} start", ecosystem.package_manager))
+            } else {
+                ecosystem.entrypoints.first().map(|main| format!("node {}", main))
+            }
+        }
+        "Python" => ecosystem.scripts.first()
+            .and_then(|script| script.split(':').next())
+            .map(|name| name.trim().to_string())
+            .or_else(|| {
+                ecosystem.entrypoints.first().map(|file| match file.as_str() {
+                    "manage.py" => "python manage.py runserver 0.0.0.0:8000".to_string(),
+                    _ => format!("python {}", file),
+                })
+            }),
+        "Rust" => ecosystem.entrypoints.first().cloned(),
+        "Go" => ecosystem.entrypoints.first().map(|_| "/app".to_string()),
+        _ => None,
+    };
+    from_manifest.or_else(|| analysis.entrypoint.clone())
+}
+
+fn node_dockerfile(ecosystem: &Ecosystem, analysis: &RepoAnalysis) -> Option<String> {
+    let start = start_command(ecosystem, analysis)?;
+    let version = version_prefix(ecosystem.runtime_version.as_deref(), 1).unwrap_or_else(|| NODE_VERSION.to_string());
+    let (install, prune) = match ecosystem.package_manager.as_str() {
+        "npm" if has_lockfile(ecosystem, "package-lock.json") => ("npm ci", "RUN npm prune --omit=dev\n"),
+        "npm" => ("npm install", "RUN npm prune --omit=dev\n"),
+        "yarn" => ("corepack enable && yarn install", ""),
+        "pnpm" => ("corepack enable && pnpm install --frozen-lockfile", "RUN pnpm prune --prod\n"),
+        _ => return None,
+    };
+    let mut manifests = vec!["package.json".to_string()];
+    manifests.extend(ecosystem.lockfiles.iter().cloned());
+    let build = if has_script(ecosystem, "build") {
+        format!("RUN {} run build\n", ecosystem.package_manager)
+    } else {
+        String::new()
+    };
+    let corepack = if ecosystem.package_manager == "npm" { "" } else { "RUN corepack enable\n" };
+
+    Some(format!(
+        "FROM node:{version}-slim AS build\n\
+        WORKDIR /app\n\
+        COPY {manifests} ./\n\
+        RUN {install}\n\
+        COPY . .\n\
+        {build}\
+        {prune}\
+        \n\
+        FROM node:{version}-slim\n\
+        WORKDIR /app\n\
+        ENV NODE_ENV=production\n\
+        {corepack}\
+        COPY --from=build /app /app\n\
+        {expose}\
+        {cmd}\n",
+        version = version,
+        manifests = manifests.join(" "),
+        install = install,
+        build = build,
+        prune = prune,
+        corepack = corepack,
+        expose = expose_lines(analysis),
+        cmd = cmd_instruction(&start),
+    ))
+}
+
+fn python_dockerfile(ecosystem: &Ecosystem, analysis: &RepoAnalysis) -> Option<String> {
+    let start = start_command(ecosystem, analysis)?;
+    let version = version_prefix(ecosystem.runtime_version.as_deref(), 2).unwrap_or_else(|| PYTHON_VERSION.to_string());
+    // requirements.txt can be installed before the sources for better layer caching; a pyproject
+    // or setup.py project needs its sources to be installed
+    let install = match ecosystem.manifest.as_str() {
+        "requirements.txt" => "COPY requirements.txt ./\n\
+            RUN pip install --no-cache-dir -r requirements.txt\n\
+            COPY . .\n".to_string(),
+        _ => "COPY . .\n\
+            RUN pip install --no-cache-dir .\n".to_string(),
+    };
+
+    Some(format!(
+        "FROM python:{version}-slim AS build\n\
+        WORKDIR /app\n\
+        RUN python -m venv /opt/venv\n\
+        ENV PATH=\"/opt/venv/bin:$PATH\"\n\
+        {install}\
+        \n\
+        FROM python:{version}-slim\n\
+        WORKDIR /app\n\
+        ENV PATH=\"/opt/venv/bin:$PATH\" PYTHONUNBUFFERED=1\n\
+        COPY --from=build /opt/venv /opt/venv\n\
+        COPY --from=build /app /app\n\
+        {expose}\
+        {cmd}\n",
+        version = version,
+        install = install,
+        expose = expose_lines(analysis),
+        cmd = cmd_instruction(&start),
+    ))
+}
+
+fn rust_dockerfile(ecosystem: &Ecosystem, analysis: &RepoAnalysis) -> Option<String> {
+    let binary = ecosystem.entrypoints.first()?;
+    let version = version_prefix(ecosystem.runtime_version.as_deref(), 2).unwrap_or_else(|| RUST_VERSION.to_string());
+    let locked = if has_lockfile(ecosystem, "Cargo.lock") { " --locked" } else { "" };
+
+    Some(format!(
+        "FROM rust:{version}-slim AS build\n\
+        WORKDIR /app\n\
+        COPY . .\n\
+        RUN cargo build --release{locked} --bin {binary}\n\
+        \n\
+        FROM debian:bookworm-slim\n\
+        RUN apt-get update && apt-get install -y --no-install-recommends ca-certificates && rm -rf /var/lib/apt/lists/*\n\
+        COPY --from=build /app/target/release/{binary} /usr/local/bin/{binary}\n\
+        {expose}\
+        {cmd}\n",
+        version = version,
+        locked = locked,
+        binary = binary,
+        expose = expose_lines(analysis),
+        cmd = cmd_instruction(&start_command(ecosystem, analysis)?),
+    ))
+}
+
+fn go_dockerfile(ecosystem: &Ecosystem, analysis: &RepoAnalysis) -> Option<String> {
+    let package = match ecosystem.entrypoints.first()?.as_str() {
+        "main.go" => ".".to_string(),
+        command => format!("./{}", command),
+    };
+    let version = version_prefix(ecosystem.runtime_version.as_deref(), 2).unwrap_or_else(|| GO_VERSION.to_string());
+    let mut modules = vec!["go.mod".to_string()];
+    modules.extend(ecosystem.lockfiles.iter().cloned());
+
+    Some(format!(
+        "FROM golang:{version} AS build\n\
+        WORKDIR /src\n\
+        COPY {modules} ./\n\
+        RUN go mod download\n\
+        COPY . .\n\
+        RUN CGO_ENABLED=0 go build -o /out/app {package}\n\
+        \n\
+        FROM gcr.io/distroless/static-debian12:nonroot\n\
+        COPY --from=build /out/app /app\n\
+        {expose}\
+        ENTRYPOINT [\"/app\"]\n",
+        version = version,
+        modules = modules.join(" "),
+        package = package,
+        expose = expose_lines(analysis),
+    ))
+}
+
+// Default ports of common web frameworks, matched against the dependency names
+const FRAMEWORK_PORTS: [(&str, u16); 9] = [
+    ("flask", 5000),
+    ("django", 8000),
+    ("fastapi", 8000),
+    ("uvicorn", 8000),
+    ("gunicorn", 8000),
+    ("streamlit", 8501),
+    ("express", 3000),
+    ("next", 3000),
+    ("actix-web", 8080),
+];
+
+// Analysis built from the manifests alone, used instead of the documentation agent with --no-llm
+pub fn heuristic_analysis(ecosystems: &[Ecosystem]) -> RepoAnalysis {
+    let Some(main) = ecosystems.first() else {
+        return RepoAnalysis::default();
+    };
+    let mut analysis = RepoAnalysis {
+        runtime: Runtime { language: main.language.clone(), version: main.runtime_version.clone() },
+        ..RepoAnalysis::default()
+    };
+
+    for ecosystem in ecosystems {
+        analysis.prerequisites.push(format!("{} ({})", ecosystem.language, ecosystem.package_manager));
+        let step = match ecosystem.package_manager.as_str() {
+            "npm" if has_lockfile(ecosystem, "package-lock.json") => "npm ci".to_string(),
+            "pip" if ecosystem.manifest == "requirements.txt" => "pip install -r requirements.txt".to_string(),
+            "pip" => "pip install .".to_string(),
+            "cargo" => "cargo build --release".to_string(),
+            "go" => "go build ./...".to_string(),
+            "maven" => "mvn package".to_string(),
+            "gradle" => "gradle build".to_string(),
+            "bundler" => "bundle install".to_string(),
+            manager => format!("{} install", manager),
+        };
+        analysis.install_steps.push(step);
+        for (framework, port) in FRAMEWORK_PORTS {
+            if ecosystem.dependencies.iter().any(|dep| dep == framework) && !analysis.exposed_ports.contains(&port) {
+                analysis.exposed_ports.push(port);
+            }
+        }
+    }
+    analysis.entrypoint = start_command(main, &analysis);
+    analysis
+}
@@ -0,0 +1,155 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use git2::{Oid, Repository};
+use rusqlite::Connection;
+
+use crate::db::{delete_repository, get_imported_modules, initialize_db, latest_repository_id, set_repository_commit};
+use crate::discovery::{is_docker_file, DockerSelection};
+use crate::lifecycle::Resources;
+use crate::repo::parse_repo;
+use crate::services::ENV_EXAMPLES;
+use crate::source::{changed_files, follow_branch, update_submodules, Metadata};
+use crate::{generate_scripts, RunOptions};
+
+// Files the ecosystem and service detection read, besides the example env files
+const MANIFESTS: [&str; 28] = [
+    "package.json", "package-lock.json", "yarn.lock", "pnpm-lock.yaml", "bun.lockb", "bun.lock", "tsconfig.json",
+    "pyproject.toml", "requirements.txt", "setup.py", "runtime.txt", "poetry.lock", "Pipfile.lock", "uv.lock", "pdm.lock",
+    "Cargo.toml", "go.mod", "pom.xml", "build.gradle", "build.gradle.kts", "Gemfile", "composer.json", "composer.lock",
+    "schema.prisma", "database.yml", "alembic.ini", "ormconfig.json", "knexfile.js",
+];
+
+// Changed files, by the stage that reads them
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub docs: Vec<String>, // Markdown, read by the analysis agent
+    pub manifests: Vec<String>, // Manifests, lockfiles, env examples and ORM configs
+    pub docker_files: Vec<String>, // Dockerfiles and compose files
+    pub code: Vec<String>, // Code parsed into the database
+}
+
+impl Changes {
+    pub fn from_paths(paths: &[String]) -> Self {
+        let mut changes = Changes::default();
+        for path in paths {
+            let name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            if path.ends_with(".md") {
+                changes.docs.push(path.clone());
+            }
+            if MANIFESTS.contains(&name.as_str()) || ENV_EXAMPLES.contains(&name.as_str()) || name == "settings.py" {
+                changes.manifests.push(path.clone());
+            }
+            if is_docker_file(&name) {
+                changes.docker_files.push(path.clone());
+            }
+            if path.ends_with(".py") {
+                changes.code.push(path.clone());
+            }
+        }
+        changes
+    }
+
+    // Whether the agents have to run again
+    pub fn affect_analysis(&self) -> bool {
+        !self.docs.is_empty() || !self.manifests.is_empty() || !self.docker_files.is_empty()
+    }
+}
+
+// The stages an update ran again
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Refreshed {
+    pub commit: Option<String>, // Commit the repository is now at
+    pub parsed: bool,
+    pub analyzed: bool,
+}
+
+// Bring an installed repository up to date: fetch and fast-forward its clone (local directories are
+// taken as they are), then parse the code and run the agents again, each only when the files they
+// read changed since the analyzed commit
+pub fn update_repo(key: &str, options: &RunOptions) -> Result<Refreshed, Box<dyn Error>> {
+    let scripts_path = Path::new("scripts").join(key);
+    let mut metadata = Metadata::load(&scripts_path).unwrap_or_default();
+    let clone_path = Path::new("source").join(key);
+    let local_path = if clone_path.is_dir() {
+        clone_path
+    } else if Path::new(&metadata.source).is_dir() {
+        PathBuf::from(&metadata.source)
+    } else {
+        return Err(format!("The code of '{}' is gone, run it again.", key).into());
+    };
+    let repo = Repository::open(&local_path)
+        .map_err(|_| format!("{} is not a git repository, run it again to analyze it anew.", local_path.display()))?;
+
+    // With the options it was run with. Shallow and sparse clones stay so through their git config.
+    let mut options = RunOptions {
+        git_ref: metadata.git_ref.clone(),
+        depth: metadata.depth,
+        full_history: metadata.full_history,
+        sparse: metadata.sparse,
+        submodules: metadata.submodules,
+        ..options.clone()
+    };
+
+    println!("Updating '{}'...", key);
+    if local_path.starts_with("source") && follow_branch(&repo, metadata.git_ref.as_deref())?.is_none() {
+        println!("Pinned to '{}', not fetching.", metadata.git_ref.as_deref().unwrap_or("a commit"));
+    }
+    let head = repo.head()?.peel_to_commit()?.id();
+    let recorded = metadata.commit.as_deref().and_then(|commit| Oid::from_str(commit).ok());
+    if recorded == Some(head) {
+        println!("Already up to date at {}.", head);
+        return Ok(Refreshed { commit: Some(head.to_string()), ..Refreshed::default() });
+    }
+    if options.submodules {
+        update_submodules(&repo)?;
+    }
+    // Everything runs again when the analyzed commit is unknown
+    let changes = match recorded {
+        Some(recorded) => {
+            println!("Moved from {} to {}.", recorded, head);
+            Some(Changes::from_paths(&changed_files(&repo, recorded, head)?))
+        }
+        None => None,
+    };
+    metadata.commit = Some(head.to_string());
+
+    let conn = Connection::open("autocontain.db")?;
+    initialize_db(&conn)?;
+    let previous = latest_repository_id(&conn, key)?;
+    let previous_imports = match previous {
+        Some(repo_id) => get_imported_modules(&conn, repo_id)?,
+        None => Vec::new(),
+    };
+    let parse = previous.is_none() || changes.as_ref().is_none_or(|changes| !changes.code.is_empty());
+    let imports = if parse {
+        println!("Parsing the code again.");
+        delete_repository(&conn, key)?;
+        let repo_id = parse_repo(key, local_path.to_string_lossy().as_ref(), &metadata, &conn);
+        get_imported_modules(&conn, repo_id)?
+    } else {
+        set_repository_commit(&conn, key, &head.to_string())?;
+        previous_imports.clone()
+    };
+
+    // The services detected depend on the modules the code imports
+    let analyze = changes.as_ref().is_none_or(Changes::affect_analysis) || imports != previous_imports;
+    if analyze {
+        println!("Running the agents again.");
+        // Keep the Dockerfile and compose file chosen before, if they are still there
+        if let Some(selection) = DockerSelection::load(&scripts_path) {
+            let existing = |path: Option<PathBuf>| path.filter(|path| local_path.join(path).is_file()).map(|path| path.to_string_lossy().to_string());
+            options.dockerfile = options.dockerfile.or(existing(selection.dockerfile));
+            options.compose = options.compose.or(existing(selection.compose));
+        }
+        generate_scripts(&local_path, &scripts_path, &imports, &options)?;
+    } else {
+        println!("Documentation, manifests and Docker files are unchanged, the analysis is kept.");
+    }
+    metadata.save(&scripts_path)?;
+
+    if Resources::load(&scripts_path).is_some_and(|resources| !resources.is_empty()) {
+        println!("The installed containers still run the previous version, install it again from the `run` menu to rebuild the image and replace them.");
+    }
+    Ok(Refreshed { commit: metadata.commit, parsed: parse, analyzed: analyze })
+}
@@ -1,24 +1,67 @@
 use std::path::Path;
-use std::io::{self, BufReader, BufRead};
+use std::io::{self, Write};
 use std::process::{self, Command, Stdio};
 use std::error::Error;
 use std::fs;
 
-use serde_json::{self, json};
-use reqwest::blocking::Client;
+use crate::budget::record_usage;
+use crate::cache;
+use crate::config::LLM_MODE;
+use crate::interrupt::{is_cancelled, RequestGuard};
+use crate::llm::{ChatRequest, LlmError, Usage, PROVIDER};
 
-use crate::config::OPENAI_API_KEY;
+// Streamed JSON answers print one progress dot per this many tokens
+const PROGRESS_TOKENS: usize = 20;
 
 // Print out the program usage then exit
 pub fn print_usage_and_exit() {
     eprintln!("Usage:");
-    eprintln!(" cargo run -- <github_repo_link> [--persist] [--depth]");
-    eprintln!(" cargo run -- rm <repo_link_or_name>");
+    eprintln!(" cargo run -- run <git_url|gh:owner/repo|local_dir> [--ref <branch|tag|sha>] [--full-history] [--sparse] [--submodules] [--persist] [--depth=<n>] [--no-cache] [--repair-rounds=<n>] [--fix-build[=<n>]] [--no-llm] [--dockerfile=<path>] [--compose=<path>] [--yes] [--dry-run]");
+    eprintln!(" cargo run -- rm <repo_link_or_name> [--keep-images]");
+    eprintln!(" cargo run -- update <repo_name>|--all [--no-llm]");
+    eprintln!(" cargo run -- list");
+    eprintln!(" cargo run -- status");
+    eprintln!(" cargo run -- logs <repo_name> [--follow]");
+    eprintln!(" cargo run -- stop|start|down <repo_name>");
     process::exit(1);
 }
 
 // Execute run.sh to install docker container
 pub fn run_script(script_path: &Path) -> io::Result<()> {
+    #[cfg(not(target_os = "windows"))]
+    return run_script_unix(script_path);
+    #[cfg(target_os = "windows")]
+    return run_script_windows(script_path);
+}
+
+// Run the script as a whole so multi-line constructs, '\' continuations, variables and 'cd' work.
+// bash when the shebang asks for it, sh otherwise.
+#[cfg(not(target_os = "windows"))]
+fn run_script_unix(script_path: &Path) -> io::Result<()> {
+    let content = fs::read_to_string(script_path)?;
+    let shebang = content.lines().next().filter(|line| line.starts_with("#!")).unwrap_or_default();
+    let shell = if shebang.contains("bash") { "bash" } else { "sh" };
+
+    println!("Executing {} with {}", script_path.display(), shell);
+    let status = Command::new(shell)
+        .arg(script_path)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .inspect_err(|e| eprintln!("Failed to execute script: {}", e))?;
+
+    if !status.success() {
+        eprintln!("Script exited with status: {}", status);
+        return Err(io::Error::other("Docker commands failed"));
+    }
+    Ok(())
+}
+
+// cmd has no equivalent of running a sh script, so each line is executed on its own
+#[cfg(target_os = "windows")]
+fn run_script_windows(script_path: &Path) -> io::Result<()> {
+    use std::io::{BufRead, BufReader};
+
     let file = fs::File::open(script_path)?;
     let reader = BufReader::new(file);
 
@@ -33,8 +76,6 @@ pub fn run_script(script_path: &Path) -> io::Result<()> {
 
         println!("Executing command: {}", &command);
 
-        // Currently only on Windows
-        #[cfg(target_os = "windows")]
         let status = Command::new("cmd")
             .arg("/C")
             .arg(&command)
@@ -46,7 +87,7 @@ pub fn run_script(script_path: &Path) -> io::Result<()> {
             Ok(status) if status.success() => continue,
             Ok(status) => {
                 eprintln!("Command exited with status: {}", status);
-                return Err(io::Error::new(io::ErrorKind::Other, "Docker commands failed"));
+                return Err(io::Error::other("Docker commands failed"));
             }
             Err(e) => {
                 eprintln!("Failed to execute command: {}", e);
@@ -58,36 +99,121 @@ pub fn run_script(script_path: &Path) -> io::Result<()> {
     Ok(())
 }
 
-// OpenAI request function general format
+// LLM request function general format, dispatched to the provider selected in .env.
+// In live mode responses are served from and saved to the on-disk cache.
 pub fn send_openai_request(
+    agent: &str,
+    model_name: &str,
+    messages: &[serde_json::Value],
+    temperature: f64,
+    max_tokens: u32,
+) -> Result<String, Box<dyn Error>> {
+    let request = ChatRequest {
+        agent,
+        model: model_name,
+        messages,
+        temperature,
+        max_tokens,
+        json_schema: None,
+    };
+    send_request(&request, None)
+}
+
+// Same as send_openai_request, but prints the answer to the terminal while it is generated
+pub fn stream_openai_request(
+    agent: &str,
+    model_name: &str,
+    messages: &[serde_json::Value],
+    temperature: f64,
+    max_tokens: u32,
+) -> Result<String, Box<dyn Error>> {
+    let request = ChatRequest {
+        agent,
+        model: model_name,
+        messages,
+        temperature,
+        max_tokens,
+        json_schema: None,
+    };
+    stream_to_stdout(&request)
+}
+
+// Streamed request whose answer is constrained to a JSON schema. The raw JSON is of no use to the
+// reader, so only a dot per few tokens is shown to tell the request is progressing.
+pub fn stream_json_request(
+    agent: &str,
     model_name: &str,
     messages: &[serde_json::Value],
     temperature: f64,
     max_tokens: u32,
+    json_schema: &serde_json::Value,
 ) -> Result<String, Box<dyn Error>> {
-    let client = Client::new();
-
-    let response = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", OPENAI_API_KEY.to_string()))
-        .json(&json!({
-            "model": model_name,
-            "messages": messages,
-            "temperature": temperature,
-            "max_tokens": max_tokens
-        }))
-        .send()?
-        .json::<serde_json::Value>()?;
-
-    // Check if the response contains an error
-    if let Some(error) = response.get("error") {
-        println!("OpenAI API Error: {}", error["message"].as_str().unwrap_or("Unknown error"));
-        return Err(Box::new(io::Error::new(
-            io::ErrorKind::Other,
-            "OpenAI API returned an error",
-        )));
+    let request = ChatRequest {
+        agent,
+        model: model_name,
+        messages,
+        temperature,
+        max_tokens,
+        json_schema: Some(json_schema),
+    };
+    let mut received = 0;
+    let mut print_progress = |_: &str| {
+        received += 1;
+        if received % PROGRESS_TOKENS == 0 {
+            print!(".");
+            io::stdout().flush().ok();
+        }
+    };
+    let result = send_request(&request, Some(&mut print_progress));
+    println!();
+    result
+}
+
+fn stream_to_stdout(request: &ChatRequest) -> Result<String, Box<dyn Error>> {
+    let mut print_token = |token: &str| {
+        print!("{}", token);
+        io::stdout().flush().ok();
+    };
+    let result = send_request(request, Some(&mut print_token));
+    println!();
+    result
+}
+
+fn send_request(request: &ChatRequest, on_token: Option<&mut dyn FnMut(&str)>) -> Result<String, Box<dyn Error>> {
+    let provider = PROVIDER.as_ref().map_err(|e| e.clone())?;
+    let agent = request.agent;
+
+    // Record and replay modes must always reach their provider
+    let use_cache = LLM_MODE.as_str() == "live";
+    if use_cache {
+        if let Some(response) = cache::lookup(provider.name(), request) {
+            println!("Using cached response for '{}'.", agent);
+            record_usage(agent, Usage::default(), true);
+            if let Some(on_token) = on_token {
+                on_token(&response);
+            }
+            return Ok(response);
+        }
     }
 
-    // Extract and return the assistant's response content
-    Ok(response["choices"][0]["message"]["content"].as_str().unwrap_or("").to_string())
-}
\ No newline at end of file
+    // Ctrl-C cancels this request instead of exiting while the guard is alive
+    let _guard = RequestGuard::begin();
+    let result = match on_token {
+        Some(on_token) => provider.stream(request, on_token),
+        None => provider.complete(request),
+    };
+    // A blocking request cannot be interrupted, but its answer is dropped once it returns
+    let completion = match result {
+        Ok(_) if is_cancelled() => Err(LlmError::Cancelled.into()),
+        other => other,
+    }.map_err(|e| {
+        println!("{} request failed: {}", provider.name(), e);
+        e
+    })?;
+
+    record_usage(agent, completion.usage, false);
+    if use_cache {
+        cache::store(provider.name(), request, &completion.content);
+    }
+    Ok(completion.content)
+}
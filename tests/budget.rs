@@ -0,0 +1,135 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use autocontain::budget::{document_rank, estimate_tokens, fit_documents, split_into_chunks};
+
+// Condensing asks the summary agent, answered from tests/fixtures/llm
+fn use_replay_fixtures() {
+    env::set_var("LLM_MODE", "replay");
+    env::set_var(
+        "LLM_FIXTURES_DIR",
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/llm"),
+    );
+}
+
+fn summary_fixture() -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/llm/documentation_summary/default.json");
+    let fixture: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    fixture["response"].as_str().unwrap().to_string()
+}
+
+fn document(name: &str, content: &str) -> (PathBuf, String) {
+    (Path::new("/repo").join(name), content.to_string())
+}
+
+fn section(name: &str, content: &str) -> String {
+    format!("--- {} ---\n{}\n\n", name, content)
+}
+
+#[test]
+fn documents_are_ranked_by_usefulness() {
+    let rank = |path: &str| document_rank(Path::new(path));
+    assert_eq!(rank("README.md"), 0);
+    assert_eq!(rank("readme.rst"), 0);
+    assert_eq!(rank("INSTALL.md"), 1);
+    assert_eq!(rank("docs/setup.md"), 1);
+    assert_eq!(rank("GETTING_STARTED.md"), 1);
+    assert_eq!(rank("CONTRIBUTING.md"), 2);
+    assert_eq!(rank("docs/usage.md"), 3);
+    // Only the README at the root comes first
+    assert_eq!(rank("docs/README.md"), 3);
+    assert_eq!(rank("Doc/api.md"), 3);
+    assert_eq!(rank("CHANGELOG.md"), 4);
+    assert_eq!(rank("src/notes.md"), 5);
+    assert_eq!(rank("examples/README.md"), 5);
+}
+
+#[test]
+fn documents_within_the_budget_are_kept_whole_in_rank_order() {
+    let documents = vec![
+        document("src/notes.md", "Internal notes."),
+        document("CHANGELOG.md", "## 1.0\nFirst release."),
+        document("docs/usage.md", "Call the API on port 5000."),
+        document("CONTRIBUTING.md", "Open a pull request."),
+        document("INSTALL.md", "pip install -r requirements.txt"),
+        document("README.md", "# App\nA Flask app."),
+    ];
+
+    let merged = fit_documents(Path::new("/repo"), documents, 10_000).unwrap();
+    let expected = [
+        section("README.md", "# App\nA Flask app."),
+        section("INSTALL.md", "pip install -r requirements.txt"),
+        section("CONTRIBUTING.md", "Open a pull request."),
+        section("docs/usage.md", "Call the API on port 5000."),
+        section("CHANGELOG.md", "## 1.0\nFirst release."),
+        section("src/notes.md", "Internal notes."),
+    ].concat();
+    assert_eq!(merged, expected);
+}
+
+#[test]
+fn chunks_end_on_line_boundaries_within_the_chunk_size() {
+    let line = "x".repeat(400);
+    let text = format!("{}\n", vec![line.as_str(); 50].join("\n"));
+
+    let chunks = split_into_chunks(&text);
+    assert_eq!(chunks.len(), 3);
+    for chunk in &chunks {
+        assert!(estimate_tokens(chunk) <= 2000, "chunk of {} tokens", estimate_tokens(chunk));
+        assert!(chunk.ends_with('\n'));
+        assert!(chunk.lines().all(|chunk_line| chunk_line == line));
+    }
+    assert_eq!(chunks.concat(), text);
+
+    // A line longer than a chunk is cut to fit
+    let chunks = split_into_chunks(&format!("short\n{}\nshort\n", "y".repeat(10_000)));
+    assert_eq!(chunks, vec!["short\n".to_string(), format!("{}\n", "y".repeat(8000)), "short\n".to_string()]);
+}
+
+#[test]
+fn documents_over_the_budget_are_summarized() {
+    use_replay_fixtures();
+    let readme = "# App\nA Flask app.";
+    let guide = "Run `flask run` to start the server.\n".repeat(300);
+    let documents = vec![document("docs/guide.md", &guide), document("README.md", readme)];
+
+    let merged = fit_documents(Path::new("/repo"), documents, 2000).unwrap();
+    // The guide is split in two chunks, summarized one by one; both summaries fit the budget
+    let expected = format!(
+        "{}--- Summary of remaining documentation ---\n{}\n\n{}",
+        section("README.md", readme),
+        summary_fixture(),
+        summary_fixture()
+    );
+    assert_eq!(merged, expected);
+}
+
+#[test]
+fn summaries_are_truncated_to_what_is_left_of_the_budget() {
+    use_replay_fixtures();
+    let readme = "# App\nA Flask app.";
+    let guide = "Run `flask run` to start the server.\n".repeat(300);
+    let documents = vec![document("README.md", readme), document("docs/guide.md", &guide)];
+    // 200 tokens are left once the README is in, less than the summary
+    let budget = estimate_tokens(&section("README.md", readme)) + 200;
+    assert!(estimate_tokens(&summary_fixture()) > 200);
+
+    let merged = fit_documents(Path::new("/repo"), documents, budget).unwrap();
+    let summary: String = summary_fixture().chars().take(800).collect();
+    let expected = format!(
+        "{}--- Summary of remaining documentation ---\n{}",
+        section("README.md", readme),
+        summary
+    );
+    assert_eq!(merged, expected);
+}
+
+#[test]
+fn documents_are_dropped_when_too_little_budget_is_left() {
+    let readme = "# App\nA Flask app.";
+    let documents = vec![document("README.md", readme), document("docs/guide.md", &"Details.\n".repeat(500))];
+    let budget = estimate_tokens(&section("README.md", readme)) + 100;
+
+    let merged = fit_documents(Path::new("/repo"), documents, budget).unwrap();
+    assert_eq!(merged, section("README.md", readme));
+}
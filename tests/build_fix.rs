@@ -0,0 +1,45 @@
+#![cfg(unix)]
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use autocontain::build::{build_with_fixes, image_tag, project_name};
+use autocontain::engine::ContainerEngine;
+
+#[test]
+fn failed_build_is_fixed_by_the_agent_and_retried() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    let engine_log = scripts.path().join("engine.log");
+    env::set_var("LLM_MODE", "replay");
+    env::set_var("LLM_FIXTURES_DIR", fixtures.join("llm"));
+    env::set_var("FAKE_ENGINE_LOG", &engine_log);
+
+    let broken = "FROM python:3.11-slim\nWORKDIR /app\nRUN pip install flask==99\n";
+    fs::write(scripts.path().join("Dockerfile"), broken).unwrap();
+
+    let engine = ContainerEngine::new(fixtures.join("fake-engine.sh").to_str().unwrap());
+    assert!(build_with_fixes(repo.path(), scripts.path(), &engine, 3).unwrap());
+
+    // Two builds: the broken Dockerfile, then the agent's correction
+    let builds = fs::read_to_string(&engine_log).unwrap();
+    assert_eq!(builds.lines().count(), 2);
+    let attempts = scripts.path().join("build_attempts");
+    assert_eq!(fs::read_to_string(attempts.join("attempt-1.Dockerfile")).unwrap(), broken);
+    assert!(fs::read_to_string(attempts.join("attempt-1.log")).unwrap().contains("No matching distribution"));
+    assert!(fs::read_to_string(attempts.join("attempt-2.log")).unwrap().contains("DONE"));
+
+    // The corrected Dockerfile replaces the one run.sh builds
+    let dockerfile = fs::read_to_string(scripts.path().join("Dockerfile")).unwrap();
+    assert!(dockerfile.contains("flask==3.0.3"));
+}
+
+#[test]
+fn compose_projects_are_named_without_dots() {
+    let scripts = Path::new("scripts/github.com/alice/My.App");
+    assert_eq!(image_tag(scripts), "autocontain-github.com-alice-my.app");
+    assert_eq!(project_name(scripts), "autocontain-github-com-alice-my-app");
+    assert!(project_name(scripts).chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_'));
+}
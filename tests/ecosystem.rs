@@ -0,0 +1,125 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use autocontain::{generate_scripts, RunOptions};
+use serde_json::{json, Value};
+
+// What --no-llm makes of one of the repositories in tests/fixtures/manifests: the detected
+// ecosystems, the analysis, and the templated Dockerfile or the error when no template fits
+struct Generated {
+    ecosystems: Value,
+    analysis: Value,
+    dockerfile: Result<String, String>,
+}
+
+fn generate(fixture: &str) -> Generated {
+    env::set_var("AUTOCONTAIN_ENGINE", "docker");
+    let repo = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/manifests").join(fixture);
+    let scripts = tempfile::tempdir().unwrap();
+    let options = RunOptions { no_llm: true, ..RunOptions::default() };
+
+    let result = generate_scripts(&repo, scripts.path(), &[], &options);
+
+    let read_json = |file: &str| serde_json::from_str(&fs::read_to_string(scripts.path().join(file)).unwrap()).unwrap();
+    Generated {
+        ecosystems: read_json("ecosystem.json"),
+        analysis: read_json("analysis.json"),
+        dockerfile: result
+            .map(|_| fs::read_to_string(scripts.path().join("Dockerfile")).unwrap())
+            .map_err(|e| e.to_string()),
+    }
+}
+
+const NO_TEMPLATE: &str = "No Dockerfile template matches this repository; run without --no-llm to generate one.";
+
+#[test]
+fn node_project_uses_the_node_template() {
+    let generated = generate("node");
+
+    let ecosystem = &generated.ecosystems[0];
+    assert_eq!(ecosystem["language"], "JavaScript");
+    assert_eq!(ecosystem["package_manager"], "npm");
+    assert_eq!(ecosystem["runtime_version"], ">=20");
+    assert_eq!(ecosystem["entrypoints"], json!(["server.js"]));
+    assert_eq!(ecosystem["lockfiles"], json!(["package-lock.json"]));
+    assert_eq!(generated.analysis["entrypoint"], "npm start");
+    let dockerfile = generated.dockerfile.unwrap();
+    assert!(dockerfile.starts_with("FROM node:20-slim AS build\n"));
+    assert!(dockerfile.contains("COPY package.json package-lock.json ./\nRUN npm ci\n"));
+    assert!(dockerfile.contains("EXPOSE 3000\nCMD [\"npm\",\"start\"]\n"));
+}
+
+#[test]
+fn rust_project_uses_the_rust_template() {
+    let generated = generate("rust");
+
+    let ecosystem = &generated.ecosystems[0];
+    assert_eq!(ecosystem["language"], "Rust");
+    assert_eq!(ecosystem["package_manager"], "cargo");
+    assert_eq!(ecosystem["runtime_version"], "1.75");
+    assert_eq!(ecosystem["entrypoints"], json!(["shop-server"]));
+    assert_eq!(generated.analysis["entrypoint"], "shop-server");
+    let dockerfile = generated.dockerfile.unwrap();
+    assert!(dockerfile.starts_with("FROM rust:1.75-slim AS build\n"));
+    assert!(dockerfile.contains("RUN cargo build --release --bin shop-server\n"));
+    assert!(dockerfile.contains("EXPOSE 8080\nCMD [\"shop-server\"]\n"));
+}
+
+#[test]
+fn go_project_uses_the_go_template() {
+    let generated = generate("go");
+
+    let ecosystem = &generated.ecosystems[0];
+    assert_eq!(ecosystem["language"], "Go");
+    assert_eq!(ecosystem["package_manager"], "go");
+    assert_eq!(ecosystem["runtime_version"], "1.21");
+    assert_eq!(ecosystem["entrypoints"], json!(["cmd/api"]));
+    assert_eq!(ecosystem["dependencies"], json!(["github.com/gin-gonic/gin"]));
+    assert_eq!(generated.analysis["entrypoint"], "/app");
+    let dockerfile = generated.dockerfile.unwrap();
+    assert!(dockerfile.starts_with("FROM golang:1.21 AS build\n"));
+    assert!(dockerfile.contains("RUN CGO_ENABLED=0 go build -o /out/app ./cmd/api\n"));
+    assert!(dockerfile.ends_with("ENTRYPOINT [\"/app\"]\n"));
+}
+
+#[test]
+fn java_project_is_detected_but_has_no_template() {
+    let generated = generate("java");
+
+    let ecosystem = &generated.ecosystems[0];
+    assert_eq!(ecosystem["language"], "Java");
+    assert_eq!(ecosystem["package_manager"], "maven");
+    assert_eq!(ecosystem["runtime_version"], "17");
+    assert_eq!(ecosystem["entrypoints"], json!(["com.example.shop.App"]));
+    assert_eq!(generated.analysis["install_steps"], json!(["mvn package"]));
+    assert_eq!(generated.dockerfile.unwrap_err(), NO_TEMPLATE);
+}
+
+#[test]
+fn ruby_project_is_detected_but_has_no_template() {
+    let generated = generate("ruby");
+
+    let ecosystem = &generated.ecosystems[0];
+    assert_eq!(ecosystem["language"], "Ruby");
+    assert_eq!(ecosystem["package_manager"], "bundler");
+    assert_eq!(ecosystem["runtime_version"], "3.2.2");
+    assert_eq!(ecosystem["entrypoints"], json!(["config.ru"]));
+    assert_eq!(ecosystem["dependencies"], json!(["sinatra", "puma"]));
+    assert_eq!(generated.analysis["install_steps"], json!(["bundle install"]));
+    assert_eq!(generated.dockerfile.unwrap_err(), NO_TEMPLATE);
+}
+
+#[test]
+fn php_project_is_detected_but_has_no_template() {
+    let generated = generate("php");
+
+    let ecosystem = &generated.ecosystems[0];
+    assert_eq!(ecosystem["language"], "PHP");
+    assert_eq!(ecosystem["package_manager"], "composer");
+    assert_eq!(ecosystem["runtime_version"], "^8.2");
+    assert_eq!(ecosystem["entrypoints"], json!(["public/index.php"]));
+    assert_eq!(ecosystem["dependencies"], json!(["slim/slim"]));
+    assert_eq!(generated.analysis["install_steps"], json!(["composer install"]));
+    assert_eq!(generated.dockerfile.unwrap_err(), NO_TEMPLATE);
+}
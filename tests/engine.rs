@@ -0,0 +1,133 @@
+#![cfg(unix)]
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Mutex;
+
+use autocontain::engine::{failing_step_log, ContainerEngine, EngineKind};
+
+// Detection reads PATH, so the tests take turns
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+fn write_executable(dir: &Path, name: &str, content: &str) {
+    let path = dir.join(name);
+    fs::write(&path, content).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+// Detect with PATH limited to `dir`
+fn detect_in(dir: &Path) -> ContainerEngine {
+    let path = env::var("PATH").unwrap_or_default();
+    env::set_var("PATH", dir);
+    let engine = ContainerEngine::detect();
+    env::set_var("PATH", path);
+    engine
+}
+
+#[test]
+fn docker_is_preferred() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let bin = tempfile::tempdir().unwrap();
+    write_executable(bin.path(), "docker", "#!/bin/sh\nexit 0\n");
+    write_executable(bin.path(), "podman", "#!/bin/sh\nexit 0\n");
+
+    let engine = detect_in(bin.path());
+    assert_eq!(engine.kind, EngineKind::Docker);
+    assert_eq!(engine.binary, "docker");
+    assert_eq!(engine.compose, ["docker", "compose"]);
+    assert!(!engine.rootless);
+}
+
+#[test]
+fn rootless_podman_falls_back_to_podman_compose() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let bin = tempfile::tempdir().unwrap();
+    // No `podman compose` subcommand, as in podman 3
+    write_executable(bin.path(), "podman", "#!/bin/sh\n[ \"$1\" = info ] && { echo true; exit 0; }\nexit 125\n");
+    write_executable(bin.path(), "podman-compose", "#!/bin/sh\nexit 0\n");
+
+    let engine = detect_in(bin.path());
+    assert_eq!(engine.kind, EngineKind::Podman);
+    assert!(engine.rootless);
+    assert_eq!(engine.compose, ["podman-compose"]);
+    assert_eq!(engine.describe(), "podman (rootless) with podman-compose");
+}
+
+#[test]
+fn podman_compose_subcommand_is_used_when_available() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let bin = tempfile::tempdir().unwrap();
+    write_executable(bin.path(), "podman", "#!/bin/sh\n[ \"$1\" = info ] && echo false\nexit 0\n");
+    write_executable(bin.path(), "podman-compose", "#!/bin/sh\nexit 0\n");
+
+    let engine = detect_in(bin.path());
+    assert!(!engine.rootless);
+    assert_eq!(engine.compose, ["podman", "compose"]);
+}
+
+#[test]
+fn nerdctl_is_found_when_alone() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let bin = tempfile::tempdir().unwrap();
+    write_executable(bin.path(), "nerdctl", "#!/bin/sh\nexit 0\n");
+
+    let engine = detect_in(bin.path());
+    assert_eq!(engine.kind, EngineKind::Nerdctl);
+    assert_eq!(engine.compose, ["nerdctl", "compose"]);
+}
+
+#[test]
+fn detection_runs_once_per_process() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let docker = tempfile::tempdir().unwrap();
+    write_executable(docker.path(), "docker", "#!/bin/sh\nexit 0\n");
+    let podman = tempfile::tempdir().unwrap();
+    write_executable(podman.path(), "podman", "#!/bin/sh\nexit 0\n");
+    let path = env::var("PATH").unwrap_or_default();
+
+    env::set_var("PATH", docker.path());
+    let first = ContainerEngine::detected();
+    // Later changes to PATH do not trigger another detection
+    env::set_var("PATH", podman.path());
+    let second = ContainerEngine::detected();
+    env::set_var("PATH", path);
+
+    assert!(std::ptr::eq(first, second));
+    assert_eq!(second.binary, "docker");
+}
+
+// Build output captured from a failing build, in tests/fixtures/build_logs
+fn build_log(name: &str) -> String {
+    fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/build_logs").join(name)).unwrap()
+}
+
+#[test]
+fn buildkit_log_is_cut_at_the_step_marked_as_failed() {
+    let log = failing_step_log(&build_log("buildkit.log"));
+
+    // Step #10 ran in parallel and started last, but #9 is the one that failed
+    assert!(log.starts_with("#9 [build 4/6] RUN npm ci\n#9 0.412 npm ERR! code EUSAGE\n"), "{}", log);
+    assert!(log.contains("#10 [stage-1 3/4] RUN corepack enable\n"));
+    assert!(log.ends_with("ERROR: failed to solve: process \"/bin/sh -c npm ci\" did not complete successfully: exit code: 1"));
+    assert!(!log.contains("[build 3/6]"));
+}
+
+#[test]
+fn classic_builder_log_is_cut_at_the_last_step() {
+    let log = failing_step_log(&build_log("classic.log"));
+
+    assert!(log.starts_with("Step 4/6 : RUN pip install --no-cache-dir -r requirements.txt\n"), "{}", log);
+    assert!(log.contains("No matching distribution found for flask==9.9"));
+    assert!(!log.contains("Step 3/6"));
+}
+
+#[test]
+fn podman_log_is_cut_at_the_last_step() {
+    let log = failing_step_log(&build_log("podman.log"));
+
+    assert!(log.starts_with("STEP 4/6: RUN pip install --no-cache-dir -r requirements.txt\n"), "{}", log);
+    assert!(log.ends_with("while running runtime: exit status 1"));
+    assert!(!log.contains("STEP 3/6"));
+}
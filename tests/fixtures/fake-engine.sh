@@ -0,0 +1,26 @@
+#!/bin/sh
+# Stand-in for the docker CLI: `build` fails while the Dockerfile still pins flask==99,
+# printing a BuildKit-style log, and succeeds otherwise. Every invocation is appended to
+# $FAKE_ENGINE_LOG when set.
+if [ -n "$FAKE_ENGINE_LOG" ]; then
+    echo "$*" >> "$FAKE_ENGINE_LOG"
+fi
+
+if [ "$1" = "build" ]; then
+    dockerfile="$3"
+    echo "#1 [internal] load build definition from Dockerfile"
+    echo "#1 DONE 0.0s"
+    echo "#5 [2/3] WORKDIR /app"
+    echo "#5 CACHED"
+    if grep -q "flask==99" "$dockerfile"; then
+        echo "#6 [3/3] RUN pip install flask==99"
+        echo "#6 1.204 ERROR: No matching distribution found for flask==99" >&2
+        echo "ERROR: failed to solve: process did not complete successfully: exit code: 1" >&2
+        exit 1
+    fi
+    echo "#6 [3/3] RUN pip install flask==3.0.3"
+    echo "#6 DONE 2.1s"
+    exit 0
+fi
+
+exit 0
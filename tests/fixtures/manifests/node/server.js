@@ -0,0 +1 @@
+require("express")().listen(3000);
@@ -0,0 +1,108 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use autocontain::repo::{installed_repos, matching_repos, migrate_layout, remove_repo};
+use autocontain::source::RepoSource;
+use git2::Repository;
+use rusqlite::Connection;
+
+// The layout lives in the working directory, so the tests take turns changing it
+static CWD_LOCK: Mutex<()> = Mutex::new(());
+
+#[test]
+fn repositories_are_found_by_key_link_or_name() {
+    let _lock = CWD_LOCK.lock().unwrap();
+    let installed = ["github.com/alice/utils", "github.com/bob/utils", "gitlab.com/carol/api"].map(String::from);
+
+    assert_eq!(matching_repos("utils", &installed), ["github.com/alice/utils", "github.com/bob/utils"]);
+    assert_eq!(matching_repos("alice/utils", &installed), ["github.com/alice/utils"]);
+    assert_eq!(matching_repos("github.com/bob/utils", &installed), ["github.com/bob/utils"]);
+    assert_eq!(matching_repos("gh:bob/utils", &installed), ["github.com/bob/utils"]);
+    assert_eq!(matching_repos("https://gitlab.com/carol/api.git", &installed), ["gitlab.com/carol/api"]);
+    assert_eq!(matching_repos("api", &installed), ["gitlab.com/carol/api"]);
+    assert!(matching_repos("tils", &installed).is_empty());
+}
+
+#[test]
+fn old_layouts_are_moved_under_their_key() {
+    let _lock = CWD_LOCK.lock().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let previous = env::current_dir().unwrap();
+    env::set_current_dir(dir.path()).unwrap();
+
+    // A clone kept as 'source/utils', with its scripts, tag and database row
+    let repo = Repository::init("source/utils").unwrap();
+    repo.remote("origin", "https://github.com/alice/utils.git").unwrap();
+    fs::create_dir_all("scripts/utils").unwrap();
+    fs::write("scripts/utils/run.sh", "docker build -t autocontain-utils -f scripts/utils/Dockerfile source/utils\n").unwrap();
+    fs::write("scripts/utils/run_plan.json", r#"{"build_context": "source/utils", "other": "source/utils2"}"#).unwrap();
+    // Analyzed in place: only scripts, with the metadata naming where it came from
+    fs::create_dir_all("scripts/api").unwrap();
+    fs::write("scripts/api/metadata.json", r#"{"source": "git@gitlab.com:carol/api.git", "git_ref": null, "commit": null}"#).unwrap();
+    // Nothing tells where it came from
+    fs::create_dir_all("scripts/orphan").unwrap();
+    fs::write("scripts/orphan/analysis.md", "").unwrap();
+    fs::write("tags.txt", "utils\n").unwrap();
+    let conn = Connection::open("autocontain.db").unwrap();
+    conn.execute("CREATE TABLE repositories (id INTEGER PRIMARY KEY, name TEXT NOT NULL, description TEXT)", []).unwrap();
+    conn.execute("INSERT INTO repositories (name, description) VALUES ('utils', '')", []).unwrap();
+
+    migrate_layout();
+
+    assert!(Path::new("source/github.com/alice/utils/.git").is_dir());
+    assert!(!Path::new("source/utils").exists());
+    assert_eq!(
+        fs::read_to_string("scripts/github.com/alice/utils/run.sh").unwrap(),
+        "docker build -t autocontain-utils -f scripts/github.com/alice/utils/Dockerfile source/github.com/alice/utils\n"
+    );
+    assert_eq!(
+        fs::read_to_string("scripts/github.com/alice/utils/run_plan.json").unwrap(),
+        r#"{"build_context": "source/github.com/alice/utils", "other": "source/utils2"}"#
+    );
+    assert!(Path::new("scripts/gitlab.com/carol/api/metadata.json").is_file());
+    assert!(Path::new("scripts/orphan/analysis.md").is_file());
+    assert_eq!(fs::read_to_string("tags.txt").unwrap().trim(), "github.com/alice/utils");
+    let name: String = conn.query_row("SELECT name FROM repositories", [], |row| row.get(0)).unwrap();
+    assert_eq!(name, "github.com/alice/utils");
+    assert_eq!(installed_repos(), ["github.com/alice/utils", "gitlab.com/carol/api"]);
+
+    // Removing the last repository of a host removes the host folder too
+    remove_repo("github.com/alice/utils", true);
+    assert!(!Path::new("source/github.com").exists());
+    assert!(!Path::new("scripts/github.com").exists());
+    assert!(Path::new("scripts/gitlab.com/carol/api").is_dir());
+
+    env::set_current_dir(previous).unwrap();
+}
+
+#[test]
+fn local_keys_gain_the_hash_of_their_path() {
+    let _lock = CWD_LOCK.lock().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let previous = env::current_dir().unwrap();
+    env::set_current_dir(dir.path()).unwrap();
+
+    // Analyzed in place before local keys had a hash
+    fs::create_dir_all("projects/app").unwrap();
+    let path = fs::canonicalize("projects/app").unwrap();
+    fs::create_dir_all("scripts/local/projects/app").unwrap();
+    let metadata = format!(r#"{{"source": "{}", "git_ref": null, "commit": null}}"#, path.display());
+    fs::write("scripts/local/projects/app/metadata.json", metadata).unwrap();
+    fs::write("scripts/local/projects/app/run.sh", "docker build -f scripts/local/projects/app/Dockerfile .\n").unwrap();
+
+    migrate_layout();
+
+    let key = RepoSource::parse(path.to_str().unwrap()).unwrap().key().to_string();
+    assert_eq!(installed_repos(), [key.as_str()]);
+    assert_eq!(
+        fs::read_to_string(Path::new("scripts").join(&key).join("run.sh")).unwrap(),
+        format!("docker build -f scripts/{}/Dockerfile .\n", key)
+    );
+    // Still found by its name
+    assert_eq!(matching_repos("app", &installed_repos()), [key.as_str()]);
+    assert_eq!(matching_repos("projects/app", &installed_repos()), [key]);
+
+    env::set_current_dir(previous).unwrap();
+}
@@ -0,0 +1,210 @@
+#![cfg(unix)]
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use autocontain::lifecycle::{self, Resources};
+use autocontain::repo::install_repo;
+use autocontain::source::Metadata;
+use autocontain::{generate_scripts, RunOptions};
+
+// The fake docker is configured through the environment, so the tests take turns
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+// Use the fake docker from tests/fixtures/bin, keeping its containers and images in `state`
+fn use_fake_docker(state: &Path) {
+    let bin = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/bin");
+    let path = env::var("PATH").unwrap_or_default();
+    if !path.starts_with(bin.to_str().unwrap()) {
+        env::set_var("PATH", format!("{}:{}", bin.display(), path));
+    }
+    env::set_var("FAKE_DOCKER_LOG", state.join("docker.log"));
+    env::set_var("FAKE_DOCKER_STATE", state);
+}
+
+fn state_file(state: &Path, name: &str) -> String {
+    fs::read_to_string(state.join(name)).unwrap_or_default()
+}
+
+// Generate and install a Python repository with the fake docker
+fn install_python_repo(repo: &Path, scripts: &Path) {
+    fs::write(repo.join("requirements.txt"), "flask\n").unwrap();
+    fs::write(repo.join("app.py"), "").unwrap();
+    fs::write(repo.join("Dockerfile"), "FROM python:3.11-slim\nVOLUME /data\nEXPOSE 5000\nCMD [\"python\", \"app.py\"]\n").unwrap();
+    let options = RunOptions { no_llm: true, yes: true, ..RunOptions::default() };
+    generate_scripts(repo, scripts, &[], &options).unwrap();
+    install_repo(repo, scripts, &options);
+}
+
+#[test]
+fn install_records_only_what_it_created() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let state = tempfile::tempdir().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    use_fake_docker(state.path());
+    fs::write(state.path().join("containers"), "other-app\n").unwrap();
+    fs::write(state.path().join("networks"), "bridge\n").unwrap();
+
+    install_python_repo(repo.path(), scripts.path());
+
+    let resources = Resources::load(scripts.path()).unwrap();
+    assert_eq!(resources.engine, "docker");
+    assert_eq!(resources.containers.len(), 1);
+    assert!(resources.containers[0].starts_with("autocontain-"));
+    assert_eq!(resources.images, [format!("{}:latest", resources.containers[0])]);
+    assert_eq!(resources.volumes, [format!("{}-data", resources.containers[0])]);
+    assert!(resources.networks.is_empty());
+    assert_eq!(resources.labels.len(), 1);
+    assert!(resources.labels[0].starts_with("autocontain.repo="));
+    // The build pulled the base image, which belongs to no repository
+    assert!(state_file(state.path(), "images").lines().any(|image| image == "python:3.11-slim"));
+}
+
+#[test]
+fn install_ignores_what_others_create_meanwhile() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let state = tempfile::tempdir().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    use_fake_docker(state.path());
+    install_python_repo(repo.path(), scripts.path());
+    let resources = Resources::load(scripts.path()).unwrap();
+
+    // Another tool starts a container and creates a volume before the repository is installed again
+    fs::write(state.path().join("containers"), format!("{}\nother-app\n", resources.containers[0])).unwrap();
+    fs::write(state.path().join("volumes"), format!("{}\nother-data\n", resources.volumes[0])).unwrap();
+    install_repo(repo.path(), scripts.path(), &RunOptions { no_llm: true, yes: true, ..RunOptions::default() });
+
+    let again = Resources::load(scripts.path()).unwrap();
+    assert_eq!(again.containers, resources.containers);
+    assert_eq!(again.volumes, resources.volumes);
+}
+
+#[test]
+fn install_after_an_update_rebuilds_and_replaces_the_container() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let state = tempfile::tempdir().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    use_fake_docker(state.path());
+    let analyzed = |commit: &str| Metadata { source: "https://example.com/app.git".to_string(), commit: Some(commit.to_string()), ..Metadata::default() };
+    analyzed("1111111").save(scripts.path()).unwrap();
+    install_python_repo(repo.path(), scripts.path());
+    assert_eq!(Resources::load(scripts.path()).unwrap().commit.as_deref(), Some("1111111"));
+    let options = RunOptions { no_llm: true, yes: true, ..RunOptions::default() };
+
+    // Same commit: the running container is kept
+    fs::remove_file(state.path().join("docker.log")).unwrap();
+    install_repo(repo.path(), scripts.path(), &options);
+    let calls = state_file(state.path(), "docker.log");
+    assert!(!calls.lines().any(|call| call.starts_with("build ") || call.starts_with("rm ") || call.starts_with("run ")));
+
+    // `update` moved to a new commit: built again and replaced, like REBUILD=1
+    analyzed("2222222").save(scripts.path()).unwrap();
+    fs::remove_file(state.path().join("docker.log")).unwrap();
+    install_repo(repo.path(), scripts.path(), &options);
+    let calls = state_file(state.path(), "docker.log");
+    let position = |prefix: &str| calls.lines().position(|call| call.starts_with(prefix)).unwrap_or_else(|| panic!("no {} in\n{}", prefix, calls));
+    assert!(position("rm -f ") < position("build ") && position("build ") < position("run "));
+    assert_eq!(state_file(state.path(), "containers").lines().count(), 1);
+    assert_eq!(Resources::load(scripts.path()).unwrap().commit.as_deref(), Some("2222222"));
+}
+
+#[test]
+fn stop_start_and_down_touch_only_recorded_containers() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let state = tempfile::tempdir().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    use_fake_docker(state.path());
+    fs::write(state.path().join("containers"), "other-app\n").unwrap();
+    install_python_repo(repo.path(), scripts.path());
+    let container = Resources::load(scripts.path()).unwrap().containers[0].clone();
+
+    lifecycle::stop(scripts.path()).unwrap();
+    assert_eq!(state_file(state.path(), "stopped"), format!("{}\n", container));
+    lifecycle::start(scripts.path()).unwrap();
+    assert_eq!(state_file(state.path(), "stopped"), "");
+
+    lifecycle::down(scripts.path()).unwrap();
+    assert_eq!(state_file(state.path(), "containers"), "other-app\n");
+    // Images and volumes stay for a quick reinstall
+    let resources = Resources::load(scripts.path()).unwrap();
+    assert!(resources.containers.is_empty());
+    assert_eq!(resources.images.len(), 1);
+    assert!(lifecycle::start(scripts.path()).is_err());
+}
+
+#[test]
+fn teardown_removes_everything_recorded() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let state = tempfile::tempdir().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    use_fake_docker(state.path());
+    fs::write(state.path().join("images"), "python:3.11-slim\n").unwrap();
+    install_python_repo(repo.path(), scripts.path());
+
+    // The container and the image, 1 MiB each in the fake engine
+    let reclaimed = lifecycle::teardown(scripts.path(), false).unwrap();
+    assert_eq!(reclaimed, 2 * 1024 * 1024);
+    assert_eq!(lifecycle::format_size(reclaimed), "2.0 MB");
+    assert_eq!(state_file(state.path(), "containers"), "");
+    assert_eq!(state_file(state.path(), "volumes"), "");
+    assert_eq!(state_file(state.path(), "images"), "python:3.11-slim\n");
+    assert!(Resources::load(scripts.path()).unwrap().is_empty());
+}
+
+#[test]
+fn teardown_can_keep_images() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let state = tempfile::tempdir().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    use_fake_docker(state.path());
+    install_python_repo(repo.path(), scripts.path());
+
+    assert_eq!(lifecycle::teardown(scripts.path(), true).unwrap(), 1024 * 1024);
+    assert_eq!(state_file(state.path(), "containers"), "");
+    assert!(state_file(state.path(), "images").lines().any(|image| image.starts_with("autocontain-")));
+    assert_eq!(Resources::load(scripts.path()).unwrap().images.len(), 1);
+}
+
+#[test]
+fn teardown_leaves_what_others_created() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let state = tempfile::tempdir().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    use_fake_docker(state.path());
+    install_python_repo(repo.path(), scripts.path());
+    let mut resources = Resources::load(scripts.path()).unwrap();
+    let image = resources.images[0].clone();
+
+    // Recorded before installs were labeled: another tool's container and the pulled base image
+    fs::write(state.path().join("containers"), format!("{}\nother-app\n", resources.containers[0])).unwrap();
+    resources.containers.push("other-app".to_string());
+    resources.images.push("python:3.11-slim".to_string());
+    resources.save(scripts.path()).unwrap();
+    // A container of something else runs the repository's image
+    fs::write(state.path().join("container_images"), format!("{} {}\nother-app {}\n", resources.containers[0], image, image)).unwrap();
+
+    lifecycle::teardown(scripts.path(), false).unwrap();
+
+    assert_eq!(state_file(state.path(), "containers"), "other-app\n");
+    let images = state_file(state.path(), "images");
+    assert!(images.lines().any(|line| line == "python:3.11-slim"));
+    assert!(images.lines().any(|line| line == image));
+    // The image stays recorded so a later teardown removes it once it is free
+    assert_eq!(Resources::load(scripts.path()).unwrap().images, [image]);
+}
+
+#[test]
+fn commands_fail_for_repositories_not_installed() {
+    let scripts = tempfile::tempdir().unwrap();
+    assert!(lifecycle::stop(scripts.path()).is_err());
+    assert!(lifecycle::show_logs(scripts.path(), false).is_err());
+}
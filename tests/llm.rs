@@ -0,0 +1,472 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use autocontain::interrupt::{cancel_request, RequestGuard};
+use autocontain::llm::{live_provider, prompt_hash, AnthropicProvider, ChatRequest, Completion, LlmError, LlmProvider, OpenAiProvider, Usage};
+use autocontain::replay::{RecordingProvider, ReplayProvider};
+use reqwest::StatusCode;
+
+// Cancellation is global, the tests take turns
+static LOCK: Mutex<()> = Mutex::new(());
+
+const COMPLETION: &str = r#"{"choices":[{"message":{"content":"ok"},"finish_reason":"stop"}],"usage":{"prompt_tokens":3,"completion_tokens":1}}"#;
+
+fn http_response(status: &str, headers: &[&str], body: &str) -> String {
+    let mut response = format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nConnection: close\r\n", status);
+    for header in headers {
+        response.push_str(&format!("{}\r\n", header));
+    }
+    response.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+    response
+}
+
+fn sse_response(events: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+        events.len(),
+        events
+    )
+}
+
+// Serve the responses in order, one per connection. Returns the base URL and the raw requests
+// received so far (head and body).
+fn stub_server(responses: Vec<String>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let received = Arc::clone(&requests);
+    thread::spawn(move || {
+        for response in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            // Read the whole request before answering
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                request.push_str(&line);
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8_lossy(&body));
+            received.lock().unwrap().push(request);
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    (base_url, requests)
+}
+
+fn request_count(requests: &Mutex<Vec<String>>) -> usize {
+    requests.lock().unwrap().len()
+}
+
+fn complete(base_url: String) -> Result<String, Box<dyn Error>> {
+    env::set_var("LLM_MAX_RETRIES", "3");
+    let messages = [serde_json::json!({"role": "user", "content": "Hello"})];
+    let request = ChatRequest {
+        agent: "retry_test",
+        model: "test-model",
+        messages: &messages,
+        temperature: 0.0,
+        max_tokens: 10,
+        json_schema: None,
+    };
+    OpenAiProvider::new("openai-compatible", base_url, None)
+        .complete(&request)
+        .map(|completion| completion.content)
+}
+
+// Stream a one-message request, returning the result and the tokens in the order they arrived
+fn stream(provider: &dyn LlmProvider, on_token: impl Fn(&str)) -> (Result<Completion, Box<dyn Error>>, Vec<String>) {
+    let messages = [serde_json::json!({"role": "user", "content": "Hello"})];
+    let request = ChatRequest {
+        agent: "stream_test",
+        model: "test-model",
+        messages: &messages,
+        temperature: 0.0,
+        max_tokens: 10,
+        json_schema: None,
+    };
+    let mut tokens = Vec::new();
+    let result = provider.stream(&request, &mut |token| {
+        on_token(token);
+        tokens.push(token.to_string());
+    });
+    (result, tokens)
+}
+
+fn llm_error(result: Result<String, Box<dyn Error>>) -> LlmError {
+    match result.unwrap_err().downcast::<LlmError>() {
+        Ok(error) => *error,
+        Err(error) => panic!("not an LlmError: {}", error),
+    }
+}
+
+#[test]
+fn server_errors_are_retried_until_the_request_succeeds() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    let unavailable = http_response("503 Service Unavailable", &["Retry-After: 0"], "");
+    let (base_url, requests) = stub_server(vec![
+        unavailable.clone(),
+        unavailable,
+        http_response("200 OK", &[], COMPLETION),
+    ]);
+
+    assert_eq!(complete(base_url).unwrap(), "ok");
+    assert_eq!(request_count(&requests), 3);
+}
+
+#[test]
+fn rate_limits_fail_after_the_last_retry() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    let body = r#"{"error":{"message":"slow down"}}"#;
+    let limited = http_response("429 Too Many Requests", &["Retry-After: 0"], body);
+    let (base_url, requests) = stub_server(vec![limited; 4]);
+
+    let error = llm_error(complete(base_url));
+    assert!(matches!(&error, LlmError::RateLimited(message) if message == "slow down"), "{}", error);
+    assert_eq!(request_count(&requests), 4);
+}
+
+#[test]
+fn failures_are_classified_by_status_and_only_transient_ones_retried() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    let cases = [
+        ("400 Bad Request", r#"{"error":{"message":"unknown model"}}"#),
+        ("401 Unauthorized", r#"{"error":{"message":"invalid api key"}}"#),
+        ("429 Too Many Requests", r#"{"error":{"message":"out of credits","code":"insufficient_quota"}}"#),
+    ];
+    let mut errors = Vec::new();
+    for (status, body) in cases {
+        let (base_url, requests) = stub_server(vec![http_response(status, &["Retry-After: 0"], body)]);
+        errors.push(llm_error(complete(base_url)));
+        assert_eq!(request_count(&requests), 1, "{} was retried", status);
+    }
+
+    assert!(matches!(&errors[0], LlmError::InvalidRequest(StatusCode::BAD_REQUEST, message) if message == "unknown model"));
+    assert!(matches!(&errors[1], LlmError::Auth(message) if message == "invalid api key"));
+    assert!(matches!(&errors[2], LlmError::Quota(message) if message == "out of credits"));
+}
+
+#[test]
+fn retries_back_off_without_retry_after() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    let (base_url, requests) = stub_server(vec![
+        http_response("503 Service Unavailable", &[], ""),
+        http_response("200 OK", &[], COMPLETION),
+    ]);
+
+    let started = Instant::now();
+    assert_eq!(complete(base_url).unwrap(), "ok");
+    // The first retry waits one second
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert_eq!(request_count(&requests), 2);
+}
+
+#[test]
+fn retry_after_accepts_an_http_date() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    // A date in the past means retry at once, instead of the one second backoff
+    let (base_url, requests) = stub_server(vec![
+        http_response("503 Service Unavailable", &["Retry-After: Wed, 21 Oct 2015 07:28:00 GMT"], ""),
+        http_response("200 OK", &[], COMPLETION),
+    ]);
+
+    let started = Instant::now();
+    assert_eq!(complete(base_url).unwrap(), "ok");
+    assert!(started.elapsed() < Duration::from_millis(900), "waited {:?}", started.elapsed());
+    assert_eq!(request_count(&requests), 2);
+}
+
+#[test]
+fn cancelling_interrupts_the_wait_between_retries() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    let (base_url, requests) = stub_server(vec![
+        http_response("429 Too Many Requests", &["Retry-After: 60"], ""),
+    ]);
+    thread::spawn(|| {
+        thread::sleep(Duration::from_millis(300));
+        cancel_request();
+    });
+
+    let started = Instant::now();
+    let error = llm_error(complete(base_url));
+    assert!(matches!(error, LlmError::Cancelled), "{}", error);
+    assert!(started.elapsed() < Duration::from_secs(5), "waited {:?}", started.elapsed());
+    assert_eq!(request_count(&requests), 1);
+}
+
+#[test]
+fn openai_output_cut_off_at_max_tokens_is_refused() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    let body = r#"{"choices":[{"message":{"content":"FROM node:20-slim\nRUN npm"},"finish_reason":"length"}],"usage":{"prompt_tokens":3,"completion_tokens":10}}"#;
+    let (base_url, _) = stub_server(vec![http_response("200 OK", &[], body)]);
+
+    let error = llm_error(complete(base_url));
+    assert!(matches!(&error, LlmError::Truncated(agent) if agent == "retry_test"), "{}", error);
+}
+
+#[test]
+fn anthropic_output_cut_off_at_max_tokens_is_refused() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    let body = r#"{"content":[{"type":"text","text":"FROM node:20-slim\nRUN npm"}],"stop_reason":"max_tokens","usage":{"input_tokens":3,"output_tokens":10}}"#;
+    let (base_url, _) = stub_server(vec![http_response("200 OK", &[], body)]);
+    let messages = [serde_json::json!({"role": "user", "content": "Hello"})];
+    let request = ChatRequest {
+        agent: "docker_file_generation",
+        model: "test-model",
+        messages: &messages,
+        temperature: 0.0,
+        max_tokens: 10,
+        json_schema: None,
+    };
+
+    let result = AnthropicProvider::new(base_url, "test-key".to_string())
+        .complete(&request)
+        .map(|completion| completion.content);
+    let error = llm_error(result);
+    assert!(matches!(&error, LlmError::Truncated(agent) if agent == "docker_file_generation"), "{}", error);
+}
+
+#[test]
+fn openai_stream_joins_multi_line_data_and_stops_at_done() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    let events = "data: {\"choices\":[{\"delta\":{\"content\":\"FROM \"}}]}\n\n\
+        data: {\"choices\":[{\"delta\":\n\
+        data: {\"content\":\"node\"}}]}\n\n\
+        : keep-alive comment\n\n\
+        data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"stop\"}],\"usage\":{\"prompt_tokens\":5,\"completion_tokens\":2}}\n\n\
+        data: [DONE]\n\n\
+        data: {\"choices\":[{\"delta\":{\"content\":\"ignored\"}}]}\n\n";
+    let (base_url, _) = stub_server(vec![sse_response(events)]);
+
+    let (result, tokens) = stream(&OpenAiProvider::new("openai", base_url, None), |_| {});
+    let completion = result.unwrap();
+    assert_eq!(tokens, ["FROM ", "node"]);
+    assert_eq!(completion.content, "FROM node");
+    assert_eq!(completion.usage.prompt_tokens, 5);
+    assert_eq!(completion.usage.completion_tokens, 2);
+}
+
+#[test]
+fn anthropic_stream_reads_text_deltas_and_usage() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    let events = "event: message_start\n\
+        data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":12,\"output_tokens\":1}}}\n\n\
+        event: content_block_start\n\
+        data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n\
+        event: content_block_delta\n\
+        data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n\
+        event: content_block_delta\n\
+        data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\" there\"}}\n\n\
+        event: message_delta\n\
+        data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":3}}\n\n\
+        event: message_stop\n\
+        data: {\"type\":\"message_stop\"}\n\n";
+    let (base_url, _) = stub_server(vec![sse_response(events)]);
+
+    let (result, tokens) = stream(&AnthropicProvider::new(base_url, "test-key".to_string()), |_| {});
+    let completion = result.unwrap();
+    assert_eq!(tokens, ["Hello", " there"]);
+    assert_eq!(completion.content, "Hello there");
+    assert_eq!(completion.usage.prompt_tokens, 12);
+    assert_eq!(completion.usage.completion_tokens, 3);
+}
+
+#[test]
+fn anthropic_stream_stopped_at_max_tokens_is_refused() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    let events = "event: content_block_delta\n\
+        data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"FROM\"}}\n\n\
+        event: message_delta\n\
+        data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"max_tokens\"},\"usage\":{\"output_tokens\":10}}\n\n";
+    let (base_url, _) = stub_server(vec![sse_response(events)]);
+
+    let (result, _) = stream(&AnthropicProvider::new(base_url, "test-key".to_string()), |_| {});
+    let error = llm_error(result.map(|completion| completion.content));
+    assert!(matches!(&error, LlmError::Truncated(agent) if agent == "stream_test"), "{}", error);
+}
+
+#[test]
+fn cancelling_stops_reading_the_stream() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    let events = "data: {\"choices\":[{\"delta\":{\"content\":\"one\"}}]}\n\n\
+        data: {\"choices\":[{\"delta\":{\"content\":\"two\"}}]}\n\n\
+        data: {\"choices\":[{\"delta\":{\"content\":\"three\"}}]}\n\n\
+        data: [DONE]\n\n";
+    let (base_url, _) = stub_server(vec![sse_response(events)]);
+
+    // Ctrl-C pressed while the first token is printed
+    let (result, tokens) = stream(&OpenAiProvider::new("openai-compatible", base_url, None), |_| cancel_request());
+    let error = llm_error(result.map(|completion| completion.content));
+    assert!(matches!(error, LlmError::Cancelled), "{}", error);
+    assert_eq!(tokens, ["one"]);
+}
+
+// Head (lower-cased) and JSON body of a raw request received by the stub server
+fn request_parts(raw: &str) -> (String, serde_json::Value) {
+    let (head, body) = raw.split_once("\r\n\r\n").unwrap();
+    (head.to_lowercase(), serde_json::from_str(body).unwrap())
+}
+
+fn chat_messages() -> [serde_json::Value; 3] {
+    [
+        serde_json::json!({"role": "system", "content": "You write Dockerfiles."}),
+        serde_json::json!({"role": "system", "content": "Answer with the file only."}),
+        serde_json::json!({"role": "user", "content": "Hello"}),
+    ]
+}
+
+fn send(provider: &dyn LlmProvider, messages: &[serde_json::Value]) -> String {
+    let request = ChatRequest {
+        agent: "provider_test",
+        model: "test-model",
+        messages,
+        temperature: 0.2,
+        max_tokens: 50,
+        json_schema: None,
+    };
+    provider.complete(&request).unwrap().content
+}
+
+#[test]
+fn anthropic_sends_system_messages_in_the_system_field() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    let body = r#"{"content":[{"type":"text","text":"ok"}],"stop_reason":"end_turn","usage":{"input_tokens":9,"output_tokens":1}}"#;
+    let (base_url, requests) = stub_server(vec![http_response("200 OK", &[], body)]);
+
+    let provider = live_provider("anthropic", Some(base_url), Some("sk-ant-test".to_string())).unwrap();
+    assert_eq!(provider.name(), "anthropic");
+    assert_eq!(send(provider.as_ref(), &chat_messages()), "ok");
+
+    let (head, body) = request_parts(&requests.lock().unwrap()[0]);
+    assert!(head.starts_with("post /v1/messages "), "{}", head);
+    assert!(head.contains("x-api-key: sk-ant-test\r\n"), "{}", head);
+    assert!(head.contains("anthropic-version: 2023-06-01\r\n"), "{}", head);
+    assert_eq!(body["system"], "You write Dockerfiles.\n\nAnswer with the file only.");
+    assert_eq!(body["messages"], serde_json::json!([{"role": "user", "content": "Hello"}]));
+    assert_eq!(body["model"], "test-model");
+    assert_eq!(body["max_tokens"], 50);
+}
+
+#[test]
+fn openai_providers_send_chat_completions_with_the_messages_as_given() {
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _guard = RequestGuard::begin();
+    let (base_url, requests) = stub_server(vec![
+        http_response("200 OK", &[], COMPLETION),
+        http_response("200 OK", &[], COMPLETION),
+    ]);
+
+    let openai = live_provider("openai", Some(base_url.clone()), Some("sk-test".to_string())).unwrap();
+    assert_eq!(openai.name(), "openai");
+    assert_eq!(send(openai.as_ref(), &chat_messages()), "ok");
+    // A local server needs no key
+    let compatible = live_provider("openai-compatible", Some(base_url), None).unwrap();
+    assert_eq!(compatible.name(), "openai-compatible");
+    assert_eq!(send(compatible.as_ref(), &chat_messages()), "ok");
+
+    let requests = requests.lock().unwrap();
+    let (head, body) = request_parts(&requests[0]);
+    assert!(head.starts_with("post /v1/chat/completions "), "{}", head);
+    assert!(head.contains("authorization: bearer sk-test\r\n"), "{}", head);
+    assert_eq!(body["messages"], serde_json::json!(chat_messages()));
+    assert!(body.get("system").is_none());
+    assert_eq!(body["temperature"], 0.2);
+    let (head, body) = request_parts(&requests[1]);
+    assert!(head.starts_with("post /v1/chat/completions "), "{}", head);
+    assert!(!head.contains("authorization:"), "{}", head);
+    assert_eq!(body["messages"], serde_json::json!(chat_messages()));
+}
+
+#[test]
+fn provider_selection_reports_missing_settings() {
+    let error = |provider: &str, base_url: Option<&str>| {
+        live_provider(provider, base_url.map(|url| url.to_string()), None).err().unwrap()
+    };
+
+    assert_eq!(error("openai", None), "OPENAI_API_KEY not found in .env");
+    assert_eq!(error("anthropic", Some("http://localhost:1/v1")), "ANTHROPIC_API_KEY not found in .env");
+    assert_eq!(error("openai-compatible", None), "LLM_BASE_URL must be set for the openai-compatible provider");
+    assert_eq!(
+        error("gemini", None),
+        "Unknown LLM_PROVIDER 'gemini', expected 'openai', 'openai-compatible' or 'anthropic'"
+    );
+}
+
+// Stands in for a live provider, answering every prompt the same way
+struct CannedProvider;
+
+impl LlmProvider for CannedProvider {
+    fn name(&self) -> &str {
+        "canned"
+    }
+
+    fn complete(&self, _request: &ChatRequest) -> Result<Completion, Box<dyn Error>> {
+        Ok(Completion {
+            content: "FROM node:20-slim\n".to_string(),
+            usage: Usage { prompt_tokens: 120, completion_tokens: 7 },
+        })
+    }
+}
+
+#[test]
+fn recorded_responses_replay_for_the_same_prompt() {
+    let fixtures = tempfile::tempdir().unwrap();
+    let messages = [serde_json::json!({"role": "user", "content": "Write a Dockerfile for a Node app."})];
+    let request = ChatRequest {
+        agent: "docker_file_generation",
+        model: "test-model",
+        messages: &messages,
+        temperature: 0.5,
+        max_tokens: 300,
+        json_schema: None,
+    };
+
+    let recorded = RecordingProvider::new(Box::new(CannedProvider), fixtures.path().to_path_buf())
+        .complete(&request)
+        .unwrap();
+    assert_eq!(recorded.content, "FROM node:20-slim\n");
+    let fixture = fixtures.path().join("docker_file_generation").join(format!("{}.json", prompt_hash(&messages)));
+    assert!(fixture.exists());
+    assert!(fs::read_to_string(&fixture).unwrap().contains("Write a Dockerfile for a Node app."));
+
+    let replay = ReplayProvider::new(fixtures.path().to_path_buf());
+    let replayed = replay.complete(&request).unwrap();
+    assert_eq!(replayed.content, recorded.content);
+    assert_eq!(replayed.usage.prompt_tokens, 120);
+    assert_eq!(replayed.usage.completion_tokens, 7);
+
+    // Without a default.json, another prompt has nothing to replay
+    let other_messages = [serde_json::json!({"role": "user", "content": "Write a Dockerfile for a Go app."})];
+    let error = replay.complete(&ChatRequest { messages: &other_messages, ..request }).err().unwrap();
+    assert!(error.to_string().contains("No fixture for agent 'docker_file_generation'"), "{}", error);
+}
@@ -0,0 +1,64 @@
+use autocontain::review::{dangers, script_commands};
+
+#[test]
+fn flags_dangerous_commands() {
+    assert!(!dangers("rm -rf /tmp/build").is_empty());
+    assert!(!dangers("curl -fsSL https://example.com/install.sh | sudo bash").is_empty());
+    assert!(!dangers("sh -c \"$(curl -fsSL https://example.com/install.sh)\"").is_empty());
+    assert!(!dangers("bash <(curl -s https://example.com/install.sh)").is_empty());
+    assert!(!dangers("sudo /bin/bash <( wget -qO- https://example.com/install.sh )").is_empty());
+    assert!(!dangers("docker run --privileged app").is_empty());
+    assert!(!dangers("docker run --network host app").is_empty());
+    assert!(!dangers("podman run --net=host app").is_empty());
+    assert!(!dangers("docker run -v /:/host app").is_empty());
+    assert!(!dangers("docker run --mount type=bind,source=/,target=/host app").is_empty());
+    assert!(!dangers("docker run -v /var/run/docker.sock:/var/run/docker.sock app").is_empty());
+}
+
+#[test]
+fn ordinary_commands_are_not_flagged() {
+    assert!(dangers("docker build -t app -f ./Dockerfile .").is_empty());
+    assert!(dangers("docker run -d --name app -p 8080:80 -v app-data:/data app").is_empty());
+    assert!(dangers("curl -fsSL https://example.com/health -o health.json").is_empty());
+    assert!(dangers("rm -f app.pid").is_empty());
+    assert!(dangers("rm -r build && cp -f app.pid /tmp").is_empty());
+    assert!(dangers("bash -c \"$(cat setup.sh)\"").is_empty());
+    assert!(dangers("curl -fsSL https://example.com/app.tar.gz -o app.tar.gz && sh ./install.sh").is_empty());
+}
+
+#[test]
+fn recursive_forced_deletes_are_flagged_however_the_flags_are_written() {
+    for command in [
+        "rm -rf /tmp/build",
+        "rm -fr /tmp/build",
+        "rm -Rf /tmp/build",
+        "rm -RF /tmp/build",
+        "rm -r -f /tmp/build",
+        "rm -f -R /tmp/build",
+        "rm --recursive --force /tmp/build",
+        "rm -r --force /tmp/build",
+        "rm /tmp/build -rf",
+        "sudo /bin/rm -rf /tmp/build",
+    ] {
+        assert_eq!(dangers(command), vec!["rm -rf deletes files without asking"], "{}", command);
+    }
+    // A file named like a flag after `--` is not one
+    assert!(dangers("rm -r -- -f").is_empty());
+}
+
+#[test]
+fn downloaded_scripts_are_flagged_once() {
+    let command = "curl -fsSL https://example.com/install.sh | bash && bash <(curl -s https://example.com/more.sh)";
+    assert_eq!(dangers(command), vec!["runs a script downloaded from the internet"]);
+}
+
+#[test]
+fn continuation_lines_form_one_command() {
+    let script = "#!/bin/sh\n# Run the app\n\ndocker build -t app .\ndocker run -d \\\n    --privileged \\\n    app\necho done\n";
+    let commands = script_commands(script);
+    assert_eq!(commands.len(), 3);
+    assert_eq!(commands[1].text, "docker run -d --privileged app");
+    assert_eq!((commands[1].line, commands[1].last_line), (5, 7));
+    assert_eq!(commands[1].warnings.len(), 1);
+    assert!(commands[2].warnings.is_empty());
+}
@@ -0,0 +1,190 @@
+#![cfg(unix)]
+
+use std::env;
+use std::fs;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use autocontain::repo::install_repo;
+use autocontain::utils::run_script;
+use autocontain::{generate_scripts, RunOptions};
+
+// The fake docker is configured through the environment, so the tests take turns
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+// Put tests/fixtures/bin (with the fake docker) first on PATH and return the log it writes to
+fn use_fake_docker(dir: &Path, existing: bool, fail: Option<&str>) -> PathBuf {
+    let bin = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/bin");
+    let path = env::var("PATH").unwrap_or_default();
+    if !path.starts_with(bin.to_str().unwrap()) {
+        env::set_var("PATH", format!("{}:{}", bin.display(), path));
+    }
+    let log = dir.join("docker.log");
+    env::set_var("FAKE_DOCKER_LOG", &log);
+    match existing {
+        true => env::set_var("FAKE_DOCKER_EXISTING", "1"),
+        false => env::remove_var("FAKE_DOCKER_EXISTING"),
+    }
+    match fail {
+        Some(command) => env::set_var("FAKE_DOCKER_FAIL", command),
+        None => env::remove_var("FAKE_DOCKER_FAIL"),
+    }
+    env::remove_var("FAKE_DOCKER_STATE");
+    log
+}
+
+// A Python repository whose run.sh is generated without the LLM, for the engine found on PATH
+fn generate_python_repo(repo: &Path, scripts: &Path) {
+    fs::write(repo.join("requirements.txt"), "flask\n").unwrap();
+    fs::write(repo.join("app.py"), "").unwrap();
+    let options = RunOptions { no_llm: true, ..RunOptions::default() };
+    generate_scripts(repo, scripts, &[], &options).unwrap();
+}
+
+#[test]
+fn runs_the_script_as_a_whole() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let log = use_fake_docker(dir.path(), false, None);
+    let script = dir.path().join("run.sh");
+    fs::write(
+        &script,
+        "#!/bin/sh\nset -e\nNAME=sample\ncd \"$(dirname \"$0\")\"\nif [ -n \"$NAME\" ]; then\n    docker build \\\n        -t \"$NAME\" .\nfi\ndocker run -d --name \"$NAME\" \"$NAME\" > \"$(pwd)/run.out\"\n",
+    )
+    .unwrap();
+
+    run_script(&script).unwrap();
+
+    let calls = fs::read_to_string(&log).unwrap();
+    assert_eq!(calls, "build -t sample .\nrun -d --name sample sample\n");
+    assert!(dir.path().join("run.out").exists());
+}
+
+#[test]
+fn generated_script_builds_and_runs_the_image() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    let log = use_fake_docker(scripts.path(), false, None);
+    generate_python_repo(repo.path(), scripts.path());
+
+    run_script(&scripts.path().join("run.sh")).unwrap();
+
+    let calls = fs::read_to_string(&log).unwrap();
+    let calls: Vec<&str> = calls.lines().collect();
+    assert_eq!(calls.len(), 4);
+    assert!(calls[0].starts_with("container inspect autocontain-"));
+    assert!(calls[1].starts_with("image inspect autocontain-"));
+    assert!(calls[2].starts_with("build -t autocontain-"));
+    assert!(calls[2].contains(&scripts.path().join("Dockerfile").display().to_string()));
+    assert!(calls[3].starts_with("run -d --name autocontain-"));
+    assert!(calls[3].contains(":5000"));
+}
+
+#[test]
+fn existing_container_is_started_instead_of_rebuilt() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    let log = use_fake_docker(scripts.path(), true, None);
+    generate_python_repo(repo.path(), scripts.path());
+
+    run_script(&scripts.path().join("run.sh")).unwrap();
+
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(calls.lines().last().unwrap().starts_with("start autocontain-"));
+    assert!(!calls.contains("build"));
+}
+
+#[test]
+fn failing_command_fails_the_script() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    let log = use_fake_docker(scripts.path(), false, Some("build"));
+    generate_python_repo(repo.path(), scripts.path());
+
+    assert!(run_script(&scripts.path().join("run.sh")).is_err());
+
+    // set -e stops the script at the failed build
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(!calls.contains("run -d"));
+}
+
+#[test]
+fn install_runs_the_plan_through_the_engine() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    let log = use_fake_docker(scripts.path(), false, None);
+    generate_python_repo(repo.path(), scripts.path());
+    // Installing must not depend on run.sh once there is a plan
+    fs::remove_file(scripts.path().join("run.sh")).unwrap();
+
+    install_repo(repo.path(), scripts.path(), &RunOptions { yes: true, ..RunOptions::default() });
+
+    // Listing calls look for existing volumes; what follows the run records the created resources
+    let calls = fs::read_to_string(&log).unwrap();
+    let calls: Vec<&str> = calls.lines().filter(|call| !call.starts_with("ps ") && !call.contains(" ls ") && !call.starts_with("images ")).collect();
+    let calls = &calls[..=calls.iter().position(|call| call.starts_with("run ")).unwrap()];
+    assert_eq!(calls.len(), 4);
+    assert!(calls[0].starts_with("container inspect -f {{.State.Running}} autocontain-"));
+    assert!(calls[1].starts_with("image inspect autocontain-"));
+    assert!(calls[2].starts_with("build -f "));
+    assert!(calls[3].starts_with("run -d --name autocontain-"));
+    assert!(calls[3].contains(" -p ") && calls[3].contains(":5000"));
+}
+
+#[test]
+fn install_moves_host_ports_taken_since_the_plan_was_made() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    let log = use_fake_docker(scripts.path(), false, None);
+    generate_python_repo(repo.path(), scripts.path());
+    let read_plan = || -> serde_json::Value {
+        serde_json::from_str(&fs::read_to_string(scripts.path().join("run_plan.json")).unwrap()).unwrap()
+    };
+    let planned = read_plan()["ports"][0]["host"].as_u64().unwrap() as u16;
+    // Something else listens on the port by the time the repository is installed
+    let _listener = TcpListener::bind(("0.0.0.0", planned)).unwrap();
+
+    install_repo(repo.path(), scripts.path(), &RunOptions { yes: true, ..RunOptions::default() });
+
+    let moved = read_plan()["ports"][0]["host"].as_u64().unwrap() as u16;
+    assert_ne!(moved, planned);
+    let calls = fs::read_to_string(&log).unwrap();
+    let run = calls.lines().find(|call| call.starts_with("run ")).unwrap();
+    assert!(run.contains(&format!(" -p {}:5000 ", moved)), "{}", run);
+    let run_script = fs::read_to_string(scripts.path().join("run.sh")).unwrap();
+    assert!(run_script.contains(&format!("{}:5000", moved)));
+    assert!(!run_script.contains(&format!("{}:5000", planned)));
+}
+
+#[test]
+fn dry_run_does_not_call_the_engine() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    let log = use_fake_docker(scripts.path(), false, None);
+    generate_python_repo(repo.path(), scripts.path());
+
+    install_repo(repo.path(), scripts.path(), &RunOptions { dry_run: true, ..RunOptions::default() });
+
+    assert!(!log.exists());
+}
+
+#[test]
+fn install_refuses_blocked_scripts() {
+    let _lock = ENV_LOCK.lock().unwrap();
+    let repo = tempfile::tempdir().unwrap();
+    let scripts = tempfile::tempdir().unwrap();
+    let log = use_fake_docker(scripts.path(), false, None);
+    // A script written by the agent, without a run plan
+    fs::write(scripts.path().join("run.sh"), "#!/bin/sh\ndocker run -d --privileged app\n").unwrap();
+
+    install_repo(repo.path(), scripts.path(), &RunOptions { yes: true, ..RunOptions::default() });
+
+    assert!(!log.exists());
+}
@@ -0,0 +1,287 @@
+use std::fs;
+use std::path::Path;
+
+use autocontain::source::{
+    changed_files, checkout_ref, complete_checkout, follow_branch, head_commit, probe_remote, progress_line, read_by_analysis, sparse_checkout,
+    update_submodules, without_path_hash, Metadata, RepoSource,
+};
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use git2::{Oid, Repository, Signature};
+
+fn remote(url: &str, key: &str) -> RepoSource {
+    RepoSource::Remote { url: url.to_string(), key: key.to_string() }
+}
+
+// Commit `files` (path, content) on top of HEAD, moving `update_ref`
+fn commit_files(repo: &Repository, update_ref: &str, files: &[(&str, &str)]) -> Oid {
+    let mut index = repo.index().unwrap();
+    for (path, content) in files {
+        let full_path = repo.workdir().unwrap().join(path);
+        fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+        fs::write(full_path, content).unwrap();
+        index.add_path(Path::new(path)).unwrap();
+    }
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("autocontain", "autocontain@example.com").unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    repo.commit(Some(update_ref), &signature, &signature, files[0].1, &tree, &parent.iter().collect::<Vec<_>>()).unwrap()
+}
+
+fn commit_readme(repo: &Repository, update_ref: &str, content: &str) -> Oid {
+    commit_files(repo, update_ref, &[("README.md", content)])
+}
+
+// A git repository with one commit
+fn init_repo(path: &Path) -> Repository {
+    let repo = Repository::init(path).unwrap();
+    commit_readme(&repo, "HEAD", "# sample\n");
+    repo
+}
+
+#[test]
+fn parses_git_urls_and_shortcuts() {
+    assert_eq!(RepoSource::parse("https://github.com/drawdb-io/drawdb").unwrap(), remote("https://github.com/drawdb-io/drawdb", "github.com/drawdb-io/drawdb"));
+    assert_eq!(RepoSource::parse("https://git.example.com/team/api.git").unwrap(), remote("https://git.example.com/team/api.git", "git.example.com/team/api"));
+    assert_eq!(RepoSource::parse("ssh://git@gitea.local:2222/team/web.git").unwrap(), remote("ssh://git@gitea.local:2222/team/web.git", "gitea.local/team/web"));
+    assert_eq!(RepoSource::parse("git@gitlab.com:team/worker.git").unwrap(), remote("git@gitlab.com:team/worker.git", "gitlab.com/team/worker"));
+    assert_eq!(RepoSource::parse("file:///srv/git/tool.git").unwrap(), remote("file:///srv/git/tool.git", "local/git/tool-35d4f165"));
+    assert_eq!(RepoSource::parse("gh:drawdb-io/drawdb").unwrap(), remote("https://github.com/drawdb-io/drawdb", "github.com/drawdb-io/drawdb"));
+    assert_eq!(RepoSource::parse("gl:team/worker").unwrap(), remote("https://gitlab.com/team/worker", "gitlab.com/team/worker"));
+}
+
+#[test]
+fn keys_tell_owners_and_hosts_apart() {
+    let key = |arg: &str| RepoSource::parse(arg).unwrap().key().to_string();
+    assert_ne!(key("gh:alice/utils"), key("gh:bob/utils"));
+    assert_ne!(key("gh:alice/utils"), key("gl:alice/utils"));
+    assert_eq!(key("https://github.com/alice/utils/"), key("git@github.com:alice/utils.git"));
+    assert_eq!(key("https://gitlab.com/group/sub/utils"), "gitlab.com/group-sub/utils");
+    assert_eq!(key("git://example.com/utils"), "example.com/_/utils");
+}
+
+#[test]
+fn rejects_unknown_arguments() {
+    assert!(RepoSource::parse("gh:drawdb").is_err());
+    assert!(RepoSource::parse("drawdb").is_err());
+    assert!(RepoSource::parse("ftp://example.com/repo").is_err());
+}
+
+#[test]
+fn local_directories_are_analyzed_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let checkout = dir.path().join("my-service");
+    fs::create_dir(&checkout).unwrap();
+
+    let source = RepoSource::parse(checkout.to_str().unwrap()).unwrap();
+    let owner = dir.path().canonicalize().unwrap().file_name().unwrap().to_string_lossy().to_string();
+    let RepoSource::Local { path, key } = &source else { panic!("{:?} is not local", source) };
+    assert_eq!(*path, checkout.canonicalize().unwrap());
+    assert_eq!(without_path_hash(key), format!("local/{}/my-service", owner));
+    assert_ne!(without_path_hash(key), key);
+}
+
+#[test]
+fn local_directories_with_the_same_names_get_different_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("home/work/app");
+    let second = dir.path().join("srv/work/app");
+    fs::create_dir_all(&first).unwrap();
+    fs::create_dir_all(&second).unwrap();
+
+    let first = RepoSource::parse(first.to_str().unwrap()).unwrap();
+    let second = RepoSource::parse(second.to_str().unwrap()).unwrap();
+    assert_ne!(first.key(), second.key());
+    assert_eq!(without_path_hash(first.key()), "local/work/app");
+    assert_eq!(without_path_hash(second.key()), "local/work/app");
+    assert_eq!(without_path_hash("github.com/alice/app-1a2b3c4d"), "github.com/alice/app-1a2b3c4d");
+}
+
+#[test]
+fn probe_works_offline_against_local_repositories() {
+    let dir = tempfile::tempdir().unwrap();
+    init_repo(dir.path());
+    let url = format!("file://{}", dir.path().display());
+    assert!(probe_remote(&url).is_ok());
+    assert!(probe_remote(dir.path().to_str().unwrap()).is_ok());
+
+    // No commits yet, and no repository at all
+    let empty = tempfile::tempdir().unwrap();
+    Repository::init(empty.path()).unwrap();
+    assert!(probe_remote(&format!("file://{}", empty.path().display())).is_err());
+    assert!(probe_remote(&format!("file://{}/missing", dir.path().display())).is_err());
+}
+
+#[test]
+fn checks_out_branches_tags_and_commits() {
+    let dir = tempfile::tempdir().unwrap();
+    let origin = init_repo(&dir.path().join("origin"));
+    let first = origin.head().unwrap().target().unwrap();
+    origin.tag_lightweight("v1.0", &origin.find_object(first, None).unwrap(), false).unwrap();
+    let second = commit_readme(&origin, "HEAD", "# sample 2\n");
+    let clone_path = dir.path().join("clone");
+    let clone = Repository::clone(&format!("file://{}", dir.path().join("origin").display()), &clone_path).unwrap();
+    assert_eq!(head_commit(&clone_path), Some(second.to_string()));
+
+    assert_eq!(checkout_ref(&clone, "v1.0").unwrap(), first);
+    assert_eq!(fs::read_to_string(clone_path.join("README.md")).unwrap(), "# sample\n");
+    assert_eq!(checkout_ref(&clone, &second.to_string()[..8]).unwrap(), second);
+    assert_eq!(head_commit(&clone_path), Some(second.to_string()));
+
+    // Pushed after cloning: fetched from origin
+    let feature = commit_readme(&origin, "refs/heads/feature", "# feature\n");
+    origin.tag_lightweight("v2.0", &origin.find_object(second, None).unwrap(), false).unwrap();
+    assert_eq!(checkout_ref(&clone, "feature").unwrap(), feature);
+    assert_eq!(checkout_ref(&clone, "v2.0").unwrap(), second);
+    assert!(checkout_ref(&clone, "missing").is_err());
+}
+
+#[test]
+fn metadata_records_the_analyzed_commit() {
+    let dir = tempfile::tempdir().unwrap();
+    assert_eq!(head_commit(dir.path()), None);
+
+    let metadata = Metadata {
+        source: "https://github.com/drawdb-io/drawdb".to_string(),
+        git_ref: Some("v1.0".to_string()),
+        commit: Some("0123456789abcdef0123456789abcdef01234567".to_string()),
+        depth: 2,
+        sparse: true,
+        ..Metadata::default()
+    };
+    let scripts_path = dir.path().join("scripts").join("drawdb");
+    metadata.save(&scripts_path).unwrap();
+    assert_eq!(Metadata::load(&scripts_path), Some(metadata));
+
+    // Written before the clone and analysis options were recorded
+    fs::write(scripts_path.join("metadata.json"), r#"{"source": "https://github.com/drawdb-io/drawdb", "git_ref": null, "commit": null}"#).unwrap();
+    let old = Metadata::load(&scripts_path).unwrap();
+    assert_eq!((old.depth, old.full_history, old.sparse, old.submodules), (0, false, false, false));
+}
+
+#[test]
+fn progress_line_shows_a_bar() {
+    assert_eq!(progress_line(0, 0, 0), "Receiving objects [                    ]   0% (0/0), 0 B");
+    assert_eq!(progress_line(50, 100, 1536), "Receiving objects [##########          ]  50% (50/100), 1.5 KB");
+    assert_eq!(progress_line(100, 100, 2048), "Receiving objects [####################] 100% (100/100), 2.0 KB");
+}
+
+#[test]
+fn sparse_checkout_keeps_what_the_analysis_reads() {
+    assert!(read_by_analysis("package.json"));
+    assert!(read_by_analysis("backend/requirements.txt"));
+    assert!(read_by_analysis("docker/prod/Dockerfile"));
+    assert!(read_by_analysis("docs/guide/install.md"));
+    assert!(read_by_analysis("src/app/main.py"));
+    assert!(!read_by_analysis("src/app/main.js"));
+    assert!(!read_by_analysis("assets/images/logo.png"));
+
+    let dir = tempfile::tempdir().unwrap();
+    let origin = init_repo(&dir.path().join("origin"));
+    commit_files(&origin, "HEAD", &[
+        ("package.json", "{}"),
+        ("docker/prod/Dockerfile", "FROM node:20\n"),
+        ("docs/guide/install.md", "# install\n"),
+        ("src/app/main.js", "console.log(1)\n"),
+        ("src/app/models.py", "import redis\n"),
+    ]);
+    let clone_path = dir.path().join("clone");
+    let mut checkout = CheckoutBuilder::new();
+    checkout.dry_run();
+    let clone = RepoBuilder::new()
+        .with_checkout(checkout)
+        .clone(&format!("file://{}", dir.path().join("origin").display()), &clone_path)
+        .unwrap();
+    sparse_checkout(&clone).unwrap();
+    for path in ["README.md", "package.json", "docker/prod/Dockerfile", "docs/guide/install.md", "src/app/models.py"] {
+        assert!(clone_path.join(path).is_file(), "{} missing", path);
+    }
+    assert!(!clone_path.join("src/app/main.js").exists());
+
+    // Still sparse after checking out another commit
+    let last = clone.head().unwrap().target().unwrap();
+    let first = clone.revparse_single("HEAD~1").unwrap().id();
+    checkout_ref(&clone, &first.to_string()).unwrap();
+    assert!(!clone_path.join("package.json").exists());
+    checkout_ref(&clone, &last.to_string()).unwrap();
+    assert!(clone_path.join("package.json").is_file());
+    assert!(!clone_path.join("src/app/main.js").exists());
+
+    assert!(complete_checkout(&clone_path).unwrap());
+    assert!(clone_path.join("src/app/main.js").is_file());
+    assert!(!complete_checkout(&clone_path).unwrap());
+    assert!(!complete_checkout(dir.path()).unwrap());
+}
+
+#[test]
+fn submodules_are_cloned_on_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let library = init_repo(&dir.path().join("library"));
+    commit_files(&library, "HEAD", &[("nested/lib.js", "module.exports = 1\n")]);
+    let app = init_repo(&dir.path().join("app"));
+    let mut submodule = app.submodule(&format!("file://{}", dir.path().join("library").display()), Path::new("lib"), true).unwrap();
+    submodule.clone(None).unwrap();
+    submodule.add_finalize().unwrap();
+    commit_readme(&app, "HEAD", "# app with lib\n");
+
+    let clone_path = dir.path().join("clone");
+    let clone = Repository::clone(&format!("file://{}", dir.path().join("app").display()), &clone_path).unwrap();
+    assert!(!clone_path.join("lib/nested/lib.js").exists());
+    update_submodules(&clone).unwrap();
+    assert!(clone_path.join("lib/nested/lib.js").is_file());
+}
+
+#[test]
+fn follows_the_branch_by_fast_forwarding() {
+    let dir = tempfile::tempdir().unwrap();
+    let origin = init_repo(&dir.path().join("origin"));
+    let first = origin.head().unwrap().target().unwrap();
+    origin.tag_lightweight("v1.0", &origin.find_object(first, None).unwrap(), false).unwrap();
+    let clone_path = dir.path().join("clone");
+    let clone = Repository::clone(&format!("file://{}", dir.path().join("origin").display()), &clone_path).unwrap();
+    assert_eq!(follow_branch(&clone, None).unwrap(), Some(first));
+
+    let second = commit_files(&origin, "HEAD", &[("package.json", "{}")]);
+    assert_eq!(follow_branch(&clone, None).unwrap(), Some(second));
+    assert!(clone.head().unwrap().is_branch());
+    assert!(clone_path.join("package.json").is_file());
+    assert_eq!(changed_files(&clone, first, second).unwrap(), ["package.json"]);
+
+    // History rewritten on the remote
+    let branch = origin.head().unwrap().name().unwrap().to_string();
+    let signature = Signature::now("autocontain", "autocontain@example.com").unwrap();
+    let parent = origin.find_commit(first).unwrap();
+    let rewritten = origin.commit(None, &signature, &signature, "Rewritten", &parent.tree().unwrap(), &[&parent]).unwrap();
+    origin.reference(&branch, rewritten, true, "rewrite").unwrap();
+    assert!(follow_branch(&clone, None).is_err());
+
+    // Pinned to a tag
+    checkout_ref(&clone, "v1.0").unwrap();
+    assert_eq!(follow_branch(&clone, Some("v1.0")).unwrap(), None);
+    assert_eq!(follow_branch(&clone, None).unwrap(), None);
+}
+
+#[test]
+fn pinned_refs_are_not_fetched() {
+    let dir = tempfile::tempdir().unwrap();
+    let origin = init_repo(&dir.path().join("origin"));
+    let first = origin.head().unwrap().target().unwrap();
+    origin.tag_lightweight("v1.0", &origin.find_object(first, None).unwrap(), false).unwrap();
+    commit_files(&origin, "HEAD", &[("package.json", "{}")]);
+    let clone = Repository::clone(&format!("file://{}", dir.path().join("origin").display()), dir.path().join("clone")).unwrap();
+    let branch = origin.head().unwrap().shorthand().unwrap().to_string();
+    // Fetching would fail now
+    drop(origin);
+    fs::remove_dir_all(dir.path().join("origin")).unwrap();
+
+    checkout_ref(&clone, "v1.0").unwrap();
+    assert_eq!(follow_branch(&clone, Some("v1.0")).unwrap(), None);
+    assert_eq!(clone.head().unwrap().target(), Some(first));
+    for commit in [first.to_string(), first.to_string()[..7].to_string()] {
+        checkout_ref(&clone, &commit).unwrap();
+        assert_eq!(follow_branch(&clone, Some(&commit)).unwrap(), None);
+        assert_eq!(clone.head().unwrap().target(), Some(first));
+    }
+    // A branch is still fetched
+    assert!(follow_branch(&clone, Some(&branch)).is_err());
+}
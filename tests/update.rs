@@ -0,0 +1,114 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use autocontain::source::Metadata;
+use autocontain::update::{update_repo, Changes};
+use autocontain::RunOptions;
+use git2::{Oid, Repository, Signature};
+use rusqlite::Connection;
+
+// The repositories live in the working directory, so the tests take turns changing it
+static CWD_LOCK: Mutex<()> = Mutex::new(());
+
+// Commit `files` (path, content) on top of HEAD
+fn commit_files(repo: &Repository, files: &[(&str, &str)]) -> Oid {
+    let mut index = repo.index().unwrap();
+    for (path, content) in files {
+        let full_path = repo.workdir().unwrap().join(path);
+        fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+        fs::write(full_path, content).unwrap();
+        index.add_path(Path::new(path)).unwrap();
+    }
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("autocontain", "autocontain@example.com").unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    repo.commit(Some("HEAD"), &signature, &signature, files[0].0, &tree, &parent.iter().collect::<Vec<_>>()).unwrap()
+}
+
+#[test]
+fn changed_files_are_sorted_by_stage() {
+    let paths = [
+        "README.md", "docs/setup.md", "package.json", "backend/requirements.txt", ".env.example", "mysite/settings.py",
+        "docker/prod/Dockerfile", "docker-compose.yml", "app/models.py", "assets/logo.png",
+    ].map(String::from);
+    let changes = Changes::from_paths(&paths);
+    assert_eq!(changes.docs, ["README.md", "docs/setup.md"]);
+    assert_eq!(changes.manifests, ["package.json", "backend/requirements.txt", ".env.example", "mysite/settings.py"]);
+    assert_eq!(changes.docker_files, ["docker/prod/Dockerfile", "docker-compose.yml"]);
+    assert_eq!(changes.code, ["mysite/settings.py", "app/models.py"]);
+    assert!(changes.affect_analysis());
+    assert!(!Changes::from_paths(&["assets/logo.png".to_string(), "app/models.py".to_string()]).affect_analysis());
+}
+
+#[test]
+fn update_runs_only_the_stages_whose_files_changed() {
+    let _lock = CWD_LOCK.lock().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let previous = env::current_dir().unwrap();
+    env::set_current_dir(dir.path()).unwrap();
+
+    let origin = Repository::init("origin").unwrap();
+    commit_files(&origin, &[
+        ("README.md", "# app\n"),
+        ("requirements.txt", "flask\n"),
+        ("app.py", "import flask\n"),
+        ("Dockerfile", "FROM python:3.11-slim\nCOPY . .\nCMD [\"python\", \"app.py\"]\n"),
+    ]);
+    let key = "example.com/team/app";
+    let url = format!("file://{}", dir.path().join("origin").display());
+    Repository::clone(&url, Path::new("source").join(key)).unwrap();
+    let options = RunOptions { no_llm: true, ..RunOptions::default() };
+
+    // Never analyzed: everything runs
+    let refreshed = update_repo(key, &options).unwrap();
+    assert!(refreshed.parsed && refreshed.analyzed);
+    assert!(Path::new("scripts").join(key).join("run.sh").is_file());
+    let refreshed = update_repo(key, &options).unwrap();
+    assert!(!refreshed.parsed && !refreshed.analyzed);
+
+    // Files nothing reads
+    commit_files(&origin, &[("static/logo.svg", "<svg/>")]);
+    let refreshed = update_repo(key, &options).unwrap();
+    assert!(!refreshed.parsed && !refreshed.analyzed);
+
+    // Code importing the same modules
+    commit_files(&origin, &[("app.py", "import flask\n\napp = flask.Flask(__name__)\n\ndef index():\n    return str(app)\n")]);
+    let refreshed = update_repo(key, &options).unwrap();
+    assert!(refreshed.parsed && !refreshed.analyzed);
+
+    // Documentation, searched as deep as when the repository was run
+    let scripts_path = Path::new("scripts").join(key);
+    let mut metadata = Metadata::load(&scripts_path).unwrap();
+    metadata.depth = 3;
+    metadata.save(&scripts_path).unwrap();
+    commit_files(&origin, &[("README.md", "# app\n\nRun it with docker.\n")]);
+    let refreshed = update_repo(key, &options).unwrap();
+    assert!(!refreshed.parsed && refreshed.analyzed);
+    assert_eq!(Metadata::load(&scripts_path).unwrap().depth, 3);
+
+    // Code importing a new module, which may need a new service
+    let head = commit_files(&origin, &[("worker.py", "import redis\n")]);
+    let refreshed = update_repo(key, &options).unwrap();
+    assert!(refreshed.parsed && refreshed.analyzed);
+
+    assert_eq!(refreshed.commit, Some(head.to_string()));
+    assert_eq!(Metadata::load(&Path::new("scripts").join(key)).unwrap().commit, Some(head.to_string()));
+    let conn = Connection::open("autocontain.db").unwrap();
+    let rows: Vec<(String, String)> = conn
+        .prepare("SELECT name, commit_sha FROM repositories").unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap()
+        .collect::<Result<_, _>>().unwrap();
+    assert_eq!(rows, [(key.to_string(), head.to_string())]);
+    // Parsed three times, the dependencies of top-level functions are only there once
+    let dependencies: i64 = conn.query_row("SELECT COUNT(*) FROM function_dependencies", [], |row| row.get(0)).unwrap();
+    let current: i64 = conn
+        .query_row("SELECT COUNT(*) FROM function_dependencies WHERE function_id IN (SELECT id FROM functions)", [], |row| row.get(0))
+        .unwrap();
+    assert!(current > 0);
+    assert_eq!(dependencies, current);
+
+    env::set_current_dir(previous).unwrap();
+}